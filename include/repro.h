@@ -0,0 +1,33 @@
+/* C API of the repro harness, see src/ffi.rs. Regenerate with
+ * `cbindgen --config cbindgen.toml --output include/repro.h`. */
+
+#ifndef REPRO_H
+#define REPRO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct Executor Executor;
+
+/* Loads the runtime at `wasm_path`. Returns null on failure. */
+Executor *repro_executor_new(const char *wasm_path, uint32_t heap_base);
+
+void repro_executor_free(Executor *executor);
+
+/* Calls `method` on a fresh instance. On success returns 0 and stores the returned bytes
+ * in `output`/`output_len`, to be freed with `repro_output_free`. Otherwise returns the
+ * exit code the `repro` binary would exit with, 101 if the harness panics. */
+int32_t repro_call(const Executor *executor,
+                   const char *method,
+                   const uint8_t *input,
+                   size_t input_len,
+                   uint8_t **output,
+                   size_t *output_len);
+
+void repro_output_free(uint8_t *output, size_t output_len);
+
+/* The message of the last failure on this thread, or null. It stays valid until the next
+ * failing call on this thread. */
+const char *repro_last_error(void);
+
+#endif /* REPRO_H */
@@ -0,0 +1,53 @@
+/* Plugin ABI of the repro harness, see src/plugin.rs. A plugin is a shared library exporting
+ * `repro_plugin_init`, loaded with `repro run --plugin`.
+ *
+ * Maintained by hand, unlike repro.h: the Rust side of the table is private to the harness.
+ * Keep it in sync with src/plugin.rs and bump REPRO_PLUGIN_API_VERSION along with
+ * PLUGIN_API_VERSION. */
+
+#ifndef REPRO_PLUGIN_H
+#define REPRO_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define REPRO_PLUGIN_API_VERSION 1
+
+typedef struct ReproRegistrar ReproRegistrar;
+
+typedef struct ReproHostContext ReproHostContext;
+
+typedef struct ReproHostApi ReproHostApi;
+
+/* A host function. `params` and `results` hold the bits of the wasm values, zero extended
+ * to 64. Returns 0 on success, anything else fails the call. */
+typedef int32_t (*ReproHostFn)(void *user_data,
+                               const ReproHostApi *api,
+                               const ReproHostContext *ctx,
+                               const uint64_t *params,
+                               size_t params_len,
+                               uint64_t *results,
+                               size_t results_len);
+
+/* The harness functions, which return 0 on success. A failure also fails the host function
+ * calling them, with the error of the harness. */
+struct ReproHostApi {
+  uint32_t version;
+  int32_t (*register_fn)(ReproRegistrar *registrar,
+                         const char *name,
+                         ReproHostFn function,
+                         void *user_data);
+  int32_t (*read_memory)(const ReproHostContext *ctx, uint32_t ptr, uint8_t *buf, uint32_t len);
+  int32_t (*write_memory)(const ReproHostContext *ctx,
+                          uint32_t ptr,
+                          const uint8_t *buf,
+                          uint32_t len);
+  int32_t (*allocate)(const ReproHostContext *ctx, uint32_t size, uint32_t *ptr);
+  int32_t (*deallocate)(const ReproHostContext *ctx, uint32_t ptr);
+};
+
+/* Implemented by the plugin: registers its host functions with `api->register_fn`. Returns 0
+ * on success. */
+int32_t repro_plugin_init(const ReproHostApi *api, ReproRegistrar *registrar);
+
+#endif /* REPRO_PLUGIN_H */
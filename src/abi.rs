@@ -0,0 +1,44 @@
+//! The packing of a pointer and a length into one `i64`, which is how runtime APIs return
+//! their output and how host functions take and return buffers.
+//!
+//! The pointer is in the low 32 bits and the length in the high ones. Wasm hands the packed
+//! value over as a signed `i64`, whose bits are the same.
+
+use std::convert::TryFrom;
+use std::ops::Range;
+
+pub fn pack_ptr_and_len(ptr: u32, len: u32) -> u64 {
+    (len as u64) << 32 | ptr as u64
+}
+
+pub fn unpack_ptr_and_len(val: u64) -> (u32, u32) {
+    let ptr = (val & (!0u32 as u64)) as u32;
+    let len = (val >> 32) as u32;
+
+    (ptr, len)
+}
+
+/// [`pack_ptr_and_len`] for the `i64` wasm deals in.
+pub fn pack_i64(ptr: u32, len: u32) -> i64 {
+    pack_ptr_and_len(ptr, len) as i64
+}
+
+/// [`unpack_ptr_and_len`] for the `i64` wasm deals in.
+pub fn unpack_i64(val: i64) -> (u32, u32) {
+    unpack_ptr_and_len(val as u64)
+}
+
+/// Packs a host side pointer and length, `None` if either doesn't fit in 32 bits.
+pub fn checked_pack(ptr: usize, len: usize) -> Option<u64> {
+    Some(pack_ptr_and_len(
+        u32::try_from(ptr).ok()?,
+        u32::try_from(len).ok()?,
+    ))
+}
+
+/// The range of linear memory the packed value points at, `None` if it runs past the end of
+/// the 32-bit address space.
+pub fn unpack_range(val: u64) -> Option<Range<u32>> {
+    let (ptr, len) = unpack_ptr_and_len(val);
+    Some(ptr..ptr.checked_add(len)?)
+}
@@ -0,0 +1,350 @@
+use serde::{Deserialize, Serialize};
+use sp_allocator::FreeingBumpHeapAllocator;
+use sp_wasm_interface::Pointer;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The allocator behind `ext_allocator_*`, carving allocations out of the linear memory.
+pub trait HeapAllocator {
+    fn allocate(&mut self, memory: &mut [u8], size: u32) -> Result<u32, String>;
+    fn deallocate(&mut self, memory: &mut [u8], ptr: u32) -> Result<(), String>;
+}
+
+impl HeapAllocator for FreeingBumpHeapAllocator {
+    fn allocate(&mut self, memory: &mut [u8], size: u32) -> Result<u32, String> {
+        FreeingBumpHeapAllocator::allocate(self, memory, size)
+            .map(|ptr| usize::from(ptr) as u32)
+            .map_err(|err| err.to_string())
+    }
+
+    fn deallocate(&mut self, memory: &mut [u8], ptr: u32) -> Result<(), String> {
+        FreeingBumpHeapAllocator::deallocate(self, memory, Pointer::new(ptr))
+            .map_err(|err| err.to_string())
+    }
+}
+
+fn align_up(ptr: u32) -> u32 {
+    (ptr + 7) & !7
+}
+
+/// Hands out memory upwards from the heap base and never reuses it.
+pub struct BumpAllocator {
+    next: u32,
+}
+
+impl BumpAllocator {
+    pub fn new(heap_base: u32) -> Self {
+        Self {
+            next: align_up(heap_base),
+        }
+    }
+}
+
+impl HeapAllocator for BumpAllocator {
+    fn allocate(&mut self, memory: &mut [u8], size: u32) -> Result<u32, String> {
+        let ptr = self.next;
+        match ptr.checked_add(size) {
+            Some(end) if end as usize <= memory.len() => {
+                self.next = align_up(end);
+                Ok(ptr)
+            }
+            _ => Err(format!(
+                "out of memory allocating {} bytes at {:#x}",
+                size, ptr
+            )),
+        }
+    }
+
+    fn deallocate(&mut self, _memory: &mut [u8], _ptr: u32) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The smallest block is `1 << MIN_ORDER` bytes.
+const MIN_ORDER: u32 = 3;
+
+/// Binary buddy allocator over the largest power of two sized arena that fits between the
+/// heap base and the end of the memory at the first allocation.
+pub struct BuddyAllocator {
+    base: u32,
+    /// Offsets of the free blocks from `base`, by order. Empty until the first allocation.
+    free: Vec<Vec<u32>>,
+    /// Orders of the allocated blocks by their pointers.
+    allocated: HashMap<u32, u32>,
+}
+
+impl BuddyAllocator {
+    pub fn new(heap_base: u32) -> Self {
+        Self {
+            base: align_up(heap_base),
+            free: Vec::new(),
+            allocated: HashMap::new(),
+        }
+    }
+
+    fn init(&mut self, memory_len: usize) -> Result<(), String> {
+        let available = (memory_len as u64).saturating_sub(u64::from(self.base));
+        if available < 1 << MIN_ORDER {
+            return Err(format!("no room for a heap above {:#x}", self.base));
+        }
+        let arena_order = 63 - available.leading_zeros();
+        self.free = vec![Vec::new(); arena_order as usize + 1];
+        self.free[arena_order as usize].push(0);
+        Ok(())
+    }
+}
+
+impl HeapAllocator for BuddyAllocator {
+    fn allocate(&mut self, memory: &mut [u8], size: u32) -> Result<u32, String> {
+        if self.free.is_empty() {
+            self.init(memory.len())?;
+        }
+        let order = u64::from(size)
+            .next_power_of_two()
+            .trailing_zeros()
+            .max(MIN_ORDER);
+        let out_of_memory = || format!("out of memory allocating {} bytes", size);
+        let mut split = (order as usize..self.free.len())
+            .find(|&split| !self.free[split].is_empty())
+            .ok_or_else(out_of_memory)?;
+        let offset = self.free[split].pop().ok_or_else(out_of_memory)?;
+        while split > order as usize {
+            split -= 1;
+            self.free[split].push(offset + (1 << split));
+        }
+        let ptr = self.base + offset;
+        self.allocated.insert(ptr, order);
+        Ok(ptr)
+    }
+
+    fn deallocate(&mut self, _memory: &mut [u8], ptr: u32) -> Result<(), String> {
+        let mut order =
+            self.allocated
+                .remove(&ptr)
+                .ok_or_else(|| format!("{:#x} is not allocated", ptr))? as usize;
+        let mut offset = ptr - self.base;
+        while order + 1 < self.free.len() {
+            let buddy = offset ^ (1 << order);
+            match self.free[order].iter().position(|&free| free == buddy) {
+                Some(idx) => {
+                    self.free[order].swap_remove(idx);
+                    offset = offset.min(buddy);
+                    order += 1;
+                }
+                None => break,
+            }
+        }
+        self.free[order].push(offset);
+        Ok(())
+    }
+}
+
+/// Which [`HeapAllocator`] the runtime gets.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AllocatorKind {
+    /// Substrate's own, what the runtime gets on a real node.
+    FreeingBump,
+    Bump,
+    Buddy,
+}
+
+impl Default for AllocatorKind {
+    fn default() -> Self {
+        AllocatorKind::FreeingBump
+    }
+}
+
+impl std::str::FromStr for AllocatorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "freeing-bump" => Ok(AllocatorKind::FreeingBump),
+            "bump" => Ok(AllocatorKind::Bump),
+            "buddy" => Ok(AllocatorKind::Buddy),
+            _ => Err(format!("unknown allocator `{}`", s)),
+        }
+    }
+}
+
+impl AllocatorKind {
+    fn create(self, heap_base: u32) -> Box<dyn HeapAllocator> {
+        match self {
+            AllocatorKind::FreeingBump => Box::new(FreeingBumpHeapAllocator::new(heap_base)),
+            AllocatorKind::Bump => Box::new(BumpAllocator::new(heap_base)),
+            AllocatorKind::Buddy => Box::new(BuddyAllocator::new(heap_base)),
+        }
+    }
+}
+
+/// Counters of the allocator activity during a call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AllocatorStats {
+    pub allocations: u32,
+    pub frees: u32,
+    /// Bytes requested and not freed yet, including the ones left over from previous calls.
+    pub live_bytes: u64,
+    pub peak_live_bytes: u64,
+    pub largest_request: u32,
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} allocations, {} frees, {} live bytes (peak {}), largest request {} bytes",
+            self.allocations,
+            self.frees,
+            self.live_bytes,
+            self.peak_live_bytes,
+            self.largest_request
+        )
+    }
+}
+
+/// A live allocation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub ptr: u32,
+    pub size: u32,
+    /// How many allocations the instance made before this one.
+    pub ordinal: u64,
+    /// The host function that allocated, `None` for the harness itself.
+    pub by: Option<String>,
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#x}, {} bytes, allocation #{} by {}",
+            self.ptr,
+            self.size,
+            self.ordinal,
+            self.by.as_deref().unwrap_or("the harness")
+        )
+    }
+}
+
+/// What freed allocations are filled with when poisoning is on.
+const POISON: u8 = 0xDE;
+
+/// [`HeapAllocator`] keeping track of what it hands out.
+pub(crate) struct InstrumentedAllocator {
+    kind: AllocatorKind,
+    heap_base: u32,
+    inner: Box<dyn HeapAllocator>,
+    /// Fill freed allocations with [`POISON`].
+    poison_freed: bool,
+    stats: AllocatorStats,
+    live: HashMap<u32, Allocation>,
+    ordinal: u64,
+    /// The ordinal of the first allocation of the current call.
+    call_start: u64,
+}
+
+impl InstrumentedAllocator {
+    pub(crate) fn new(kind: AllocatorKind, heap_base: u32) -> Self {
+        Self {
+            kind,
+            heap_base,
+            inner: kind.create(heap_base),
+            poison_freed: false,
+            stats: AllocatorStats::default(),
+            live: HashMap::new(),
+            ordinal: 0,
+            call_start: 0,
+        }
+    }
+
+    pub(crate) fn poison_freed(mut self, poison_freed: bool) -> Self {
+        self.poison_freed = poison_freed;
+        self
+    }
+
+    pub(crate) fn heap_base(&self) -> u32 {
+        self.heap_base
+    }
+
+    /// Forgets every allocation, as if the allocator was created anew.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.kind, self.heap_base).poison_freed(self.poison_freed);
+    }
+
+    /// Starts counting anew, for the call about to be made.
+    pub(crate) fn begin_call(&mut self) {
+        self.stats = AllocatorStats {
+            live_bytes: self.stats.live_bytes,
+            peak_live_bytes: self.stats.live_bytes,
+            ..AllocatorStats::default()
+        };
+        self.call_start = self.ordinal;
+    }
+
+    pub(crate) fn stats(&self) -> AllocatorStats {
+        self.stats
+    }
+
+    /// Every allocation not freed yet.
+    pub(crate) fn live(&self) -> Vec<Allocation> {
+        self.live.values().cloned().collect()
+    }
+
+    /// The bookkeeping the allocator keeps in front of every allocation.
+    pub(crate) fn header_size(&self) -> u32 {
+        match self.kind {
+            AllocatorKind::FreeingBump => 8,
+            AllocatorKind::Bump | AllocatorKind::Buddy => 0,
+        }
+    }
+
+    /// The allocations made during the current call and not freed yet, oldest first.
+    pub(crate) fn outstanding(&self) -> Vec<Allocation> {
+        let mut outstanding = self
+            .live
+            .values()
+            .filter(|allocation| allocation.ordinal >= self.call_start)
+            .cloned()
+            .collect::<Vec<_>>();
+        outstanding.sort_by_key(|allocation| allocation.ordinal);
+        outstanding
+    }
+
+    pub(crate) fn allocate(
+        &mut self,
+        memory: &mut [u8],
+        size: u32,
+        by: Option<&str>,
+    ) -> Result<u32, String> {
+        let ptr = self.inner.allocate(memory, size)?;
+        let allocation = Allocation {
+            ptr,
+            size,
+            ordinal: self.ordinal,
+            by: by.map(str::to_string),
+        };
+        self.live.insert(ptr, allocation);
+        self.ordinal += 1;
+        self.stats.allocations += 1;
+        self.stats.largest_request = self.stats.largest_request.max(size);
+        self.stats.live_bytes += u64::from(size);
+        self.stats.peak_live_bytes = self.stats.peak_live_bytes.max(self.stats.live_bytes);
+        Ok(ptr)
+    }
+
+    pub(crate) fn deallocate(&mut self, memory: &mut [u8], ptr: u32) -> Result<(), String> {
+        self.inner.deallocate(memory, ptr)?;
+        if let Some(allocation) = self.live.remove(&ptr) {
+            self.stats.live_bytes -= u64::from(allocation.size);
+            if self.poison_freed {
+                let start = ptr as usize;
+                if let Some(freed) = memory.get_mut(start..start + allocation.size as usize) {
+                    freed.iter_mut().for_each(|byte| *byte = POISON);
+                }
+            }
+        }
+        self.stats.frees += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! The engines a runtime can be instantiated under.
+//!
+//! The host functions, the allocator and the reporting only see an [`ExecBackend`], so adding
+//! an engine, or a wasmtime version with a different API, comes down to implementing it.
+
+use crate::executor::HostCallable;
+use crate::state::{ExportedGlobal, LinearMemory};
+use crate::{ExecError, Executor};
+use std::fmt;
+use std::rc::Rc;
+use wasmtime::{Extern, Func, Instance, Module, Trap, Val};
+
+/// The engine executing the calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Compiled to native code by wasmtime.
+    Wasmtime,
+    /// Interpreted by wasmi, which tells a miscompilation from a bug in the runtime.
+    Wasmi,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Wasmtime
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wasmtime" => Ok(Backend::Wasmtime),
+            "wasmi" => Ok(Backend::Wasmi),
+            _ => Err(format!("unknown backend `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Wasmtime => "wasmtime",
+            Backend::Wasmi => "wasmi",
+        })
+    }
+}
+
+/// An instance of the runtime under some engine.
+///
+/// Values cross the boundary as wasmtime's [`Val`]s and traps as its [`Trap`]s, worded like
+/// wasmtime words them, so that the errors come out the same under every engine.
+pub(crate) trait ExecBackend {
+    /// Instantiates the module of `executor`, with `imports` implementing its imports in order,
+    /// and runs its start function.
+    fn instantiate(executor: &Executor, imports: Vec<HostCallable>) -> Result<Self, ExecError>
+    where
+        Self: Sized;
+
+    fn backend(&self) -> Backend;
+
+    /// Calls the exported function, which the caller has checked exists.
+    fn call(&self, method_name: &str, params: &[Val]) -> Result<Box<[Val]>, Trap>;
+
+    /// The names of everything the instance exports.
+    fn exports(&self) -> Vec<String>;
+
+    /// The exported memory `memory`.
+    fn memory(&self) -> Option<LinearMemory>;
+
+    fn global(&self, name: &str) -> Option<ExportedGlobal>;
+
+    /// The exported function as a wasmtime [`Func`], if the engine has them.
+    fn func(&self, _method_name: &str) -> Option<&Func> {
+        None
+    }
+}
+
+pub(crate) struct WasmtimeBackend {
+    instance: Instance,
+    module: Module,
+}
+
+impl ExecBackend for WasmtimeBackend {
+    fn instantiate(executor: &Executor, imports: Vec<HostCallable>) -> Result<Self, ExecError> {
+        let externs = imports
+            .into_iter()
+            .map(|callable| {
+                let func_ty = callable.func_ty.clone();
+                Extern::Func(Func::new(&executor.store, func_ty, Rc::new(callable)))
+            })
+            .collect::<Vec<_>>();
+        let instance =
+            Instance::new(&executor.module, &externs).map_err(ExecError::Instantiation)?;
+        Ok(Self {
+            instance,
+            module: executor.module.clone(),
+        })
+    }
+
+    fn backend(&self) -> Backend {
+        Backend::Wasmtime
+    }
+
+    fn call(&self, method_name: &str, params: &[Val]) -> Result<Box<[Val]>, Trap> {
+        self.func(method_name)
+            .ok_or_else(|| Trap::new(format!("no exported function `{}`", method_name)))?
+            .call(params)
+    }
+
+    fn exports(&self) -> Vec<String> {
+        self.module
+            .exports()
+            .iter()
+            .map(|export| export.name().to_string())
+            .collect()
+    }
+
+    fn memory(&self) -> Option<LinearMemory> {
+        let memory = self.instance.get_export("memory")?.memory()?;
+        Some(LinearMemory::Wasmtime(memory.clone()))
+    }
+
+    fn global(&self, name: &str) -> Option<ExportedGlobal> {
+        let global = self.instance.get_export(name)?.global()?;
+        Some(ExportedGlobal::Wasmtime(global.clone()))
+    }
+
+    fn func(&self, method_name: &str) -> Option<&Func> {
+        self.instance.get_export(method_name)?.func()
+    }
+}
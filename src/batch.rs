@@ -0,0 +1,97 @@
+use crate::config::Call;
+use repro::ExecError;
+use std::panic::{self, AssertUnwindSafe};
+
+pub enum Status {
+    Success,
+    Trap(String),
+    Panic(String),
+    Timeout(String),
+    Error(String),
+}
+
+impl Status {
+    fn is_success(&self) -> bool {
+        matches!(self, Status::Success)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Success => "ok",
+            Status::Trap(_) => "trap",
+            Status::Panic(_) => "panic",
+            Status::Timeout(_) => "timeout",
+            Status::Error(_) => "error",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            Status::Success => "",
+            Status::Trap(msg) | Status::Panic(msg) | Status::Timeout(msg) | Status::Error(msg) => {
+                msg
+            }
+        }
+    }
+}
+
+/// Performs every call in order, carrying on after a failed one.
+pub fn run<F>(calls: &[Call], mut perform: F) -> Vec<Status>
+where
+    F: FnMut(&Call) -> Result<(), ExecError>,
+{
+    calls
+        .iter()
+        .map(|call| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| perform(call)));
+            let status = match result {
+                Ok(Ok(())) => Status::Success,
+                Ok(Err(ExecError::HostPanic { message, .. })) => Status::Panic(message),
+                // Classified like the exit code, so that failing host functions count as traps.
+                Ok(Err(err)) => match err.exit_code() {
+                    10 => Status::Trap(err.to_string()),
+                    14 => Status::Timeout(err.to_string()),
+                    _ => Status::Error(err.to_string()),
+                },
+                Err(payload) => Status::Panic(repro::panic_message(&*payload)),
+            };
+            log::info!(target: "batch", "{}: {}", call.method, status.name());
+            status
+        })
+        .collect()
+}
+
+/// Prints a table with a row per call and returns the number of failed calls.
+pub fn print_summary(calls: &[Call], statuses: &[Status]) -> usize {
+    let method_width = calls
+        .iter()
+        .map(|call| call.method.len())
+        .max()
+        .unwrap_or(0)
+        .max("method".len());
+    println!(
+        "{:>3}  {:<width$}  {:<6}  detail",
+        "#",
+        "method",
+        "status",
+        width = method_width
+    );
+    for (idx, (call, status)) in calls.iter().zip(statuses).enumerate() {
+        // Trap messages tend to span several lines, only the first one fits the table.
+        let detail = status.detail().lines().next().unwrap_or("");
+        println!(
+            "{:>3}  {:<width$}  {:<6}  {}",
+            idx,
+            call.method,
+            status.name(),
+            detail,
+            width = method_width
+        );
+    }
+    let failed = statuses
+        .iter()
+        .filter(|status| !status.is_success())
+        .count();
+    println!("{} calls, {} failed", statuses.len(), failed);
+    failed
+}
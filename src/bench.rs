@@ -0,0 +1,55 @@
+use repro::ExecutorBuilder;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Compiles and instantiates the runtime and performs the call `iterations` times, reporting the
+/// timings of both phases.
+pub fn bench(
+    builder: &ExecutorBuilder,
+    wasm: &Path,
+    method_name: &str,
+    input_data: &[u8],
+    iterations: usize,
+) -> anyhow::Result<()> {
+    let mut instantiations = Vec::with_capacity(iterations);
+    let mut calls = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let runtime = builder.clone().build_from_file(wasm)?.instantiate()?;
+        let instantiated = Instant::now();
+        runtime.call(method_name, input_data)?;
+        instantiations.push(instantiated - start);
+        calls.push(instantiated.elapsed());
+    }
+    report("instantiate", &instantiations);
+    report("call", &calls);
+    Ok(())
+}
+
+/// Compiles the runtime with the cache enabled, which leaves the compiled module in it.
+pub fn precompile(builder: ExecutorBuilder, wasm: &Path) -> anyhow::Result<()> {
+    let start = Instant::now();
+    builder.build_from_file(wasm)?;
+    println!(
+        "{}: compiled in {:?}, later runs with the same flags load it from the cache",
+        wasm.display(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+fn report(phase: &str, samples: &[Duration]) {
+    let (min, max) = match (samples.iter().min(), samples.iter().max()) {
+        (Some(min), Some(max)) => (min, max),
+        _ => return,
+    };
+    let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+    println!(
+        "{:<12} min {:>10?}  mean {:>10?}  max {:>10?}  ({} iterations)",
+        phase,
+        min,
+        mean,
+        max,
+        samples.len()
+    );
+}
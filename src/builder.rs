@@ -0,0 +1,416 @@
+use crate::executor::new_store;
+use crate::hooks::Hooks;
+use crate::trace::Replay;
+use crate::Symbolizer;
+use crate::{
+    AllocatorKind, Backend, ExecError, Executor, HostCallRecord, HostCallTrace, HostContext,
+    HostFunction, HostRegistry, OffchainConfig, Storage, UnknownImportPolicy, DEFAULT_HEAP_BASE,
+};
+use anyhow::anyhow;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+use wasmtime::{Config, Module, OptLevel, Trap, Val};
+
+/// Configures and builds an [`Executor`].
+///
+/// ```no_run
+/// let executor = repro::ExecutorBuilder::new()
+///     .heap_base(1055861)
+///     .memory_limit(1024)
+///     .host_fn_override("ext_logging_log_version_1", |_params, _results| Ok(()))
+///     .build_from_file("sc_runtime_test.wasm".as_ref())?;
+/// # Ok::<(), repro::ExecError>(())
+/// ```
+#[derive(Clone)]
+pub struct ExecutorBuilder {
+    config: Config,
+    heap_base: u32,
+    allocator: AllocatorKind,
+    poison_freed: bool,
+    debug_info: Option<bool>,
+    opt_level: Option<OptLevel>,
+    symbolize: bool,
+    backend: Backend,
+    registry: HostRegistry,
+    storage: Storage,
+    offchain: OffchainConfig,
+    hooks: Hooks,
+    memory_limit: Option<u32>,
+    stack_size: Option<usize>,
+    fuel: Option<u64>,
+    nan_canonicalization: bool,
+    timeout: Option<Duration>,
+    unknown_import_policy: UnknownImportPolicy,
+    replay: Option<Vec<HostCallRecord>>,
+}
+
+impl Default for ExecutorBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config::new(),
+            heap_base: DEFAULT_HEAP_BASE,
+            allocator: AllocatorKind::default(),
+            poison_freed: false,
+            debug_info: None,
+            opt_level: None,
+            symbolize: true,
+            backend: Backend::default(),
+            registry: HostRegistry::with_builtins(),
+            storage: Storage::new(),
+            offchain: OffchainConfig::default(),
+            hooks: Hooks::default(),
+            memory_limit: None,
+            stack_size: None,
+            fuel: None,
+            nan_canonicalization: false,
+            timeout: None,
+            unknown_import_policy: UnknownImportPolicy::default(),
+            replay: None,
+        }
+    }
+}
+
+impl ExecutorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where the allocator starts handing out memory if the module doesn't export
+    /// `__heap_base`. Defaults to [`DEFAULT_HEAP_BASE`].
+    pub fn heap_base(mut self, heap_base: u32) -> Self {
+        self.heap_base = heap_base;
+        self
+    }
+
+    /// The allocator behind `ext_allocator_*`. Defaults to [`AllocatorKind::FreeingBump`].
+    pub fn allocator(mut self, allocator: AllocatorKind) -> Self {
+        self.allocator = allocator;
+        self
+    }
+
+    /// Fill allocations with `0xDE` once freed, so that using them afterwards stands out.
+    pub fn poison_freed(mut self, poison_freed: bool) -> Self {
+        self.poison_freed = poison_freed;
+        self
+    }
+
+    /// Whether wasmtime generates debug info for the compiled code, overriding the config.
+    /// Defaults to generating it if the module has DWARF sections.
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info = Some(debug_info);
+        self
+    }
+
+    /// The cranelift optimization level, overriding the config.
+    pub fn opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = Some(opt_level);
+        self
+    }
+
+    /// Whether to resolve backtrace frames to function names and source locations. Defaults
+    /// to `true`.
+    pub fn symbolize(mut self, symbolize: bool) -> Self {
+        self.symbolize = symbolize;
+        self
+    }
+
+    /// The wasmtime config to compile with. The memory reservation and guard sizes are not
+    /// part of it, the pinned wasmtime hardcodes them.
+    pub fn wasmtime_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The engine executing the calls. Defaults to [`Backend::Wasmtime`].
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// The host functions to link the imports against. Defaults to
+    /// [`HostRegistry::with_builtins`].
+    pub fn host_registry(mut self, registry: HostRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// The storage the `ext_storage_*` host functions operate on, for starting from
+    /// prepopulated storage. Host functions registered under the same names take precedence.
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// What the `ext_offchain_*` host functions respond with. Host functions registered
+    /// under the same names take precedence.
+    pub fn offchain(mut self, offchain: OffchainConfig) -> Self {
+        self.offchain = offchain;
+        self
+    }
+
+    /// Registers `function` as the implementation of the imported function `name`.
+    pub fn register_host_fn(mut self, name: &str, function: impl HostFunction + 'static) -> Self {
+        self.registry.register(name, function);
+        self
+    }
+
+    /// Implements the imported functions the Rhai `script` defines with it, see
+    /// [`register_script`](crate::register_script).
+    #[cfg(feature = "scripting")]
+    pub fn host_script(mut self, script: &str) -> Self {
+        crate::register_script(&mut self.registry, script);
+        self
+    }
+
+    /// Implements the imported functions the shared library at `path` registers, see
+    /// [`load_plugin`](crate::load_plugin).
+    #[cfg(unix)]
+    pub fn plugin(mut self, path: &Path) -> Result<Self, ExecError> {
+        crate::load_plugin(&mut self.registry, path)?;
+        Ok(self)
+    }
+
+    /// Replaces the implementation of the imported function `name` with one that doesn't
+    /// need access to the host state.
+    pub fn host_fn_override<F>(mut self, name: &str, f: F) -> Self
+    where
+        F: Fn(&[Val], &mut [Val]) -> Result<(), Trap> + 'static,
+    {
+        self.registry
+            .register_fn(name, move |_: &HostContext, params, results| {
+                f(params, results).map_err(ExecError::from)
+            });
+        self
+    }
+
+    /// Replaces the implementation of the imported function `name`, keeping the defaults for
+    /// every other one. `f` gets the params and returns the result, if the function has one.
+    ///
+    /// ```no_run
+    /// # use parity_scale_codec::Encode;
+    /// let executor = repro::ExecutorBuilder::new()
+    ///     .override_host_fn("ext_storage_get_version_1", |ctx, _params| {
+    ///         let value = Some(b"canned".to_vec());
+    ///         Ok(Some(wasmtime::Val::I64(ctx.allocate_packed(&value.encode())? as i64)))
+    ///     })
+    ///     .build_from_file("sc_runtime_test.wasm".as_ref())?;
+    /// # Ok::<(), repro::ExecError>(())
+    /// ```
+    pub fn override_host_fn<F>(mut self, name: &str, f: F) -> Self
+    where
+        F: Fn(&HostContext, &[Val]) -> Result<Option<Val>, ExecError> + 'static,
+    {
+        self.registry
+            .register_fn(name, move |ctx, params, results| {
+                if let (Some(ret), Some(result)) = (f(ctx, params)?, results.first_mut()) {
+                    *result = ret;
+                }
+                Ok(())
+            });
+        self
+    }
+
+    /// Runs `hook` before every export invocation. It may rewrite the params and the memory.
+    pub fn on_before_call<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &mut [Val], &HostContext) + 'static,
+    {
+        self.hooks.before_call.push(Rc::new(hook));
+        self
+    }
+
+    /// Runs `hook` after every export invocation, whether it succeeded or not.
+    pub fn on_after_call<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, Result<&[Val], &ExecError>, &HostContext) + 'static,
+    {
+        self.hooks.after_call.push(Rc::new(hook));
+        self
+    }
+
+    /// Runs `hook` after every host function call that returned successfully.
+    pub fn on_host_call<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HostContext, &[Val], &[Val]) + 'static,
+    {
+        self.hooks.host_call.push(Rc::new(hook));
+        self
+    }
+
+    /// Runs `hook` after every host function call that failed, with the message it traps with.
+    pub fn on_host_call_failed<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HostContext, &[Val], &str) + 'static,
+    {
+        self.hooks.host_call_failed.push(Rc::new(hook));
+        self
+    }
+
+    /// Records every host call into `trace`, the failed ones with their error.
+    pub fn trace_host_calls(self, trace: HostCallTrace) -> Self {
+        let failed = trace.clone();
+        self.on_host_call(move |ctx, params, results| trace.record(ctx, params, Ok(results)))
+            .on_host_call_failed(move |ctx, params, message| {
+                failed.record(ctx, params, Err(message))
+            })
+    }
+
+    /// Drops every hook registered so far, e.g. to perform a call again without recording it
+    /// twice.
+    pub fn without_hooks(mut self) -> Self {
+        self.hooks = Hooks::default();
+        self
+    }
+
+    /// Replays a trace recorded with [`ExecutorBuilder::trace_host_calls`]: every host call
+    /// must match the next record, and gets the recorded results instead of its own. The host
+    /// functions still run, so that their effects on the memory are reproduced.
+    ///
+    /// All the executor's instances draw from the same trace.
+    pub fn replay(mut self, records: Vec<HostCallRecord>) -> Self {
+        self.replay = Some(records);
+        self
+    }
+
+    /// The maximum number of wasm pages the linear memory may grow to. wasmtime can't stop
+    /// `memory.grow` short of the maximum the module declares, so exceeding the limit is
+    /// reported after the host call or the wasm call during which it happened, naming the
+    /// export being called.
+    pub fn memory_limit(mut self, pages: u32) -> Self {
+        self.memory_limit = Some(pages);
+        self
+    }
+
+    /// The native stack size of the thread the calls are made on, reported when they overflow
+    /// it. wasm frames live on the native stack and the pinned wasmtime has no limit of its
+    /// own, so this is what bounds the recursion depth; make the calls inside
+    /// [`on_stack`](crate::on_stack) with the same size.
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Meters the calls, each of which traps with [`ExecError::OutOfFuel`] once it has
+    /// executed roughly `fuel` instructions. The pinned wasmtime has no fuel of its own, so the
+    /// module is instrumented to count them, which leaves its DWARF behind. More than
+    /// `i64::MAX` is clamped to it.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel.min(crate::fuel::MAX_FUEL));
+        self
+    }
+
+    /// Replaces every NaN a float operation produces with the canonical one, so that float
+    /// using runtimes behave the same across hosts. Like [`ExecutorBuilder::fuel`], this
+    /// instruments the module and leaves its DWARF behind.
+    pub fn nan_canonicalization(mut self, enable: bool) -> Self {
+        self.nan_canonicalization = enable;
+        self
+    }
+
+    /// Fails every call that is still running `timeout` after it started with
+    /// [`ExecError::Timeout`]. The pinned wasmtime can't interrupt wasm code, so this is
+    /// checked on every host call and a loop that makes none runs on: the CLI performs calls
+    /// on a thread of their own and abandons it.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// What to do about function imports without a registered host function. Defaults to
+    /// [`UnknownImportPolicy::Zero`].
+    pub fn unknown_import_policy(mut self, policy: UnknownImportPolicy) -> Self {
+        self.unknown_import_policy = policy;
+        self
+    }
+
+    pub fn build(self, code: &[u8]) -> Result<Executor, ExecError> {
+        let mut config = self.config;
+        let mut instrumented = None;
+        if self.nan_canonicalization {
+            let canonicalized = crate::nan::canonicalize_nans(code);
+            instrumented = Some(canonicalized.map_err(ExecError::Compilation)?);
+        }
+        if let Some(fuel) = self.fuel {
+            let metered = crate::fuel::instrument(instrumented.as_deref().unwrap_or(code), fuel);
+            instrumented = Some(metered.map_err(ExecError::Compilation)?);
+        }
+        match self.debug_info {
+            Some(debug_info) => {
+                config.debug_info(debug_info);
+            }
+            None if instrumented.is_none() && crate::dwarf::has_debug_info(code) => {
+                config.debug_info(true);
+            }
+            None => {}
+        }
+        if let Some(opt_level) = self.opt_level {
+            config.cranelift_opt_level(opt_level);
+        }
+        let data_segments = crate::module_info::data_segments(code).unwrap_or_else(|err| {
+            tracing::warn!("can't parse the data section: {:#}", err);
+            Vec::new()
+        });
+        let symbolizer = if self.symbolize {
+            Symbolizer::new(code)
+        } else {
+            Symbolizer::default()
+        };
+        let store = new_store(&config);
+        let code = instrumented.as_deref().unwrap_or(code);
+        let module = Module::new(&store, code).map_err(ExecError::Compilation)?;
+        let interpreted = match self.backend {
+            Backend::Wasmtime => None,
+            Backend::Wasmi => Some(
+                wasmi::Module::from_buffer(code)
+                    .map_err(|err| ExecError::Compilation(anyhow!("{}", err)))?,
+            ),
+        };
+        let mut registry = HostRegistry::new();
+        self.storage.register(&mut registry);
+        crate::offchain::register(&self.offchain, &mut registry);
+        registry.extend(self.registry);
+        Ok(Executor {
+            store,
+            module,
+            interpreted,
+            heap_base: self.heap_base,
+            allocator: self.allocator,
+            poison_freed: self.poison_freed,
+            symbolizer: Rc::new(symbolizer),
+            data_segments: Rc::new(data_segments),
+            registry,
+            storage: self.storage,
+            hooks: Rc::new(self.hooks),
+            memory_limit: self.memory_limit,
+            stack_size: self.stack_size,
+            fuel: self.fuel,
+            nan_canonicalization: self.nan_canonicalization,
+            timeout: self.timeout,
+            unknown_import_policy: self.unknown_import_policy,
+            replay: self.replay.map(|records| Rc::new(Replay::new(records))),
+        })
+    }
+
+    pub fn build_from_file(self, wasm: &Path) -> Result<Executor, ExecError> {
+        self.build(&read_wasm(wasm)?)
+    }
+}
+
+/// Reads the module at `wasm`, assembling it first if it is a `.wat` file.
+pub fn read_wasm(wasm: &Path) -> Result<Vec<u8>, ExecError> {
+    if wasm
+        .extension()
+        .map_or(false, |extension| extension == "wat")
+    {
+        return wat::parse_file(wasm).map_err(|err| ExecError::ParseWat {
+            path: wasm.to_path_buf(),
+            message: err.to_string(),
+        });
+    }
+    fs::read(wasm).map_err(|source| ExecError::ReadWasm {
+        path: wasm.to_path_buf(),
+        source,
+    })
+}
@@ -0,0 +1,566 @@
+use crate::config::{Call, OptLevelName, Proposal, StrategyName};
+use crate::input::{parse_arg, parse_hex, HexBytes, InputOpts};
+use crate::scale::ScaleType;
+use crate::settings::{self, Layer, Settings};
+use crate::watch::WatchRange;
+use anyhow::anyhow;
+use repro::{
+    AllocatorKind, Backend, ExecError, ExecutorBuilder, HostCallTrace, HostContext, MemorySnapshot,
+    TraceVal, UnknownImportPolicy,
+};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "repro",
+    about = "Calls exports of a substrate runtime under wasmtime"
+)]
+pub enum Cli {
+    /// Instantiate the runtime and call a single export.
+    Run(RunOpts),
+    /// Execute every call described in a session file (see `repro.toml`).
+    Session {
+        #[structopt(parse(from_os_str), default_value = "repro.toml")]
+        config: PathBuf,
+    },
+    /// Perform several calls in a row, continuing after failures, and print a summary.
+    Batch(BatchOpts),
+    /// Instantiate the runtime once and issue commands against it interactively.
+    Repl {
+        /// Path to the runtime wasm blob.
+        #[structopt(long, parse(from_os_str), default_value = "sc_runtime_test.wasm")]
+        wasm: PathBuf,
+    },
+    /// Print the imports and exports of the runtime, and how it was built.
+    Inspect {
+        /// Path to the runtime wasm blob.
+        #[structopt(parse(from_os_str), default_value = "sc_runtime_test.wasm")]
+        wasm: PathBuf,
+    },
+    /// Check that the runtime is valid wasm that the harness can instantiate.
+    Validate {
+        /// Path to the runtime wasm blob.
+        #[structopt(long, parse(from_os_str), default_value = "sc_runtime_test.wasm")]
+        wasm: PathBuf,
+        /// Also check that this export is a runtime API.
+        #[structopt(long)]
+        method: Option<String>,
+    },
+    /// Print the wasm text of one function, such as one from a trap backtrace.
+    Disasm {
+        /// Path to the runtime wasm blob.
+        #[structopt(parse(from_os_str), default_value = "sc_runtime_test.wasm")]
+        wasm: PathBuf,
+        /// The index of the function, or its export or demangled name.
+        #[structopt(long)]
+        func: String,
+    },
+    /// Pretty-print a core dump written by `run --core-dump-on-trap`.
+    Analyze {
+        #[structopt(parse(from_os_str))]
+        dump: PathBuf,
+    },
+    /// Compile the runtime into wasmtime's on-disk cache, so that later runs with the same
+    /// wasmtime flags skip compilation. The pinned wasmtime can't serialize a compiled module
+    /// to a file of its own.
+    Precompile {
+        #[structopt(flatten)]
+        run: RunOpts,
+    },
+    /// Perform the call under several backends and print where they first diverge: in the
+    /// host calls, the return value or the allocator state.
+    Diff {
+        #[structopt(flatten)]
+        run: RunOpts,
+        /// The backends to compare, the first one is the reference.
+        #[structopt(long, use_delimiter = true, default_value = "wasmtime,wasmi")]
+        backends: Vec<Backend>,
+    },
+    /// Call `Core_version` and print the spec name and version and the declared runtime APIs.
+    Version {
+        #[structopt(flatten)]
+        run: RunOpts,
+    },
+    /// Drive the block import and authoring paths of the runtime with SCALE encoded blocks,
+    /// headers and extrinsics, all against the same instance.
+    Block {
+        #[structopt(flatten)]
+        run: RunOpts,
+        #[structopt(subcommand)]
+        call: BlockCall,
+    },
+    /// Fetch the metadata of the runtime through `Metadata_metadata` and print its version.
+    Metadata {
+        #[structopt(flatten)]
+        run: RunOpts,
+        /// Write the SCALE encoded metadata to this file.
+        #[structopt(long, parse(from_os_str))]
+        out: Option<PathBuf>,
+    },
+    /// Measure how long instantiation and the call take.
+    Bench {
+        #[structopt(flatten)]
+        run: RunOpts,
+        /// How many times to instantiate and call.
+        #[structopt(long, default_value = "10")]
+        iterations: usize,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum BlockCall {
+    /// Call `Core_execute_block` with the encoded block.
+    Execute {
+        #[structopt(parse(from_os_str))]
+        block: PathBuf,
+    },
+    /// Call `Core_initialize_block` with the encoded header.
+    Initialize {
+        #[structopt(parse(from_os_str))]
+        header: PathBuf,
+    },
+    /// Initialize the block with the encoded header, then call
+    /// `BlockBuilder_apply_extrinsic` with every encoded extrinsic in turn.
+    ApplyExtrinsic {
+        #[structopt(parse(from_os_str))]
+        header: PathBuf,
+        #[structopt(parse(from_os_str), required = true)]
+        extrinsics: Vec<PathBuf>,
+    },
+}
+
+/// Options of a single call. Anything not given here falls back to the environment, the
+/// `--config` file and the defaults, see `settings`.
+#[derive(StructOpt)]
+pub struct RunOpts {
+    /// Path to the runtime wasm blob, or `.wat` [env: REPRO_WASM] [default: sc_runtime_test.wasm].
+    #[structopt(long, parse(from_os_str))]
+    pub wasm: Option<PathBuf>,
+    /// Name of the export to call [env: REPRO_METHOD].
+    #[structopt(long)]
+    pub method: Option<String>,
+    /// Heap base of the allocator, if the module doesn't export `__heap_base` [env:
+    /// REPRO_HEAP_BASE] [default: 1055861].
+    #[structopt(long)]
+    pub heap_base: Option<u32>,
+    #[structopt(flatten)]
+    pub input: InputOpts,
+    /// Session file to take the wasm, heap base, wasmtime flags, offchain responses and the
+    /// first call from.
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Output format, `human` or `json`.
+    #[structopt(long, default_value = "human")]
+    pub output: OutputFormat,
+    /// Fail unless the call returns these hex encoded bytes.
+    #[structopt(long, parse(try_from_str = parse_hex))]
+    pub expect_ret: Option<HexBytes>,
+    /// Fail unless the call traps with a message containing this, e.g. `unreachable`. The
+    /// expected trap is a success.
+    #[structopt(long, conflicts_with = "expect-ret")]
+    pub expect_trap: Option<String>,
+    /// Write the bytes the call returns to this file.
+    #[structopt(long, parse(from_os_str))]
+    pub ret_out: Option<PathBuf>,
+    /// Call an export that doesn't take the pointer and length of its input with these
+    /// params, e.g. `i32:5,i64:0`, and print what it returns.
+    #[structopt(long, use_delimiter = true, parse(try_from_str = parse_arg))]
+    pub args: Vec<TraceVal>,
+    /// Decode the returned bytes as this SCALE type, e.g. `bool`, `u32`, `Vec<u8>` or
+    /// `Result<(), String>`.
+    #[structopt(long)]
+    pub ret_type: Option<ScaleType>,
+    /// The engine to execute the call: `wasmtime`, or the `wasmi` interpreter.
+    #[structopt(long, default_value = "wasmtime")]
+    pub backend: Backend,
+    /// Compile, link and instantiate the runtime and look up the export, but don't call it.
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// What to link imports the harness doesn't implement against: `zero` for stubs returning
+    /// zeroes, `trap` for functions trapping once called, `error` to refuse to instantiate.
+    #[structopt(long, default_value = "zero")]
+    pub unknown_import: UnknownImportPolicy,
+    /// Trap, naming the function and its params, once an unimplemented host function is
+    /// called. Same as `--unknown-import trap`.
+    #[structopt(long)]
+    pub strict: bool,
+    /// Record every host call, with its params, results and the memory the params point to,
+    /// to a JSON file.
+    #[structopt(long, parse(from_os_str))]
+    pub trace_host_calls: Option<PathBuf>,
+    /// Replay a trace recorded with `--trace-host-calls`: host calls get the recorded results
+    /// and fail if they deviate from the recorded sequence.
+    #[structopt(long, parse(from_os_str))]
+    pub replay: Option<PathBuf>,
+    /// Print the memory ranges the call changed, with their bytes before and after.
+    #[structopt(long)]
+    pub mem_diff: bool,
+    /// Limit `--mem-diff` to the heap, above the heap base.
+    #[structopt(long)]
+    pub mem_diff_heap_only: bool,
+    /// Fail the call once the linear memory grows past this many 64 KiB pages.
+    #[structopt(long)]
+    pub max_memory_pages: Option<u32>,
+    /// The allocator behind `ext_allocator_*`: `freeing-bump`, `bump` or `buddy`. Overrides
+    /// the one of the session file.
+    #[structopt(long)]
+    pub allocator: Option<AllocatorKind>,
+    /// Fill allocations with 0xDE once freed, so that use after free stands out.
+    #[structopt(long)]
+    pub poison_freed: bool,
+    /// Print the number of allocations and frees, the peak live bytes and the largest
+    /// allocation request of the call.
+    #[structopt(long)]
+    pub alloc_stats: bool,
+    /// Print the allocations the call left behind, with their size, ordinal and whether the
+    /// runtime or the harness made them.
+    #[structopt(long)]
+    pub leaks: bool,
+    /// Log which host call window changed the bytes of a range, e.g. `0x101C00..0x101C40`.
+    /// Can be given several times.
+    #[structopt(long)]
+    pub watch: Vec<WatchRange>,
+    /// Dump the linear memory to `memdump-<method>.bin`, along with a JSON index of the heap
+    /// base, the memory size and the data segments, when the call traps.
+    #[structopt(long)]
+    pub memdump_on_trap: bool,
+    /// Write the trap, backtrace, exported globals, allocator state, pending host call and
+    /// linear memory to `core-<method>.dump` when the call traps. See `analyze`.
+    #[structopt(long)]
+    pub core_dump_on_trap: bool,
+    /// Write the report printed when the harness itself segfaults, with the native backtrace,
+    /// the method being called and the last host call, to `crash-<pid>.txt` as well.
+    #[cfg(target_os = "linux")]
+    #[structopt(long)]
+    pub crash_dump: bool,
+    /// Write `crash-report.json`, with the environment, the module hash, the call, the last
+    /// host calls and the trap or signal, when the call traps or the harness crashes.
+    #[structopt(long)]
+    pub crash_report: bool,
+    /// Capture the debug logs, host calls included, and only print them if the call fails.
+    #[structopt(long)]
+    pub log_on_failure: bool,
+    /// Write the logs captured by `--log-on-failure` to this file instead of stderr.
+    #[structopt(long, parse(from_os_str))]
+    pub failure_log: Option<PathBuf>,
+    /// If the call traps, perform it again under the wasmi interpreter with the same settings
+    /// and report whether the outcome diverges.
+    #[structopt(long)]
+    pub rerun_interpreted: bool,
+    /// If the call traps under wasmtime, retry it under wasmi with the same settings and
+    /// report whether the trap reproduces. If it doesn't, the outcome under wasmi is taken.
+    #[structopt(long)]
+    pub auto_fallback: bool,
+    /// Perform the call against a native build of the runtime as well and report whether the
+    /// outputs diverge, see `repro::native` for the entry points it must export.
+    #[cfg(unix)]
+    #[structopt(long, parse(from_os_str))]
+    pub native: Option<PathBuf>,
+    /// Perform the call on a thread with a native stack of this many bytes, which is what
+    /// limits the wasm recursion depth.
+    #[structopt(long)]
+    pub stack_size: Option<usize>,
+    /// Give up on the call after this long, e.g. `500ms`, `5s` or `2m`, exiting with 14.
+    /// wasmtime can't interrupt wasm code, so a call spinning without host calls is given up
+    /// on a second later and left running on its thread until the process exits.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    pub timeout: Option<Duration>,
+    /// Trap once the call has executed roughly this many instructions, and report the fuel
+    /// consumed when it completes or traps.
+    #[structopt(long)]
+    pub fuel: Option<u64>,
+    /// Whether wasmtime generates debug info for the compiled code, `true` or `false`.
+    /// Defaults to the session file, or `true` if the module has DWARF sections.
+    #[structopt(long)]
+    pub debug_info: Option<bool>,
+    /// Leave backtrace frames as bare function indices, without names and source locations.
+    #[structopt(long)]
+    pub no_symbolize: bool,
+    /// Run the cranelift IR verifier on every function compiled.
+    #[structopt(long)]
+    pub cranelift_debug_verifier: bool,
+    /// Replace every NaN float operations produce with the canonical one, so that the call
+    /// behaves the same on every host.
+    #[structopt(long)]
+    pub nan_canonicalization: bool,
+    /// Cranelift optimization level: `none`, `speed` or `speed_and_size`. Overrides the one of
+    /// the session file.
+    #[structopt(long)]
+    pub opt_level: Option<OptLevelName>,
+    /// The compiler: `auto`, `cranelift` or `lightbeam`. Overrides the one of the session
+    /// file.
+    #[structopt(long)]
+    pub strategy: Option<StrategyName>,
+    /// Support a wasm proposal: `simd`, `bulk-memory`, `multi-value`, `reference-types`,
+    /// `threads` or `sign-ext`. Can be given several times.
+    #[structopt(long)]
+    pub enable_wasm: Vec<Proposal>,
+    /// Don't support a wasm proposal the session file enables, to narrow down which one a
+    /// miscompilation comes from. Can be given several times.
+    #[structopt(long)]
+    pub disable_wasm: Vec<Proposal>,
+    /// Compile the functions of the module one after another, on a single thread.
+    #[structopt(long)]
+    pub no_parallel_compilation: bool,
+    /// How many threads to compile the module on. Defaults to one per CPU.
+    #[structopt(long)]
+    pub compile_threads: Option<usize>,
+    /// Compile the module anew instead of loading it from wasmtime's on-disk cache.
+    #[structopt(long)]
+    pub no_cache: bool,
+    /// The wasmtime cache config file, instead of the default one.
+    #[structopt(long, parse(from_os_str))]
+    pub cache_config: Option<PathBuf>,
+    /// A cranelift setting in the form `name=value`. Can be given several times.
+    #[structopt(long)]
+    pub cranelift_flag: Vec<String>,
+    /// Implement the host functions defined by a Rhai script, overriding the built-in ones.
+    #[cfg(feature = "scripting")]
+    #[structopt(long, parse(from_os_str))]
+    pub host_script: Option<PathBuf>,
+    /// Implement the host functions a shared library registers, overriding the built-in
+    /// ones, see `include/repro_plugin.h`. Can be given several times.
+    #[cfg(unix)]
+    #[structopt(long, parse(from_os_str))]
+    pub plugin: Vec<PathBuf>,
+}
+
+impl RunOpts {
+    /// The builder for the resolved settings, with the knobs only the command line has.
+    pub fn executor_builder(&self, settings: &Settings) -> anyhow::Result<ExecutorBuilder> {
+        let mut builder = settings
+            .executor_builder()?
+            .symbolize(!self.no_symbolize)
+            .backend(self.backend)
+            .unknown_import_policy(unknown_import_policy(self.unknown_import, self.strict));
+        if let Some(stack_size) = self.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        if let Some(debug_info) = self.debug_info {
+            builder = builder.debug_info(debug_info);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(fuel) = self.fuel {
+            builder = builder.fuel(fuel).on_after_call(move |method, _, ctx| {
+                if let Some(consumed) = ctx.fuel_consumed() {
+                    eprintln!("`{}` consumed {} of {} fuel", method, consumed, fuel);
+                }
+            });
+        }
+        if let Some(ref replay) = self.replay {
+            builder = builder.replay(HostCallTrace::load(replay)?);
+        }
+        if let Some(pages) = self.max_memory_pages {
+            builder = builder.memory_limit(pages);
+        }
+        if let Some(allocator) = self.allocator {
+            builder = builder.allocator(allocator);
+        }
+        if self.poison_freed {
+            builder = builder.poison_freed(true);
+        }
+        builder = builder.on_after_call(|_, outcome, ctx| {
+            if let Some(addr) = outcome.err().and_then(ExecError::address) {
+                eprintln!("{:#x} is in {}", addr, ctx.locate(addr));
+            }
+        });
+        if self.mem_diff {
+            builder = with_mem_diff(builder, self.mem_diff_heap_only);
+        }
+        if self.alloc_stats {
+            builder = builder.on_after_call(|method, _, ctx| {
+                eprintln!("`{}` allocator: {}", method, ctx.allocator_stats());
+            });
+        }
+        if self.leaks {
+            builder = builder.on_after_call(|method, _, ctx| {
+                let outstanding = ctx.outstanding_allocations();
+                eprintln!("`{}` left {} allocation(s):", method, outstanding.len());
+                for allocation in outstanding {
+                    eprintln!("  {}", allocation);
+                }
+            });
+        }
+        if !self.watch.is_empty() {
+            builder = crate::watch::watch(builder, &self.watch);
+        }
+        if self.memdump_on_trap {
+            builder = crate::memdump::on_trap(builder, &settings.wasm)?;
+        }
+        if self.core_dump_on_trap {
+            builder = crate::coredump::on_trap(builder);
+        }
+        if self.crash_report {
+            builder = crate::crash_report::enable(builder, settings)?;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            crate::crash::write_file(self.crash_dump);
+            builder = crate::crash::track(builder);
+        }
+        #[cfg(feature = "scripting")]
+        {
+            if let Some(ref script) = self.host_script {
+                builder = builder.host_script(&std::fs::read_to_string(script)?);
+            }
+        }
+        #[cfg(unix)]
+        {
+            for plugin in &self.plugin {
+                builder = builder.plugin(plugin)?;
+            }
+        }
+        Ok(builder)
+    }
+
+    pub fn settings(&self) -> anyhow::Result<Settings> {
+        let cli = Layer {
+            wasm: self.wasm.clone(),
+            heap_base: self.heap_base,
+            method: self.method.clone(),
+            input: self.input.resolve()?,
+        };
+        let mut settings = settings::resolve(cli, self.config.as_deref())?;
+        let wasmtime = &mut settings.wasmtime;
+        wasmtime.cranelift_debug_verifier |= self.cranelift_debug_verifier;
+        wasmtime.nan_canonicalization |= self.nan_canonicalization;
+        wasmtime.cache &= !self.no_cache;
+        wasmtime.parallel_compilation &= !self.no_parallel_compilation;
+        if self.compile_threads.is_some() {
+            wasmtime.compile_threads = self.compile_threads;
+        }
+        if self.cache_config.is_some() {
+            wasmtime.cache_config = self.cache_config.clone();
+        }
+        if self.opt_level.is_some() {
+            wasmtime.opt_level = self.opt_level;
+        }
+        if self.strategy.is_some() {
+            wasmtime.strategy = self.strategy;
+        }
+        for flag in &self.cranelift_flag {
+            wasmtime.set_cranelift_flag(flag)?;
+        }
+        for &proposal in &self.enable_wasm {
+            wasmtime.set_proposal(proposal, true)?;
+        }
+        for &proposal in &self.disable_wasm {
+            wasmtime.set_proposal(proposal, false)?;
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(StructOpt)]
+pub struct BatchOpts {
+    /// Path to the runtime wasm blob. Ignored if `--manifest` is given.
+    #[structopt(long, parse(from_os_str), default_value = "sc_runtime_test.wasm")]
+    pub wasm: PathBuf,
+    /// Session file providing the wasm, heap base and calls to perform.
+    #[structopt(long, parse(from_os_str))]
+    pub manifest: Option<PathBuf>,
+    /// Calls in the form `method` or `method:0x0402`, performed after the manifest ones.
+    #[structopt(parse(try_from_str = parse_call))]
+    pub calls: Vec<Call>,
+    /// Output format, `human` for a summary table or `json` for a record per line.
+    #[structopt(long, default_value = "human")]
+    pub output: OutputFormat,
+    /// Instantiate the runtime once and perform all calls against it, resetting the
+    /// allocator in between.
+    #[structopt(long)]
+    pub reuse_instance: bool,
+    /// What to link imports the harness doesn't implement against, see `run --help`.
+    #[structopt(long, default_value = "zero")]
+    pub unknown_import: UnknownImportPolicy,
+    /// Same as `--unknown-import trap`.
+    #[structopt(long)]
+    pub strict: bool,
+    /// Give up on a call after this long, see `run --help`. Overrides the manifest's. A call
+    /// spinning without host calls ends the whole batch.
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    pub timeout: Option<Duration>,
+}
+
+impl BatchOpts {
+    pub fn unknown_import_policy(&self) -> UnknownImportPolicy {
+        unknown_import_policy(self.unknown_import, self.strict)
+    }
+}
+
+/// Snapshots the memory, or only the heap, before every call and prints what changed after.
+fn with_mem_diff(builder: ExecutorBuilder, heap_only: bool) -> ExecutorBuilder {
+    let base = move |ctx: &HostContext| if heap_only { ctx.heap_base() } else { 0 };
+    let snapshot = Rc::new(RefCell::new(None));
+    let before = snapshot.clone();
+    builder
+        .on_before_call(move |_, _, ctx| {
+            *before.borrow_mut() = Some(MemorySnapshot::take(ctx.memory(), base(ctx)));
+        })
+        .on_after_call(move |method, _, ctx| {
+            if let Some(before) = snapshot.borrow_mut().take() {
+                let changed = before.diff(&MemorySnapshot::take(ctx.memory(), base(ctx)));
+                eprintln!("`{}` changed {} memory range(s):", method, changed.len());
+                for range in changed {
+                    eprintln!("  {}", range);
+                }
+            }
+        })
+}
+
+fn unknown_import_policy(policy: UnknownImportPolicy, strict: bool) -> UnknownImportPolicy {
+    if strict {
+        UnknownImportPolicy::Trap
+    } else {
+        policy
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format `{}`", s)),
+        }
+    }
+}
+
+/// Parses a duration with a `ms`, `s` or `m` unit, e.g. `5s`.
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse()?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        _ => Err(anyhow!(
+            "`{}` lacks a known unit, expected `ms`, `s` or `m`",
+            s
+        )),
+    }
+}
+
+fn parse_call(s: &str) -> Result<Call, hex::FromHexError> {
+    let mut parts = s.splitn(2, ':');
+    let method = parts.next().unwrap_or_default().to_string();
+    let input = match parts.next() {
+        Some(input) => parse_hex(input)?.0,
+        None => Vec::new(),
+    };
+    Ok(Call { method, input })
+}
@@ -0,0 +1,447 @@
+use anyhow::{anyhow, bail};
+use repro::{AllocatorKind, ExecutorBuilder, OffchainConfig};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use wasmtime::{Config, OptLevel, Strategy};
+
+/// A whole reproduction session, as described by a `repro.toml` file.
+///
+/// ```toml
+/// wasm = "sc_runtime_test.wasm"
+/// heap_base = 1055861
+/// allocator = "freeing-bump"
+/// reuse_instance = false
+/// timeout = "5s"
+///
+/// [logging]
+/// filter = "host-call=debug"
+///
+/// [wasmtime]
+/// opt_level = "speed"
+/// strategy = "cranelift"
+///
+/// [offchain]
+/// is_validator = true
+/// timestamp = 1579000000000
+///
+/// [[offchain.http_response]]
+/// status = 200
+/// headers = [["content-type", "application/json"]]
+/// body = '{"price": 42}'
+///
+/// [[call]]
+/// method = "test_conditional_panic"
+/// input = "0x0402"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    /// Path to the runtime wasm blob, relative to the session file.
+    pub wasm: PathBuf,
+    #[serde(default = "default_heap_base")]
+    pub heap_base: u32,
+    /// `freeing-bump`, `bump` or `buddy`.
+    #[serde(default)]
+    pub allocator: AllocatorKind,
+    /// Perform all the calls against a single instance, resetting the allocator in between,
+    /// instead of instantiating the runtime anew for each of them.
+    #[serde(default)]
+    pub reuse_instance: bool,
+    /// Perform the calls on a thread with a native stack of this many bytes, which is what
+    /// limits the wasm recursion depth.
+    pub stack_size: Option<usize>,
+    /// Give up on a call after this long, e.g. `5s`, see `run --help`.
+    #[serde(default, deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    #[serde(default)]
+    pub logging: Logging,
+    #[serde(default)]
+    pub wasmtime: WasmtimeFlags,
+    #[serde(default)]
+    pub offchain: Offchain,
+    #[serde(default, rename = "call")]
+    pub calls: Vec<Call>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Logging {
+    /// `env_logger` style filter, e.g. `host-call=debug`. Takes precedence over `RUST_LOG`.
+    pub filter: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WasmtimeFlags {
+    #[serde(default)]
+    pub debug_info: bool,
+    #[serde(default)]
+    pub cranelift_debug_verifier: bool,
+    pub opt_level: Option<OptLevelName>,
+    pub strategy: Option<StrategyName>,
+    /// Canonicalize the NaNs float operations produce, for determinism across hosts.
+    #[serde(default)]
+    pub nan_canonicalization: bool,
+    #[serde(default)]
+    pub simd: bool,
+    #[serde(default)]
+    pub bulk_memory: bool,
+    #[serde(default)]
+    pub multi_value: bool,
+    #[serde(default)]
+    pub reference_types: bool,
+    #[serde(default)]
+    pub threads: bool,
+    /// Cache compiled modules on disk, keyed by the module and the compiler settings. wasmtime
+    /// reads where from its cache config file, see `cache_config`.
+    #[serde(default = "default_cache")]
+    pub cache: bool,
+    /// The wasmtime cache config file, instead of the default one.
+    pub cache_config: Option<PathBuf>,
+    /// Compile the functions of the module on several threads.
+    #[serde(default = "default_parallel_compilation")]
+    pub parallel_compilation: bool,
+    /// How many threads to compile on, defaults to one per CPU.
+    pub compile_threads: Option<usize>,
+}
+
+impl Default for WasmtimeFlags {
+    fn default() -> Self {
+        Self {
+            debug_info: false,
+            cranelift_debug_verifier: false,
+            opt_level: None,
+            strategy: None,
+            nan_canonicalization: false,
+            simd: false,
+            bulk_memory: false,
+            multi_value: false,
+            reference_types: false,
+            threads: false,
+            cache: default_cache(),
+            cache_config: None,
+            parallel_compilation: default_parallel_compilation(),
+            compile_threads: None,
+        }
+    }
+}
+
+fn default_cache() -> bool {
+    true
+}
+
+fn default_parallel_compilation() -> bool {
+    true
+}
+
+/// A wasm proposal wasmtime can be told to support.
+#[derive(Clone, Copy)]
+pub enum Proposal {
+    Simd,
+    BulkMemory,
+    MultiValue,
+    ReferenceTypes,
+    Threads,
+    /// Always supported by the pinned wasmtime, it can't be disabled.
+    SignExt,
+}
+
+impl std::str::FromStr for Proposal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simd" => Ok(Proposal::Simd),
+            "bulk-memory" => Ok(Proposal::BulkMemory),
+            "multi-value" => Ok(Proposal::MultiValue),
+            "reference-types" => Ok(Proposal::ReferenceTypes),
+            "threads" => Ok(Proposal::Threads),
+            "sign-ext" => Ok(Proposal::SignExt),
+            _ => Err(format!("unknown wasm proposal `{}`", s)),
+        }
+    }
+}
+
+/// The compiler wasmtime uses, see `wasmtime::Strategy`.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyName {
+    Auto,
+    Cranelift,
+    /// The baseline compiler, only if wasmtime is built with its `lightbeam` feature.
+    Lightbeam,
+}
+
+impl From<StrategyName> for Strategy {
+    fn from(name: StrategyName) -> Strategy {
+        match name {
+            StrategyName::Auto => Strategy::Auto,
+            StrategyName::Cranelift => Strategy::Cranelift,
+            StrategyName::Lightbeam => Strategy::Lightbeam,
+        }
+    }
+}
+
+impl std::str::FromStr for StrategyName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(StrategyName::Auto),
+            "cranelift" => Ok(StrategyName::Cranelift),
+            "lightbeam" => Ok(StrategyName::Lightbeam),
+            _ => Err(format!("unknown compilation strategy `{}`", s)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OptLevelName {
+    None,
+    Speed,
+    SpeedAndSize,
+}
+
+impl From<OptLevelName> for OptLevel {
+    fn from(name: OptLevelName) -> OptLevel {
+        match name {
+            OptLevelName::None => OptLevel::None,
+            OptLevelName::Speed => OptLevel::Speed,
+            OptLevelName::SpeedAndSize => OptLevel::SpeedAndSize,
+        }
+    }
+}
+
+impl std::str::FromStr for OptLevelName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(OptLevelName::None),
+            "speed" => Ok(OptLevelName::Speed),
+            "speed_and_size" => Ok(OptLevelName::SpeedAndSize),
+            _ => Err(format!("unknown optimization level `{}`", s)),
+        }
+    }
+}
+
+impl WasmtimeFlags {
+    /// Applies a cranelift setting given as `name=value`. The pinned wasmtime only lets
+    /// `opt_level` and `enable_verifier` through to cranelift, the harness implements
+    /// `enable_nan_canonicalization` itself.
+    pub fn set_cranelift_flag(&mut self, flag: &str) -> anyhow::Result<()> {
+        let mut parts = flag.splitn(2, '=');
+        let name = parts.next().unwrap_or_default();
+        let value = parts
+            .next()
+            .ok_or_else(|| anyhow!("`{}` is not in the form `name=value`", flag))?;
+        match name {
+            "opt_level" => self.opt_level = Some(value.parse().map_err(|err| anyhow!("{}", err))?),
+            "enable_verifier" => self.cranelift_debug_verifier = value.parse()?,
+            "enable_nan_canonicalization" => self.nan_canonicalization = value.parse()?,
+            _ => bail!(
+                "unsupported cranelift setting `{}`, try `opt_level`, `enable_verifier` or \
+                 `enable_nan_canonicalization`",
+                name
+            ),
+        }
+        Ok(())
+    }
+
+    /// Enables or disables support for a wasm proposal.
+    pub fn set_proposal(&mut self, proposal: Proposal, enable: bool) -> anyhow::Result<()> {
+        match proposal {
+            Proposal::Simd => self.simd = enable,
+            Proposal::BulkMemory => self.bulk_memory = enable,
+            Proposal::MultiValue => self.multi_value = enable,
+            Proposal::ReferenceTypes => self.reference_types = enable,
+            Proposal::Threads => self.threads = enable,
+            Proposal::SignExt if enable => {}
+            Proposal::SignExt => bail!("the pinned wasmtime always supports sign-ext"),
+        }
+        Ok(())
+    }
+
+    /// How many threads to compile on, `None` for one per CPU.
+    pub fn compile_threads(&self) -> Option<usize> {
+        if self.parallel_compilation {
+            self.compile_threads
+        } else {
+            Some(1)
+        }
+    }
+
+    pub fn to_config(&self) -> anyhow::Result<Config> {
+        let mut config = Config::new();
+        config
+            .debug_info(self.debug_info)
+            .cranelift_debug_verifier(self.cranelift_debug_verifier)
+            .wasm_simd(self.simd)
+            .wasm_bulk_memory(self.bulk_memory)
+            .wasm_multi_value(self.multi_value)
+            .wasm_reference_types(self.reference_types)
+            .wasm_threads(self.threads);
+        if let Some(opt_level) = self.opt_level {
+            config.cranelift_opt_level(opt_level.into());
+        }
+        if let Some(strategy) = self.strategy {
+            config.strategy(strategy.into())?;
+        }
+        if self.cache {
+            let loaded = match self.cache_config {
+                Some(ref path) => config.cache_config_load(path).map(drop),
+                None => config.cache_config_load_default().map(drop),
+            };
+            // A broken cache shouldn't stand in the way of reproducing anything.
+            if let Err(err) = loaded {
+                log::warn!("compiling without the cache: {:#}", err);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Canned responses of the offchain host functions, see [`OffchainConfig`].
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Offchain {
+    #[serde(default)]
+    pub is_validator: bool,
+    #[serde(default)]
+    pub timestamp: u64,
+    /// Hex encoded, 32 bytes.
+    #[serde(default, deserialize_with = "hex_bytes")]
+    pub random_seed: Vec<u8>,
+    #[serde(default)]
+    pub local_storage: Vec<LocalStorageEntry>,
+    #[serde(default, rename = "http_response")]
+    pub http_responses: Vec<HttpResponse>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LocalStorageEntry {
+    /// Hex encoded.
+    #[serde(deserialize_with = "hex_bytes")]
+    pub key: Vec<u8>,
+    /// Hex encoded.
+    #[serde(deserialize_with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HttpResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl Offchain {
+    pub fn to_config(&self) -> anyhow::Result<OffchainConfig> {
+        let mut random_seed = [0; 32];
+        if !self.random_seed.is_empty() {
+            if self.random_seed.len() != random_seed.len() {
+                return Err(anyhow!(
+                    "offchain random_seed must be 32 bytes, got {}",
+                    self.random_seed.len()
+                ));
+            }
+            random_seed.copy_from_slice(&self.random_seed);
+        }
+        Ok(OffchainConfig {
+            is_validator: self.is_validator,
+            timestamp: self.timestamp,
+            random_seed,
+            local_storage: self
+                .local_storage
+                .iter()
+                .map(|entry| (entry.key.clone(), entry.value.clone()))
+                .collect(),
+            http_responses: self
+                .http_responses
+                .iter()
+                .map(|response| repro::HttpResponse {
+                    status: response.status,
+                    headers: response
+                        .headers
+                        .iter()
+                        .map(|(name, value)| {
+                            (name.clone().into_bytes(), value.clone().into_bytes())
+                        })
+                        .collect(),
+                    body: response.body.clone().into_bytes(),
+                })
+                .collect(),
+        })
+    }
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Call {
+    pub method: String,
+    /// Hex encoded input data.
+    #[serde(default, deserialize_with = "hex_bytes")]
+    pub input: Vec<u8>,
+}
+
+pub use repro::DEFAULT_HEAP_BASE;
+
+pub const DEFAULT_WASM: &str = "sc_runtime_test.wasm";
+
+fn default_heap_base() -> u32 {
+    DEFAULT_HEAP_BASE
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    crate::cli::parse_duration(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn hex_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    crate::input::parse_hex(&s)
+        .map(|bytes| bytes.0)
+        .map_err(serde::de::Error::custom)
+}
+
+impl Session {
+    pub fn executor_builder(&self) -> anyhow::Result<ExecutorBuilder> {
+        let builder = ExecutorBuilder::new()
+            .wasmtime_config(self.wasmtime.to_config()?)
+            .nan_canonicalization(self.wasmtime.nan_canonicalization)
+            .heap_base(self.heap_base)
+            .allocator(self.allocator)
+            .offchain(self.offchain.to_config()?);
+        let builder = match self.timeout {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
+        };
+        Ok(match self.stack_size {
+            Some(stack_size) => builder.stack_size(stack_size),
+            None => builder,
+        })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut session: Session = toml::from_str(&contents)?;
+        if let Some(dir) = path.parent() {
+            session.wasm = dir.join(&session.wasm);
+        }
+        Ok(session)
+    }
+}
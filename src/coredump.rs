@@ -0,0 +1,129 @@
+use crate::report::to_hex;
+use repro::{
+    Allocation, AllocatorStats, ExecError, ExecutorBuilder, Frame, HostContext, TraceVal, TrapKind,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// Everything known about the instance at the moment a call trapped.
+///
+/// Stored as a line of JSON followed by the raw linear memory.
+#[derive(Serialize, Deserialize)]
+pub struct CoreDump {
+    pub method: String,
+    pub trap_kind: Option<TrapKind>,
+    pub message: String,
+    pub backtrace: Vec<Frame>,
+    pub globals: Vec<(String, TraceVal)>,
+    pub heap_base: u32,
+    pub allocator: AllocatorStats,
+    /// The allocations made during the call and not freed yet.
+    pub allocations: Vec<Allocation>,
+    /// The host function the call failed in, if it did.
+    pub pending_host_call: Option<String>,
+    #[serde(skip)]
+    pub memory: Vec<u8>,
+}
+
+impl CoreDump {
+    fn capture(method: &str, err: &ExecError, ctx: &HostContext) -> anyhow::Result<Self> {
+        let memory = ctx.memory();
+        Ok(Self {
+            method: method.to_string(),
+            trap_kind: err.trap_kind(),
+            message: err.to_string(),
+            backtrace: err.backtrace().to_vec(),
+            globals: ctx.exported_globals(),
+            heap_base: ctx.heap_base(),
+            allocator: ctx.allocator_stats(),
+            allocations: ctx.outstanding_allocations(),
+            pending_host_call: ctx.pending_host_call(),
+            memory: memory.read(0, memory.len())?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut file = File::create(path)?;
+        serde_json::to_writer(&mut file, self)?;
+        file.write_all(b"\n")?;
+        file.write_all(&self.memory)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let mut dump: Self = serde_json::from_str(&header)?;
+        reader.read_to_end(&mut dump.memory)?;
+        Ok(dump)
+    }
+}
+
+/// Writes a `core-<method>.dump` whenever a call traps.
+pub fn on_trap(builder: ExecutorBuilder) -> ExecutorBuilder {
+    builder.on_after_call(|method, outcome, ctx| {
+        let err = match outcome {
+            Err(err) if err.is_trap() => err,
+            _ => return,
+        };
+        let path = format!("core-{}.dump", method);
+        match CoreDump::capture(method, err, ctx).and_then(|dump| dump.save(path.as_ref())) {
+            Ok(()) => eprintln!("core dumped to {}", path),
+            Err(err) => log::warn!("failed to write the core dump: {:#}", err),
+        }
+    })
+}
+
+/// Pretty-prints a dump written by [`on_trap`].
+pub fn analyze(path: &Path) -> anyhow::Result<()> {
+    let dump = CoreDump::load(path)?;
+    println!("Method: {}", dump.method);
+    if let Some(kind) = dump.trap_kind {
+        println!("Trap kind: {}", kind);
+    }
+    println!("Message: {}", dump.message);
+    if let Some(ref function) = dump.pending_host_call {
+        println!("Pending host call: {}", function);
+    }
+    println!("Backtrace:");
+    for (idx, frame) in dump.backtrace.iter().enumerate() {
+        let name = frame.name.as_deref().unwrap_or("<unknown>");
+        print!("  {}: {} (func {})", idx, name, frame.func_index);
+        match frame.location {
+            Some(ref location) => println!(" at {}", location),
+            None => println!(),
+        }
+    }
+    println!("Globals:");
+    for (name, val) in &dump.globals {
+        println!("  {} = {:?}", name, val);
+    }
+    println!("Heap base: {:#x}", dump.heap_base);
+    println!("Allocator: {}", dump.allocator);
+    println!("Live allocations:");
+    for allocation in &dump.allocations {
+        println!("  {}", allocation);
+    }
+    let used = dump
+        .memory
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |end| end + 1);
+    println!(
+        "Memory: {} bytes ({} pages), last non-zero byte at {:#x}",
+        dump.memory.len(),
+        dump.memory.len() / 65536,
+        used.saturating_sub(1)
+    );
+    if let Some(heap) = dump
+        .memory
+        .get(dump.heap_base as usize..used.max(dump.heap_base as usize))
+    {
+        let preview = &heap[..heap.len().min(64)];
+        println!("Heap start: {}", to_hex(preview));
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Reporting what is known when the harness itself crashes, which a wasm trap handler can't
+//! catch.
+//!
+//! This is not a minidump. `crash-handler` and `minidumper` need a far newer toolchain and
+//! libc than we pin, so the report is text instead: the fault, the call being performed, the
+//! native backtrace and the memory map. It goes to stderr, and to `crash-<pid>.txt` with
+//! `--crash-dump`.
+//!
+//! The handler may only do what is async signal safe, so the call context is formatted by the
+//! call hooks ahead of time, and the handler merely `write(2)`s it. The backtrace is left as
+//! raw instruction pointers, to symbolize against the memory map the file ends with.
+
+use repro::{ExecutorBuilder, HostContext};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::io::Write;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::{io, mem, ptr};
+
+/// Size of the stack the handler runs on, so that it works on a native stack overflow too.
+const ALT_STACK_SIZE: usize = 64 * 1024;
+/// How many native frames the report shows.
+const MAX_FRAMES: usize = 64;
+
+const SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGBUS];
+
+/// Where to write the report as well, a leaked C string, or null.
+static FILE_PATH: AtomicPtr<c_char> = AtomicPtr::new(ptr::null_mut());
+/// The call context, formatted ahead of time, or null.
+static CONTEXT: AtomicPtr<Vec<u8>> = AtomicPtr::new(ptr::null_mut());
+/// Where the linear memory of the call being performed lives, to locate the fault in it.
+static MEMORY_BASE: AtomicUsize = AtomicUsize::new(0);
+static MEMORY_LEN: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct CallContext {
+    method: String,
+    last_host_call: Option<String>,
+}
+
+thread_local! {
+    static CALL: RefCell<CallContext> = RefCell::new(CallContext::default());
+}
+
+/// Gives the calling thread a stack for the handler to run on, which a native stack overflow
+/// leaves none of. The stack is per thread, so every thread performing calls needs one.
+pub fn install_alt_stack() -> io::Result<()> {
+    let stack = Box::leak(vec![0u8; ALT_STACK_SIZE].into_boxed_slice());
+    let alt_stack = libc::stack_t {
+        ss_sp: stack.as_mut_ptr() as *mut libc::c_void,
+        ss_flags: 0,
+        ss_size: ALT_STACK_SIZE,
+    };
+    if unsafe { libc::sigaltstack(&alt_stack, ptr::null_mut()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Installs the `SIGSEGV` and `SIGBUS` handler printing the report, and its stack on the
+/// calling thread.
+///
+/// Must run before the first instantiation: wasmtime installs its own handler then, and
+/// forwards the faults outside of wasm code to the handler it found.
+pub fn install() -> io::Result<()> {
+    install_alt_stack()?;
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_fault as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        for &signum in &SIGNALS {
+            if libc::sigaction(signum, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the report to `crash-<pid>.txt` as well.
+pub fn write_file(enabled: bool) {
+    let path = if enabled {
+        let path = format!("crash-{}.txt", std::process::id());
+        CString::new(path).expect("no nul in the path").into_raw()
+    } else {
+        ptr::null_mut()
+    };
+    let old = FILE_PATH.swap(path, Ordering::SeqCst);
+    if !old.is_null() {
+        drop(unsafe { CString::from_raw(old) });
+    }
+}
+
+/// Keeps track of the call being performed, for the report.
+pub fn track(builder: ExecutorBuilder) -> ExecutorBuilder {
+    builder
+        .on_before_call(|method, _, ctx| {
+            CALL.with(|call| {
+                *call.borrow_mut() = CallContext {
+                    method: method.to_string(),
+                    last_host_call: None,
+                };
+                publish(&call.borrow(), ctx);
+            })
+        })
+        .on_host_call(|ctx, _, _| {
+            CALL.with(|call| {
+                call.borrow_mut().last_host_call = Some(ctx.name().to_string());
+                publish(&call.borrow(), ctx);
+            })
+        })
+}
+
+/// Formats the context for the handler, which can't.
+fn publish(call: &CallContext, ctx: &HostContext) {
+    let mut context = Vec::new();
+    let _ = writeln!(context, "method: {}", call.method);
+    if let Some(ref function) = call.last_host_call {
+        let _ = writeln!(context, "last returned host call: {}", function);
+    }
+    let _ = writeln!(
+        context,
+        "host calls since instantiation: {}",
+        ctx.host_calls()
+    );
+    let memory = ctx.memory();
+    MEMORY_BASE.store(memory.base(), Ordering::SeqCst);
+    MEMORY_LEN.store(memory.len() as usize, Ordering::SeqCst);
+    let old = CONTEXT.swap(Box::into_raw(Box::new(context)), Ordering::SeqCst);
+    if !old.is_null() {
+        drop(unsafe { Box::from_raw(old) });
+    }
+}
+
+extern "C" fn on_fault(signum: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let address = unsafe { (*info).si_addr() } as usize;
+    let signal = match signum {
+        libc::SIGBUS => "SIGBUS",
+        _ => "SIGSEGV",
+    };
+    // Taken rather than borrowed, so that a hook running on another thread can't free it.
+    let context = CONTEXT.swap(ptr::null_mut(), Ordering::SeqCst);
+    let context = unsafe { context.as_ref() }.map_or(&[][..], |context| &context[..]);
+    let mut frames = [0usize; MAX_FRAMES];
+    let mut len = 0;
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            frames[len] = frame.ip() as usize;
+            len += 1;
+            len < MAX_FRAMES
+        });
+    }
+    let report = Report {
+        signal,
+        address,
+        context,
+        frames: &frames[..len],
+    };
+    report.write(libc::STDERR_FILENO);
+    let path = FILE_PATH.load(Ordering::SeqCst);
+    if !path.is_null() {
+        let fd = unsafe { libc::open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC, 0o644) };
+        if fd >= 0 {
+            report.write(fd);
+            copy_memory_map(fd);
+            unsafe { libc::close(fd) };
+            let path = unsafe { CStr::from_ptr(path) };
+            write_all(libc::STDERR_FILENO, b"crash report written to ");
+            write_all(libc::STDERR_FILENO, path.to_bytes());
+            write_all(libc::STDERR_FILENO, b"\n");
+        } else {
+            write_all(libc::STDERR_FILENO, b"failed to write the crash report\n");
+        }
+    }
+    crate::crash_report::on_signal(signal, address);
+    // Returning re-executes the faulting instruction, which now kills the process.
+    unsafe {
+        libc::signal(signum, libc::SIG_DFL);
+    }
+}
+
+struct Report<'a> {
+    signal: &'a str,
+    address: usize,
+    context: &'a [u8],
+    frames: &'a [usize],
+}
+
+impl Report<'_> {
+    fn write(&self, fd: libc::c_int) {
+        let mut buf = [0; 18];
+        write_all(fd, self.signal.as_bytes());
+        write_all(fd, b" at address ");
+        write_all(fd, hex(self.address, &mut buf));
+        write_all(fd, b"\n");
+        let base = MEMORY_BASE.load(Ordering::SeqCst);
+        let len = MEMORY_LEN.load(Ordering::SeqCst);
+        if let Some(offset) = self
+            .address
+            .checked_sub(base)
+            .filter(|&offset| offset < len)
+        {
+            write_all(fd, b"linear memory offset ");
+            write_all(fd, hex(offset, &mut buf));
+            write_all(fd, b"\n");
+        }
+        write_all(fd, self.context);
+        write_all(fd, b"native backtrace:\n");
+        for &ip in self.frames {
+            write_all(fd, b"  ");
+            write_all(fd, hex(ip, &mut buf));
+            write_all(fd, b"\n");
+        }
+    }
+}
+
+/// Appends `/proc/self/maps`, which the instruction pointers are symbolized against.
+fn copy_memory_map(fd: libc::c_int) {
+    let path = b"/proc/self/maps\0".as_ptr() as *const c_char;
+    let maps = unsafe { libc::open(path, libc::O_RDONLY) };
+    if maps < 0 {
+        return;
+    }
+    write_all(fd, b"memory map:\n");
+    let mut buf = [0u8; 4096];
+    loop {
+        let read = unsafe { libc::read(maps, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if read <= 0 {
+            break;
+        }
+        write_all(fd, &buf[..read as usize]);
+    }
+    unsafe { libc::close(maps) };
+}
+
+/// `write(2)`s all of `bytes`, giving up on the first error: there is no one to report it to.
+pub(crate) fn write_all(fd: libc::c_int, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        let written =
+            unsafe { libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+        if written <= 0 {
+            return;
+        }
+        bytes = &bytes[written as usize..];
+    }
+}
+
+/// Formats `value` as `0x…` into `buf`, without allocating.
+fn hex(mut value: usize, buf: &mut [u8; 18]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b"0123456789abcdef"[value & 0xf];
+        value >>= 4;
+        if value == 0 {
+            break;
+        }
+    }
+    start -= 2;
+    buf[start..start + 2].copy_from_slice(b"0x");
+    &buf[start..]
+}
+
+/// Formats `value` in decimal into `buf`, without allocating.
+pub(crate) fn decimal(mut value: usize, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    &buf[start..]
+}
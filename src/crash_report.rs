@@ -0,0 +1,294 @@
+//! `crash-report.json`, everything needed to file a call that traps, crashes or hangs upstream.
+
+use crate::config::WasmtimeFlags;
+use crate::report::to_hex;
+use crate::settings::Settings;
+use repro::{ExecError, ExecutorBuilder, Frame, HostCallRecord, HostCallTrace, TrapKind};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+const PATH: &str = "crash-report.json";
+#[cfg(target_os = "linux")]
+const PATH_C: &[u8] = b"crash-report.json\0";
+/// The wasmtime revision we pin, see `Cargo.toml`.
+const WASMTIME_REV: &str = "83ff015";
+/// How many of the last host calls the report includes.
+const HOST_CALL_TAIL: usize = 32;
+
+/// How wasmtime lays out the linear memory. The pinned revision hardcodes its tunables, so
+/// these can't be toggled, only recorded: every memory is static on 64-bit hosts.
+#[derive(Clone, Serialize)]
+struct MemoryConfig {
+    static_memory_maximum_size: u64,
+    static_memory_guard_size: u64,
+    dynamic_memory_guard_size: u64,
+}
+
+#[cfg(target_pointer_width = "64")]
+const MEMORY_CONFIG: MemoryConfig = MemoryConfig {
+    static_memory_maximum_size: 4 << 30,
+    static_memory_guard_size: 2 << 30,
+    dynamic_memory_guard_size: 64 << 10,
+};
+
+#[cfg(target_pointer_width = "32")]
+const MEMORY_CONFIG: MemoryConfig = MemoryConfig {
+    static_memory_maximum_size: 1 << 30,
+    static_memory_guard_size: 64 << 10,
+    dynamic_memory_guard_size: 0,
+};
+
+#[derive(Clone, Serialize)]
+struct Environment {
+    repro: &'static str,
+    wasmtime: &'static str,
+    cranelift: WasmtimeFlags,
+    memory: MemoryConfig,
+    os: &'static str,
+    arch: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+struct Module {
+    path: PathBuf,
+    size: usize,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Failure {
+    Trap {
+        message: String,
+        kind: Option<TrapKind>,
+        panic: Option<String>,
+        backtrace: Vec<Frame>,
+    },
+    #[cfg(target_os = "linux")]
+    Signal {
+        signal: &'static str,
+        address: usize,
+    },
+    Timeout {
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct CrashReport {
+    environment: Environment,
+    module: Module,
+    method: String,
+    /// Hex encoded input data.
+    input: String,
+    host_calls: Vec<HostCallRecord>,
+    failure: Failure,
+}
+
+/// What is known before the call.
+#[derive(Clone)]
+struct Pending {
+    environment: Environment,
+    module: Module,
+    input: String,
+    method: Option<String>,
+    trace: HostCallTrace,
+}
+
+impl Pending {
+    fn report(&self, failure: Failure) -> CrashReport {
+        CrashReport {
+            environment: self.environment.clone(),
+            module: self.module.clone(),
+            method: self.method.clone().unwrap_or_default(),
+            input: self.input.clone(),
+            host_calls: self.trace.last(HOST_CALL_TAIL),
+            failure,
+        }
+    }
+
+    fn write(&self, failure: Failure) -> anyhow::Result<()> {
+        fs::write(PATH, serde_json::to_vec_pretty(&self.report(failure))?)?;
+        eprintln!("crash report written to {}", PATH);
+        Ok(())
+    }
+
+    /// Renders the report of a fatal signal for the handler, which can't serialize.
+    #[cfg(target_os = "linux")]
+    fn prerender(&self) {
+        let placeholder = Failure::Signal {
+            signal: "",
+            address: 0,
+        };
+        let report = serde_json::to_vec_pretty(&self.report(placeholder))
+            .ok()
+            .and_then(SignalReport::split);
+        let report = match report {
+            Some(report) => Box::into_raw(Box::new(report)),
+            None => {
+                log::warn!("failed to render the crash report");
+                return;
+            }
+        };
+        let old = SIGNAL_REPORT.swap(report, Ordering::SeqCst);
+        if !old.is_null() {
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+}
+
+/// The report of a fatal signal, but for the signal and the address which go in between.
+#[cfg(target_os = "linux")]
+struct SignalReport {
+    head: Vec<u8>,
+    middle: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+#[cfg(target_os = "linux")]
+impl SignalReport {
+    /// Splits a report rendered with an empty signal and a zero address around them.
+    fn split(report: Vec<u8>) -> Option<Self> {
+        const SIGNAL: &[u8] = b"\"signal\": \"";
+        const ADDRESS: &[u8] = b"\"address\": ";
+        let find = |needle: &[u8], from: usize| {
+            report[from..]
+                .windows(needle.len())
+                .position(|window| window == needle)
+                .map(|at| from + at + needle.len())
+        };
+        let signal = find(SIGNAL, 0)?;
+        let address = find(ADDRESS, signal)?;
+        Some(Self {
+            head: report[..signal].to_vec(),
+            middle: report[signal..address].to_vec(),
+            // Past the zero.
+            tail: report[address + 1..].to_vec(),
+        })
+    }
+}
+
+lazy_static::lazy_static! {
+    // Global rather than thread local, the watchdog writes the report of a stuck call.
+    static ref PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+}
+
+/// What the signal handler writes, or null if the report is disabled.
+#[cfg(target_os = "linux")]
+static SIGNAL_REPORT: AtomicPtr<SignalReport> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Writes `crash-report.json` when a call traps or times out, or the harness receives a fatal
+/// signal.
+pub fn enable(builder: ExecutorBuilder, settings: &Settings) -> anyhow::Result<ExecutorBuilder> {
+    let code = repro::read_wasm(&settings.wasm)?;
+    let trace = HostCallTrace::new();
+    let pending = Pending {
+        environment: Environment {
+            repro: env!("CARGO_PKG_VERSION"),
+            wasmtime: WASMTIME_REV,
+            cranelift: settings.wasmtime.clone(),
+            memory: MEMORY_CONFIG,
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        },
+        module: Module {
+            path: settings.wasm.clone(),
+            size: code.len(),
+            sha256: hex::encode(Sha256::digest(&code)),
+        },
+        input: to_hex(&settings.input),
+        method: None,
+        trace: trace.clone(),
+    };
+    #[cfg(target_os = "linux")]
+    pending.prerender();
+    *PENDING.lock().unwrap() = Some(pending);
+    let builder = builder
+        .trace_host_calls(trace)
+        .on_before_call(|method, _, _| {
+            if let Some(ref mut pending) = *PENDING.lock().unwrap() {
+                pending.method = Some(method.to_string());
+                #[cfg(target_os = "linux")]
+                pending.prerender();
+            }
+        })
+        .on_after_call(|_, outcome, _| {
+            if let Err(err) = outcome {
+                if err.is_trap() || matches!(err, ExecError::Timeout { .. }) {
+                    on_failure(err);
+                }
+            }
+        });
+    // Rendered again after every host call, for the tail of the trace to be up to date.
+    #[cfg(target_os = "linux")]
+    let builder = builder
+        .on_host_call(|_, _, _| prerender())
+        .on_host_call_failed(|_, _, _| prerender());
+    Ok(builder)
+}
+
+#[cfg(target_os = "linux")]
+fn prerender() {
+    if let Some(ref pending) = *PENDING.lock().unwrap() {
+        pending.prerender();
+    }
+}
+
+/// Writes the report of a call that failed, if the report is enabled.
+pub fn on_failure(err: &ExecError) {
+    let pending = PENDING.lock().unwrap().clone();
+    if let Some(pending) = pending {
+        if let Err(err) = pending.write(failure(err)) {
+            log::warn!("failed to write the crash report: {:#}", err);
+        }
+    }
+}
+
+fn failure(err: &ExecError) -> Failure {
+    match err {
+        ExecError::Timeout { .. } => Failure::Timeout {
+            message: err.to_string(),
+        },
+        _ => Failure::Trap {
+            message: err.to_string(),
+            kind: err.trap_kind(),
+            panic: err.panic_message().map(str::to_string),
+            backtrace: err.backtrace().to_vec(),
+        },
+    }
+}
+
+/// Writes the report from the fatal signal handler, if the report is enabled. Only writes what
+/// was rendered ahead of time, which is async signal safe.
+#[cfg(target_os = "linux")]
+pub fn on_signal(signal: &str, address: usize) {
+    use crate::crash::{decimal, write_all};
+
+    let report = SIGNAL_REPORT.swap(std::ptr::null_mut(), Ordering::SeqCst);
+    let report = match unsafe { report.as_ref() } {
+        Some(report) => report,
+        None => return,
+    };
+    let path = PATH_C.as_ptr() as *const libc::c_char;
+    let fd = unsafe { libc::open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC, 0o644) };
+    if fd < 0 {
+        write_all(libc::STDERR_FILENO, b"failed to write the crash report\n");
+        return;
+    }
+    let mut buf = [0; 20];
+    write_all(fd, &report.head);
+    write_all(fd, signal.as_bytes());
+    write_all(fd, &report.middle);
+    write_all(fd, decimal(address, &mut buf));
+    write_all(fd, &report.tail);
+    unsafe { libc::close(fd) };
+    write_all(
+        libc::STDERR_FILENO,
+        b"crash report written to crash-report.json\n",
+    );
+}
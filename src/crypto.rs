@@ -0,0 +1,77 @@
+//! The signature verification `ext_crypto_*` host functions.
+
+use crate::{ExecError, HostContext, HostRegistry};
+use parity_scale_codec::Encode;
+use sp_core::{ed25519, sr25519, Pair};
+
+/// Mirrors `sp_io::EcdsaVerifyError`, which is what the runtime decodes.
+#[derive(Encode)]
+enum EcdsaVerifyError {
+    BadRS,
+    BadV,
+    BadSignature,
+}
+
+fn read_sig(ctx: &HostContext, ptr: u32) -> Result<[u8; 64], ExecError> {
+    let mut sig = [0; 64];
+    sig.copy_from_slice(&ctx.read_memory(ptr, 64)?);
+    Ok(sig)
+}
+
+fn read_32(ctx: &HostContext, ptr: u32) -> Result<[u8; 32], ExecError> {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(&ctx.read_memory(ptr, 32)?);
+    Ok(bytes)
+}
+
+fn ecdsa_recover(sig: &[u8], msg: &[u8; 32]) -> Result<[u8; 64], EcdsaVerifyError> {
+    let rs = secp256k1::Signature::parse_slice(&sig[0..64]).map_err(|_| EcdsaVerifyError::BadRS)?;
+    let v = secp256k1::RecoveryId::parse(if sig[64] > 26 { sig[64] - 27 } else { sig[64] })
+        .map_err(|_| EcdsaVerifyError::BadV)?;
+    let pubkey = secp256k1::recover(&secp256k1::Message::parse(msg), &rs, &v)
+        .map_err(|_| EcdsaVerifyError::BadSignature)?;
+    let mut res = [0; 64];
+    res.copy_from_slice(&pubkey.serialize()[1..65]);
+    Ok(res)
+}
+
+host_fn! {
+    fn ext_crypto_ed25519_verify_version_1(ctx, sig: u32, msg: u64, pub_key: u32) -> u32 {
+        let sig = ed25519::Signature::from_raw(read_sig(ctx, sig)?);
+        let pub_key = ed25519::Public::from_raw(read_32(ctx, pub_key)?);
+        let msg = ctx.read_packed(msg)?;
+        Ok(ed25519::Pair::verify(&sig, &msg, &pub_key) as u32)
+    }
+}
+
+host_fn! {
+    fn ext_crypto_sr25519_verify_version_1(ctx, sig: u32, msg: u64, pub_key: u32) -> u32 {
+        let sig = sr25519::Signature::from_raw(read_sig(ctx, sig)?);
+        let pub_key = sr25519::Public::from_raw(read_32(ctx, pub_key)?);
+        let msg = ctx.read_packed(msg)?;
+        Ok(sr25519::Pair::verify(&sig, &msg, &pub_key) as u32)
+    }
+}
+
+host_fn! {
+    fn ext_crypto_secp256k1_ecdsa_recover_version_1(ctx, sig: u32, msg: u32) -> u64 {
+        let sig = ctx.read_memory(sig, 65)?;
+        let msg = read_32(ctx, msg)?;
+        ctx.allocate_packed(&ecdsa_recover(&sig, &msg).encode())
+    }
+}
+
+pub(crate) fn register(registry: &mut HostRegistry) {
+    registry.register_fn(
+        "ext_crypto_ed25519_verify_version_1",
+        ext_crypto_ed25519_verify_version_1,
+    );
+    registry.register_fn(
+        "ext_crypto_sr25519_verify_version_1",
+        ext_crypto_sr25519_verify_version_1,
+    );
+    registry.register_fn(
+        "ext_crypto_secp256k1_ecdsa_recover_version_1",
+        ext_crypto_secp256k1_ecdsa_recover_version_1,
+    );
+}
@@ -0,0 +1,179 @@
+use crate::report::to_hex;
+use anyhow::{anyhow, Context};
+use repro::{
+    Allocation, AllocatorStats, Backend, ExecError, ExecutorBuilder, HostCallRecord, HostCallTrace,
+};
+use std::path::Path;
+
+/// What a call does under one backend.
+struct Outcome {
+    backend: Backend,
+    result: Result<Vec<u8>, ExecError>,
+    host_calls: Vec<HostCallRecord>,
+    stats: AllocatorStats,
+    outstanding: Vec<Allocation>,
+}
+
+impl Outcome {
+    fn new(
+        builder: &ExecutorBuilder,
+        backend: Backend,
+        wasm: &Path,
+        method_name: &str,
+        input_data: &[u8],
+    ) -> anyhow::Result<Self> {
+        let trace = HostCallTrace::new();
+        let runtime = builder
+            .clone()
+            .backend(backend)
+            .trace_host_calls(trace.clone())
+            .build_from_file(wasm)
+            .and_then(|executor| executor.instantiate())
+            .with_context(|| format!("can't instantiate the runtime under {}", backend))?;
+        let result = runtime.call(method_name, input_data);
+        Ok(Self {
+            backend,
+            result,
+            host_calls: trace.records(),
+            stats: runtime.allocator_stats(),
+            outstanding: runtime.outstanding_allocations(),
+        })
+    }
+
+    fn describe_result(&self) -> String {
+        match self.result {
+            Ok(ref output) => format!("returns {}", to_hex(output)),
+            Err(ref err) => format!("fails: {}", err),
+        }
+    }
+}
+
+fn describe_call(record: &HostCallRecord) -> String {
+    format!(
+        "{}{:?} -> {:?}",
+        record.function, record.params, record.results
+    )
+}
+
+/// The timestamps differ from run to run, the rest of the record must not.
+fn same_call(a: &HostCallRecord, b: &HostCallRecord) -> bool {
+    a.function == b.function
+        && a.params == b.params
+        && a.decoded == b.decoded
+        && a.results == b.results
+}
+
+fn same_result(a: &Result<Vec<u8>, ExecError>, b: &Result<Vec<u8>, ExecError>) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => a == b,
+        (Err(a), Err(b)) => a.exit_code() == b.exit_code() && a.trap_kind() == b.trap_kind(),
+        _ => false,
+    }
+}
+
+/// The first difference between the outcomes, in the order the call produces them: the host
+/// calls, the return value and then what is left in the allocator.
+fn first_divergence(a: &Outcome, b: &Outcome) -> Option<String> {
+    let calls = a.host_calls.len().max(b.host_calls.len());
+    for idx in 0..calls {
+        match (a.host_calls.get(idx), b.host_calls.get(idx)) {
+            (Some(call_a), Some(call_b)) if same_call(call_a, call_b) => continue,
+            (call_a, call_b) => {
+                let describe = |call: Option<&HostCallRecord>| {
+                    call.map_or("makes no more host calls".to_string(), describe_call)
+                };
+                return Some(format!(
+                    "host call #{}:\n  {}: {}\n  {}: {}",
+                    idx,
+                    a.backend,
+                    describe(call_a),
+                    b.backend,
+                    describe(call_b)
+                ));
+            }
+        }
+    }
+    if !same_result(&a.result, &b.result) {
+        return Some(format!(
+            "the result:\n  {}: {}\n  {}: {}",
+            a.backend,
+            a.describe_result(),
+            b.backend,
+            b.describe_result()
+        ));
+    }
+    if a.stats != b.stats {
+        return Some(format!(
+            "the allocator stats:\n  {}: {}\n  {}: {}",
+            a.backend, a.stats, b.backend, b.stats
+        ));
+    }
+    if a.outstanding != b.outstanding {
+        let describe = |outstanding: &[Allocation]| {
+            outstanding
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+        return Some(format!(
+            "the outstanding allocations:\n  {}: {}\n  {}: {}",
+            a.backend,
+            describe(&a.outstanding),
+            b.backend,
+            describe(&b.outstanding)
+        ));
+    }
+    None
+}
+
+/// Performs the call under every backend and compares each of them to the first one.
+pub fn diff(
+    builder: &ExecutorBuilder,
+    backends: &[Backend],
+    wasm: &Path,
+    method_name: &str,
+    input_data: &[u8],
+) -> anyhow::Result<()> {
+    if backends.len() < 2 {
+        return Err(anyhow!("diffing needs at least two backends"));
+    }
+    let outcomes = backends
+        .iter()
+        .map(|&backend| Outcome::new(builder, backend, wasm, method_name, input_data))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for outcome in &outcomes {
+        println!(
+            "{}: {} after {} host calls, {}",
+            outcome.backend,
+            outcome.describe_result(),
+            outcome.host_calls.len(),
+            outcome.stats
+        );
+    }
+    let (reference, others) = outcomes
+        .split_first()
+        .expect("there are at least two backends");
+    let mut diverged = 0;
+    for other in others {
+        match first_divergence(reference, other) {
+            Some(divergence) => {
+                diverged += 1;
+                println!(
+                    "{} and {} first diverge in {}",
+                    reference.backend, other.backend, divergence
+                );
+            }
+            None => println!("{} and {} agree", reference.backend, other.backend),
+        }
+    }
+    match diverged {
+        0 => Ok(()),
+        diverged => Err(anyhow!(
+            "{} of {} backends diverge from {}",
+            diverged,
+            others.len(),
+            reference.backend
+        )),
+    }
+}
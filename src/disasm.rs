@@ -0,0 +1,98 @@
+//! Wasm text of a single function, for looking at the function a backtrace points at.
+
+use anyhow::anyhow;
+use parity_wasm::elements::{ImportCountType, Instruction, Internal, Module, Type};
+use repro::Symbolizer;
+
+/// The index of the function `func` refers to: an index itself, the name of an export or the
+/// demangled name from the name section.
+fn resolve(module: &Module, symbolizer: &Symbolizer, func: &str, funcs: u32) -> Option<u32> {
+    if let Ok(func_index) = func.parse() {
+        return Some(func_index);
+    }
+    let exported = module.export_section().and_then(|exports| {
+        exports
+            .entries()
+            .iter()
+            .find_map(|export| match export.internal() {
+                Internal::Function(func_index) if export.field() == func => Some(*func_index),
+                _ => None,
+            })
+    });
+    exported.or_else(|| (0..funcs).find(|&func_index| symbolizer.name(func_index) == Some(func)))
+}
+
+/// Prints the function `func`, its instructions indented by the blocks they are in.
+pub fn disasm(code: &[u8], func: &str) -> anyhow::Result<()> {
+    let module: Module = parity_wasm::deserialize_buffer(code).map_err(|err| anyhow!("{}", err))?;
+    let symbolizer = Symbolizer::new(code);
+    let imported_funcs = module.import_count(ImportCountType::Function);
+    let bodies = module.code_section().map_or(&[][..], |code| code.bodies());
+    let funcs = (imported_funcs + bodies.len()) as u32;
+    let func_index = resolve(&module, &symbolizer, func, funcs)
+        .ok_or_else(|| anyhow!("no function is exported or named `{}`", func))?;
+    let defined = (func_index as usize)
+        .checked_sub(imported_funcs)
+        .ok_or_else(|| anyhow!("function #{} is imported, it has no code", func_index))?;
+    let body = bodies
+        .get(defined)
+        .ok_or_else(|| anyhow!("there is no function #{}, only {}", func_index, funcs))?;
+
+    let type_ref = module
+        .function_section()
+        .and_then(|funcs| funcs.entries().get(defined))
+        .map(|func| func.type_ref())
+        .ok_or_else(|| anyhow!("function #{} has no type", func_index))?;
+    let Type::Function(func_ty) = module
+        .type_section()
+        .and_then(|types| types.types().get(type_ref as usize))
+        .ok_or_else(|| {
+            anyhow!(
+                "function #{} has type #{}, which is missing",
+                func_index,
+                type_ref
+            )
+        })?;
+
+    if let Some(name) = symbolizer.name(func_index) {
+        println!(";; {}", name);
+    }
+    if let Some(location) = symbolizer.location(func_index) {
+        println!(";; at {}", location);
+    }
+    let mut header = format!("(func ${}", func_index);
+    if !func_ty.params().is_empty() {
+        let params: Vec<_> = func_ty.params().iter().map(ToString::to_string).collect();
+        header += &format!(" (param {})", params.join(" "));
+    }
+    if let Some(ret) = func_ty.return_type() {
+        header += &format!(" (result {})", ret);
+    }
+    println!("{}", header);
+    let locals: Vec<_> = body
+        .locals()
+        .iter()
+        .flat_map(|local| (0..local.count()).map(move |_| local.value_type().to_string()))
+        .collect();
+    if !locals.is_empty() {
+        println!("  (local {})", locals.join(" "));
+    }
+    let mut depth = 1;
+    for instruction in body.code().elements() {
+        if matches!(instruction, Instruction::End | Instruction::Else) {
+            depth -= 1;
+        }
+        if depth == 0 {
+            break;
+        }
+        println!("{}{}", "  ".repeat(depth), instruction);
+        if matches!(
+            instruction,
+            Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) | Instruction::Else
+        ) {
+            depth += 1;
+        }
+    }
+    println!(")");
+    Ok(())
+}
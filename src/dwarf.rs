@@ -0,0 +1,139 @@
+//! Source locations of the functions of a module, taken from its DWARF custom sections.
+
+use anyhow::anyhow;
+use gimli::{AttributeValue, EndianSlice, LittleEndian, SectionId};
+use std::collections::HashMap;
+use std::ops::Range;
+use wasmparser::{BinaryReaderError, ImportSectionEntryType, ModuleReader, SectionCode};
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+fn parse_error(err: BinaryReaderError) -> anyhow::Error {
+    anyhow!("{} at offset {:#x}", err.message, err.offset)
+}
+
+/// The sections of the module that matter for mapping DWARF to functions.
+#[derive(Default)]
+struct Sections<'a> {
+    debug: HashMap<&'a str, &'a [u8]>,
+    imported_funcs: u32,
+    /// Ranges of the function bodies, relative to the start of the code section as DWARF
+    /// addresses are.
+    bodies: Vec<Range<u64>>,
+}
+
+fn read_sections(code: &[u8]) -> anyhow::Result<Sections> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    let mut sections = Sections::default();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        match section.code {
+            SectionCode::Custom { name, .. } if name.starts_with(".debug_") => {
+                let mut data = section.get_binary_reader();
+                let len = data.bytes_remaining();
+                sections
+                    .debug
+                    .insert(name, data.read_bytes(len).map_err(parse_error)?);
+            }
+            SectionCode::Import => {
+                for import in section.get_import_section_reader().map_err(parse_error)? {
+                    if let ImportSectionEntryType::Function(_) = import.map_err(parse_error)?.ty {
+                        sections.imported_funcs += 1;
+                    }
+                }
+            }
+            SectionCode::Code => {
+                let code_start = section.range().start;
+                for body in section.get_code_section_reader().map_err(parse_error)? {
+                    let reader = body.map_err(parse_error)?.get_binary_reader();
+                    let start = (reader.original_position() - code_start) as u64;
+                    sections
+                        .bodies
+                        .push(start..start + reader.bytes_remaining() as u64);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(sections)
+}
+
+/// Whether the module carries DWARF debug info.
+pub(crate) fn has_debug_info(code: &[u8]) -> bool {
+    read_sections(code)
+        .map(|sections| sections.debug.contains_key(".debug_info"))
+        .unwrap_or(false)
+}
+
+/// The `file:line` each function is declared at, by function index.
+pub(crate) fn function_locations(code: &[u8]) -> anyhow::Result<HashMap<u32, String>> {
+    let sections = read_sections(code)?;
+    let dwarf = gimli::Dwarf::load(
+        |id: SectionId| -> Result<Reader, gimli::Error> {
+            let data = sections.debug.get(id.name()).copied().unwrap_or(&[]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        },
+        |_| Ok(EndianSlice::new(&[], LittleEndian)),
+    )?;
+
+    let mut locations = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            let low_pc = match entry.attr_value(gimli::DW_AT_low_pc)? {
+                Some(AttributeValue::Addr(low_pc)) => low_pc,
+                _ => continue,
+            };
+            let body = match sections
+                .bodies
+                .iter()
+                .position(|body| body.contains(&low_pc))
+            {
+                Some(body) => body as u32,
+                None => continue,
+            };
+            let line = entry
+                .attr_value(gimli::DW_AT_decl_line)?
+                .and_then(|line| line.udata_value())
+                .unwrap_or(0);
+            let file = match entry.attr_value(gimli::DW_AT_decl_file)? {
+                Some(AttributeValue::FileIndex(file)) => file_name(&dwarf, &unit, file)?,
+                _ => None,
+            };
+            let file = file.unwrap_or_else(|| "<unknown>".to_string());
+            locations.insert(sections.imported_funcs + body, format!("{}:{}", file, line));
+        }
+    }
+    Ok(locations)
+}
+
+fn file_name(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    index: u64,
+) -> Result<Option<String>, gimli::Error> {
+    let header = match unit.line_program {
+        Some(ref program) => program.header(),
+        None => return Ok(None),
+    };
+    let file = match header.file(index) {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+    let mut path = dwarf
+        .attr_string(unit, file.path_name())?
+        .to_string_lossy()
+        .into_owned();
+    if let Some(dir) = file.directory(header) {
+        let dir = dwarf.attr_string(unit, dir)?.to_string_lossy().into_owned();
+        if !path.starts_with('/') && !dir.is_empty() {
+            path = format!("{}/{}", dir, path);
+        }
+    }
+    Ok(Some(path))
+}
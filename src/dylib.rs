@@ -0,0 +1,44 @@
+//! Loading of shared libraries, for plugins and native builds of the runtime.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_void;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// A `dlopen`ed library, closed once dropped.
+pub(crate) struct Library(*mut c_void);
+
+impl Library {
+    pub(crate) fn open(path: &Path) -> Result<Self, String> {
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(|err| err.to_string())?;
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(dlerror());
+        }
+        Ok(Library(handle))
+    }
+
+    pub(crate) fn symbol(&self, name: &CStr) -> Result<*mut c_void, String> {
+        let symbol = unsafe { libc::dlsym(self.0, name.as_ptr()) };
+        if symbol.is_null() {
+            return Err(dlerror());
+        }
+        Ok(symbol)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.0) };
+    }
+}
+
+fn dlerror() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        return "unknown dlopen error".to_string();
+    }
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,388 @@
+use crate::preflight::Violation;
+use crate::symbolizer::Symbolizer;
+use crate::Backend;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use wasmtime::{FrameInfo, Trap, ValType};
+
+/// A wasm frame of a trap backtrace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub module: Option<String>,
+    pub func_index: u32,
+    /// The name of the function, from the name section of the module.
+    pub name: Option<String>,
+    /// Where the function is declared, as `file:line`, from the DWARF sections of the module.
+    pub location: Option<String>,
+}
+
+impl From<&FrameInfo> for Frame {
+    fn from(frame: &FrameInfo) -> Self {
+        Self {
+            module: frame.module_name().map(str::to_string),
+            func_index: frame.func_index(),
+            name: None,
+            location: None,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{} (func {})", name, self.func_index)?,
+            None => write!(f, "func {}", self.func_index)?,
+        }
+        match self.location {
+            Some(ref location) => write!(f, " at {}", location),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What caused a trap, as far as the trap message tells.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    UnreachableExecuted,
+    MemoryOutOfBounds,
+    TableOutOfBounds,
+    StackOverflow,
+    IndirectCallTypeMismatch,
+    IntegerOverflow,
+    IntegerDivisionByZero,
+    BadConversionToInteger,
+    Interrupt,
+    /// The metered call used up its fuel.
+    OutOfFuel,
+    /// A host function failed, see the error for why.
+    HostFunctionError,
+    HostPanic,
+    Unknown,
+}
+
+impl TrapKind {
+    /// The pinned wasmtime doesn't expose trap codes, so this goes by the messages of
+    /// cranelift's `TrapCode`.
+    fn from_message(message: &str) -> Self {
+        const KINDS: &[(&str, TrapKind)] = &[
+            ("unreachable", TrapKind::UnreachableExecuted),
+            ("out of bounds memory access", TrapKind::MemoryOutOfBounds),
+            ("out of bounds table access", TrapKind::TableOutOfBounds),
+            ("call stack exhausted", TrapKind::StackOverflow),
+            (
+                "indirect call type mismatch",
+                TrapKind::IndirectCallTypeMismatch,
+            ),
+            ("integer overflow", TrapKind::IntegerOverflow),
+            ("integer divide by zero", TrapKind::IntegerDivisionByZero),
+            (
+                "invalid conversion to integer",
+                TrapKind::BadConversionToInteger,
+            ),
+            ("interrupt", TrapKind::Interrupt),
+        ];
+        let first_line = message.lines().next().unwrap_or_default();
+        KINDS
+            .iter()
+            .find(|(pattern, _)| first_line.contains(pattern))
+            .map_or(TrapKind::Unknown, |&(_, kind)| kind)
+    }
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Everything that can go wrong while loading a runtime or calling into it.
+#[derive(Debug, Error)]
+pub enum ExecError {
+    #[error("failed to read {}: {}", .path.display(), .source)]
+    ReadWasm { path: PathBuf, source: io::Error },
+    #[error("can't assemble {}: {}", .path.display(), .message)]
+    ParseWat { path: PathBuf, message: String },
+    #[error("compilation failed: {0:#}")]
+    Compilation(anyhow::Error),
+    #[error("can't provide import `{module}::{name}`, only function imports are supported")]
+    ImportResolution { module: String, name: String },
+    #[error("no host function is registered for import `{module}::{name}`")]
+    UnknownImport { module: String, name: String },
+    #[error("instantiation failed: {0:#}")]
+    Instantiation(anyhow::Error),
+    #[error("`memory` is not exported")]
+    MissingMemory,
+    #[error("`{0}` is not exported as a function")]
+    MissingExport(String),
+    #[error("the runtime doesn't fit the harness:{}", list_violations(.0))]
+    Preflight(Vec<Violation>),
+    #[error("`{method}` takes ({expected}), not ({actual})")]
+    SignatureMismatch {
+        method: String,
+        expected: String,
+        actual: String,
+    },
+    #[error(
+        "{}{message}",
+        .panic.as_ref().map_or(String::new(), |panic| format!("the runtime {}\n", panic))
+    )]
+    Trap {
+        message: String,
+        backtrace: Vec<Frame>,
+        /// The panic message and location the runtime logged before trapping, if any.
+        panic: Option<String>,
+    },
+    #[error(
+        "wasm call stack exhausted {} frames deep{}",
+        .backtrace.len(),
+        .stack_size.map_or(String::new(), |size| format!(", on a {} byte stack", size))
+    )]
+    StackOverflow {
+        /// The native stack size of the calling thread, which the wasm frames live on.
+        stack_size: Option<usize>,
+        backtrace: Vec<Frame>,
+    },
+    #[error("the call timed out after {timeout:?}")]
+    Timeout { timeout: Duration },
+    #[error("the call ran out of fuel, all {limit} units of it were consumed")]
+    OutOfFuel { limit: u64, backtrace: Vec<Frame> },
+    /// A host function panicked, which is turned into a trap.
+    #[error("{message}")]
+    HostPanic {
+        message: String,
+        backtrace: Vec<Frame>,
+    },
+    #[error("allocator failure: {0}")]
+    AllocatorFailure(String),
+    #[error("`{function}` was passed invalid utf-8 at {ptr:#x}..+{len}")]
+    InvalidUtf8InHostCall {
+        function: String,
+        ptr: u32,
+        len: u32,
+    },
+    #[error("`{function}` accessed {ptr:#x}..+{len}, out of bounds of the linear memory")]
+    OutOfBoundsInHostCall {
+        function: String,
+        ptr: u32,
+        len: u32,
+    },
+    #[error(
+        "`{function}` is not implemented by the harness, called with ({})",
+        .params.join(", ")
+    )]
+    UnimplementedHostFunction {
+        function: String,
+        params: Vec<String>,
+    },
+    #[error(
+        "host call #{index} diverges from the replayed trace: expected {expected}, got {actual}"
+    )]
+    ReplayDivergence {
+        index: usize,
+        expected: String,
+        actual: String,
+    },
+    #[error("the script implementing `{function}` failed: {message}")]
+    Script { function: String, message: String },
+    #[error("the plugin implementing `{function}` failed with {code}")]
+    PluginCall { function: String, code: i32 },
+    #[error("`{function}` was called with params not matching its signature")]
+    HostSignatureMismatch { function: String },
+    #[error("`{function}` returns {ty:?}, which the harness has no zero value of")]
+    UnsupportedResult { function: String, ty: ValType },
+    #[error("`{function}` was passed input it can't decode: {source}")]
+    HostArgDecode {
+        function: String,
+        source: parity_scale_codec::Error,
+    },
+    #[error("invalid utf-8 at {ptr:#x}..+{len}")]
+    InvalidUtf8 { ptr: u32, len: u32 },
+    #[error("{ptr:#x}..+{len} is out of bounds of the linear memory")]
+    OutOfBounds { ptr: u32, len: u32 },
+    #[error(
+        "memory limit exceeded at {pages} pages (limit {limit}) while executing {}",
+        .method.as_ref().map_or("the start function".to_string(), |method| format!("`{}`", method))
+    )]
+    MemoryLimitExceeded {
+        limit: u32,
+        pages: u32,
+        /// The export being called, `None` during instantiation.
+        method: Option<String>,
+    },
+    #[error("can't load the plugin {}: {}", .path.display(), .message)]
+    Plugin { path: PathBuf, message: String },
+    #[error("can't decode the result of `{method}`: {source}")]
+    Decode {
+        method: String,
+        source: parity_scale_codec::Error,
+    },
+    #[error("{what} is not supported by the {backend} backend")]
+    Unsupported {
+        what: &'static str,
+        backend: Backend,
+    },
+}
+
+impl ExecError {
+    /// The process exit code the CLI reports this failure with:
+    ///
+    /// - 10: the wasm trapped, including traps raised by failing host functions,
+    /// - 11: a host function panicked,
+    /// - 12: the module couldn't be loaded or instantiated,
+    /// - 13: the export is missing,
+    /// - 14: the call timed out,
+    /// - 1: any other failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ExecError::Trap { .. }
+            | ExecError::StackOverflow { .. }
+            | ExecError::OutOfFuel { .. }
+            | ExecError::AllocatorFailure(_)
+            | ExecError::InvalidUtf8InHostCall { .. }
+            | ExecError::OutOfBoundsInHostCall { .. }
+            | ExecError::UnimplementedHostFunction { .. }
+            | ExecError::HostSignatureMismatch { .. }
+            | ExecError::UnsupportedResult { .. }
+            | ExecError::ReplayDivergence { .. }
+            | ExecError::Script { .. }
+            | ExecError::PluginCall { .. }
+            | ExecError::HostArgDecode { .. }
+            | ExecError::MemoryLimitExceeded { .. } => 10,
+            ExecError::HostPanic { .. } => 11,
+            ExecError::ReadWasm { .. }
+            | ExecError::ParseWat { .. }
+            | ExecError::Compilation(_)
+            | ExecError::ImportResolution { .. }
+            | ExecError::UnknownImport { .. }
+            | ExecError::Instantiation(_)
+            | ExecError::Plugin { .. }
+            | ExecError::MissingMemory => 12,
+            ExecError::Preflight(violations)
+                if violations
+                    .iter()
+                    .any(|violation| matches!(violation, Violation::MissingExport(_))) =>
+            {
+                13
+            }
+            ExecError::Preflight(_) => 12,
+            ExecError::MissingExport(_) => 13,
+            ExecError::Timeout { .. } => 14,
+            ExecError::OutOfBounds { .. }
+            | ExecError::InvalidUtf8 { .. }
+            | ExecError::Decode { .. }
+            | ExecError::SignatureMismatch { .. }
+            | ExecError::Unsupported { .. } => 1,
+        }
+    }
+
+    /// Whether the call trapped, in the wasm or in a host function, panics included: the
+    /// exit codes 10 and 11.
+    pub fn is_trap(&self) -> bool {
+        matches!(self.exit_code(), 10 | 11)
+    }
+
+    /// Fills in the function names of the backtrace frames.
+    pub(crate) fn symbolize(mut self, symbolizer: &Symbolizer) -> Self {
+        if let ExecError::Trap { backtrace, .. }
+        | ExecError::StackOverflow { backtrace, .. }
+        | ExecError::OutOfFuel { backtrace, .. }
+        | ExecError::HostPanic { backtrace, .. } = &mut self
+        {
+            symbolizer.symbolize(backtrace);
+        }
+        self
+    }
+
+    /// Records the stack size the overflowing call was made with.
+    pub(crate) fn with_stack_size(mut self, size: Option<usize>) -> Self {
+        if let ExecError::StackOverflow {
+            ref mut stack_size, ..
+        } = self
+        {
+            *stack_size = size;
+        }
+        self
+    }
+
+    /// Attaches the panic message the runtime logged to a trap.
+    pub(crate) fn with_panic_message(mut self, message: Option<String>) -> Self {
+        if let ExecError::Trap { ref mut panic, .. } = self {
+            *panic = message;
+        }
+        self
+    }
+
+    /// What caused the trap, `None` if this is not one.
+    pub fn trap_kind(&self) -> Option<TrapKind> {
+        match self {
+            ExecError::Trap { message, .. } => Some(TrapKind::from_message(message)),
+            ExecError::StackOverflow { .. } => Some(TrapKind::StackOverflow),
+            ExecError::OutOfFuel { .. } => Some(TrapKind::OutOfFuel),
+            ExecError::HostPanic { .. } => Some(TrapKind::HostPanic),
+            err if err.exit_code() == 10 => Some(TrapKind::HostFunctionError),
+            _ => None,
+        }
+    }
+
+    /// The address of the linear memory the failure is about, if any.
+    pub fn address(&self) -> Option<u32> {
+        match self {
+            ExecError::InvalidUtf8InHostCall { ptr, .. }
+            | ExecError::OutOfBoundsInHostCall { ptr, .. }
+            | ExecError::InvalidUtf8 { ptr, .. }
+            | ExecError::OutOfBounds { ptr, .. } => Some(*ptr),
+            _ => None,
+        }
+    }
+
+    /// The panic message the runtime logged before trapping.
+    pub fn panic_message(&self) -> Option<&str> {
+        match self {
+            ExecError::Trap { panic, .. } => panic.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The backtrace of the trap, empty for anything else.
+    pub fn backtrace(&self) -> &[Frame] {
+        match self {
+            ExecError::Trap { backtrace, .. }
+            | ExecError::StackOverflow { backtrace, .. }
+            | ExecError::OutOfFuel { backtrace, .. }
+            | ExecError::HostPanic { backtrace, .. } => backtrace,
+            _ => &[],
+        }
+    }
+}
+
+impl From<Trap> for ExecError {
+    fn from(trap: Trap) -> Self {
+        let message = trap.message().to_string();
+        let backtrace = trap.trace().iter().map(Frame::from).collect();
+        if message.starts_with(crate::HOST_PANIC_PREFIX) {
+            ExecError::HostPanic { message, backtrace }
+        } else if TrapKind::from_message(&message) == TrapKind::StackOverflow {
+            ExecError::StackOverflow {
+                stack_size: None,
+                backtrace,
+            }
+        } else {
+            ExecError::Trap {
+                message,
+                backtrace,
+                panic: None,
+            }
+        }
+    }
+}
+
+fn list_violations(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|violation| format!("\n  - {}", violation))
+        .collect()
+}
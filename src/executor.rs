@@ -0,0 +1,668 @@
+use parity_scale_codec::{Decode, Encode};
+use std::any::Any;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use wasmtime::*;
+
+use crate::abi::unpack_ptr_and_len;
+use crate::allocator::InstrumentedAllocator;
+use crate::backend::{ExecBackend, WasmtimeBackend};
+use crate::fuel::FUEL_GLOBAL;
+use crate::hooks::Hooks;
+use crate::host;
+use crate::interpreter::WasmiBackend;
+use crate::module_info::DataSegment;
+use crate::preflight::{self, Violation};
+use crate::state::{InstanceState, LinearMemory};
+use crate::symbolizer::Symbolizer;
+use crate::trace::{decode, Replay};
+use crate::{
+    Allocation, AllocatorKind, AllocatorStats, Backend, ExecError, ExecutorBuilder, HostContext,
+    HostFunction, HostRegistry, MemCtx, Region, Storage, UnknownImportPolicy,
+};
+
+/// Prefix of the trap message produced when a host function panics.
+pub const HOST_PANIC_PREFIX: &str = "host function panicked: ";
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Box<Any>".to_string()
+    }
+}
+
+fn default_val(val_ty: &ValType) -> Option<Val> {
+    match *val_ty {
+        ValType::I32 => Some(Val::I32(0)),
+        ValType::I64 => Some(Val::I64(0)),
+        ValType::F32 => Some(Val::F32(0)),
+        ValType::F64 => Some(Val::F64(0)),
+        ValType::AnyRef => Some(Val::AnyRef(AnyRef::null())),
+        _ => None,
+    }
+}
+
+pub(crate) struct HostCallable {
+    pub(crate) func_ty: FuncType,
+    ctx: HostContext,
+    handler: Rc<dyn HostFunction>,
+    replay: Option<Rc<Replay>>,
+    hooks: Rc<Hooks>,
+    state: Rc<InstanceState>,
+    memory_limit: Option<u32>,
+    timeout: Option<Duration>,
+}
+
+impl HostCallable {
+    /// Stashes the error so that the caller gets it instead of the opaque trap.
+    pub(crate) fn fail(&self, err: ExecError) -> Trap {
+        let trap = Trap::new(err.to_string());
+        *self.state.host_error.borrow_mut() = Some(err);
+        trap
+    }
+
+    /// The zero value of every result type, which a host function returns unless it sets them.
+    pub(crate) fn default_results(&self) -> Result<Vec<Val>, Trap> {
+        self.func_ty
+            .results()
+            .iter()
+            .map(|ty| {
+                default_val(ty).ok_or_else(|| {
+                    self.fail(ExecError::UnsupportedResult {
+                        function: self.ctx.name().to_string(),
+                        ty: ty.clone(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    fn handle_call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Trap> {
+        let span = tracing::debug_span!(
+            target: "host-call",
+            "host_call",
+            function = self.ctx.name(),
+            params = ?params,
+            decoded = tracing::field::Empty,
+        );
+        // Decoding reads the memory behind every pointer, so only for a subscriber wanting it.
+        if !span.is_disabled() {
+            let decoded: Vec<_> = params
+                .iter()
+                .map(|param| decode(&self.ctx, param))
+                .collect();
+            span.record("decoded", &tracing::field::debug(&decoded));
+        }
+        let _entered = span.enter();
+        results.clone_from_slice(&self.default_results()?);
+        let recorded = match self.replay {
+            Some(ref replay) => Some(
+                replay
+                    .next(self.ctx.name(), params)
+                    .map_err(|err| self.fail(err))?,
+            ),
+            None => None,
+        };
+        self.handler
+            .call(&self.ctx, params, results)
+            .map_err(|err| self.fail(err))?;
+        if let Some(recorded) = recorded {
+            for (result, recorded) in results.iter_mut().zip(&recorded.results) {
+                *result = Val::from(*recorded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Performs the call, leaving the host call hooks to [`Callable::call`].
+    fn perform(&self, params: &[Val], results: &mut [Val]) -> Result<(), Trap> {
+        let host_calls = &self.state.host_calls;
+        host_calls.set(host_calls.get() + 1);
+        if let (Some(deadline), Some(timeout)) = (self.state.deadline.get(), self.timeout) {
+            if Instant::now() >= deadline {
+                return Err(self.fail(ExecError::Timeout { timeout }));
+            }
+        }
+        *self.state.pending_host_call.borrow_mut() = Some(self.ctx.name().to_string());
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            Self::handle_call(self, params, results)
+        }))
+        .map_err(|payload| {
+            Trap::new(format!(
+                "{}{}: {}",
+                HOST_PANIC_PREFIX,
+                self.ctx.name(),
+                panic_message(&*payload)
+            ))
+        })
+        .and_then(|i| i)
+        .and_then(|()| {
+            self.ctx
+                .memory()
+                .with(|memory| check_memory_limit(memory, self.memory_limit))
+                .map_err(|err| self.fail(err))?;
+            self.state.pending_host_call.borrow_mut().take();
+            Ok(())
+        })
+    }
+}
+
+impl Callable for HostCallable {
+    fn call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Trap> {
+        let outcome = self.perform(params, results);
+        match outcome {
+            Ok(()) => self.hooks.host_call(&self.ctx, params, results),
+            Err(ref trap) => self
+                .hooks
+                .host_call_failed(&self.ctx, params, trap.message()),
+        }
+        outcome
+    }
+}
+
+pub(crate) fn type_list(tys: &[ValType]) -> String {
+    tys.iter()
+        .map(|ty| format!("{:?}", ty))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn check_memory_limit(memory: &LinearMemory, memory_limit: Option<u32>) -> Result<(), ExecError> {
+    match memory_limit {
+        Some(limit) if memory.pages() > limit => Err(ExecError::MemoryLimitExceeded {
+            limit,
+            pages: memory.pages(),
+            method: None,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Runs `f` on a thread with a native stack of `stack_size` bytes, which is what the wasm
+/// frames live on, and waits for it. The executor can't cross threads, build it inside `f`.
+pub fn on_stack<F, R>(stack_size: usize, f: F) -> std::io::Result<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let handle = std::thread::Builder::new()
+        .name("wasm".to_string())
+        .stack_size(stack_size)
+        .spawn(f)?;
+    Ok(handle
+        .join()
+        .unwrap_or_else(|payload| std::panic::resume_unwind(payload)))
+}
+
+pub fn new_store(config: &Config) -> Store {
+    let engine = Engine::new(config);
+    Store::new(&engine)
+}
+
+/// A compiled runtime. Every call is performed against a fresh instance of it.
+///
+/// Use [`ExecutorBuilder`] to tweak anything beyond the wasmtime config and heap base.
+///
+/// The executor is neither `Send` nor `Sync`: the `Store`, `Module` and `Func` of the wasmtime
+/// version we pin are reference counted with `Rc`, so swapping our own `Rc<RefCell<..>>`
+/// host state for `Arc<Mutex<..>>` wouldn't change that. To run calls from several threads,
+/// build an executor on each of them.
+///
+/// There is no async call API either, the pinned wasmtime has no async support to build it
+/// on. Async tests can build and call an executor inside `spawn_blocking`.
+pub struct Executor {
+    pub(crate) store: Store,
+    pub(crate) module: Module,
+    /// The module to interpret instead, under the wasmi backend.
+    pub(crate) interpreted: Option<wasmi::Module>,
+    pub(crate) heap_base: u32,
+    pub(crate) allocator: AllocatorKind,
+    pub(crate) poison_freed: bool,
+    pub(crate) symbolizer: Rc<Symbolizer>,
+    pub(crate) data_segments: Rc<Vec<DataSegment>>,
+    pub(crate) registry: HostRegistry,
+    pub(crate) storage: Storage,
+    pub(crate) hooks: Rc<Hooks>,
+    pub(crate) memory_limit: Option<u32>,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) fuel: Option<u64>,
+    pub(crate) nan_canonicalization: bool,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) unknown_import_policy: UnknownImportPolicy,
+    pub(crate) replay: Option<Rc<Replay>>,
+}
+
+impl Executor {
+    /// Loads the runtime using the default wasmtime config and heap base.
+    pub fn from_file(wasm: impl AsRef<Path>) -> Result<Self, ExecError> {
+        ExecutorBuilder::new().build_from_file(wasm.as_ref())
+    }
+
+    pub fn load(config: &Config, wasm: &Path, heap_base: u32) -> Result<Self, ExecError> {
+        ExecutorBuilder::new()
+            .wasmtime_config(config.clone())
+            .heap_base(heap_base)
+            .build_from_file(wasm)
+    }
+
+    pub fn new(config: &Config, code: &[u8], heap_base: u32) -> Result<Self, ExecError> {
+        ExecutorBuilder::new()
+            .wasmtime_config(config.clone())
+            .heap_base(heap_base)
+            .build(code)
+    }
+
+    /// The module as compiled by wasmtime, which is compiled under the wasmi backend as well
+    /// for the types of its imports and exports.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self.interpreted {
+            Some(_) => Backend::Wasmi,
+            None => Backend::Wasmtime,
+        }
+    }
+
+    /// The storage the calls read and write.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Whether a host function is registered under `name`.
+    pub fn implements(&self, name: &str) -> bool {
+        self.registry.get(name).is_some()
+    }
+
+    /// What the function imports without a registered host function are linked against.
+    pub fn unknown_import_policy(&self) -> UnknownImportPolicy {
+        self.unknown_import_policy
+    }
+
+    /// The function imports without a registered host function. What they are linked
+    /// against depends on the [`UnknownImportPolicy`].
+    pub fn stubbed_imports(&self) -> Vec<&ImportType> {
+        self.module
+            .imports()
+            .iter()
+            .filter(|import| {
+                matches!(import.ty(), ExternType::Func(_)) && !self.implements(import.name())
+            })
+            .collect()
+    }
+
+    /// Checks that the module fits the harness and that `method_name` is a runtime API,
+    /// failing with every problem found. The ones the call can go ahead despite are logged.
+    pub fn preflight(&self, method_name: &str) -> Result<(), ExecError> {
+        let (fatal, warnings): (Vec<_>, Vec<_>) = preflight::check(self, Some(method_name))
+            .into_iter()
+            .partition(Violation::is_fatal);
+        for warning in warnings {
+            tracing::warn!("{}", warning);
+        }
+        if fatal.is_empty() {
+            Ok(())
+        } else {
+            Err(ExecError::Preflight(fatal))
+        }
+    }
+
+    /// Instantiates the runtime, for performing several calls against the same instance.
+    pub fn instantiate(&self) -> Result<Runtime, ExecError> {
+        Runtime::new(self)
+    }
+
+    /// Instantiates the runtime and calls the export, see [`Runtime::call`].
+    pub fn call(&self, method_name: &str, input_data: &[u8]) -> Result<Vec<u8>, ExecError> {
+        self.preflight(method_name)?;
+        self.instantiate()?.call(method_name, input_data)
+    }
+
+    /// Instantiates the runtime and calls the export, see [`Runtime::call_typed`].
+    pub fn call_typed<Args: Encode, Ret: Decode>(
+        &self,
+        method_name: &str,
+        args: &Args,
+    ) -> Result<Ret, ExecError> {
+        self.preflight(method_name)?;
+        self.instantiate()?.call_typed(method_name, args)
+    }
+}
+
+/// Builds the extern for each import, looking up its host function once.
+struct ImportResolver<'a> {
+    executor: &'a Executor,
+    state: Rc<InstanceState>,
+}
+
+impl ImportResolver<'_> {
+    fn callable(&self, import: &ImportType) -> Result<HostCallable, ExecError> {
+        let func_ty = match import.ty() {
+            ExternType::Func(func_ty) => func_ty,
+            _ => {
+                return Err(ExecError::ImportResolution {
+                    module: import.module().to_string(),
+                    name: import.name().to_string(),
+                })
+            }
+        };
+        let handler = match self.executor.registry.get(import.name()) {
+            Some(handler) => handler,
+            None => match self.executor.unknown_import_policy {
+                UnknownImportPolicy::Zero => {
+                    tracing::debug!(target: "imports", "stubbing `{}`", import.name());
+                    Rc::new(host::stub)
+                }
+                UnknownImportPolicy::Trap => Rc::new(host::unimplemented),
+                UnknownImportPolicy::Error => {
+                    return Err(ExecError::UnknownImport {
+                        module: import.module().to_string(),
+                        name: import.name().to_string(),
+                    })
+                }
+            },
+        };
+        Ok(HostCallable {
+            func_ty: func_ty.clone(),
+            ctx: HostContext::new(import.name(), self.state.clone()),
+            handler,
+            replay: self.executor.replay.clone(),
+            hooks: self.executor.hooks.clone(),
+            state: self.state.clone(),
+            memory_limit: self.executor.memory_limit,
+            timeout: self.executor.timeout,
+        })
+    }
+}
+
+/// An instantiated runtime together with the host state its imports operate on.
+pub struct Runtime {
+    instance: Box<dyn ExecBackend>,
+    module: Module,
+    ctx: HostContext,
+    state: Rc<InstanceState>,
+    hooks: Rc<Hooks>,
+    memory_limit: Option<u32>,
+    stack_size: Option<usize>,
+    timeout: Option<Duration>,
+    nan_canonicalization: bool,
+    symbolizer: Rc<Symbolizer>,
+}
+
+impl Runtime {
+    fn new(executor: &Executor) -> Result<Self, ExecError> {
+        let state = InstanceState::new(
+            InstrumentedAllocator::new(executor.allocator, executor.heap_base)
+                .poison_freed(executor.poison_freed),
+            executor.data_segments.clone(),
+            executor.fuel,
+        );
+        let resolver = ImportResolver {
+            executor,
+            state: state.clone(),
+        };
+        let imports = executor
+            .module
+            .imports()
+            .iter()
+            .map(|import| resolver.callable(import))
+            .collect::<Result<Vec<_>, _>>()?;
+        let instance: Box<dyn ExecBackend> = match executor.backend() {
+            Backend::Wasmtime => Box::new(WasmtimeBackend::instantiate(executor, imports)?),
+            Backend::Wasmi => Box::new(WasmiBackend::instantiate(executor, imports)?),
+        };
+        state.bind(&*instance)?;
+        state.with_memory(|memory| check_memory_limit(memory, executor.memory_limit))?;
+        // Builds other than the one the default was taken from place the heap elsewhere.
+        let exported_heap_base = state
+            .global("__heap_base")
+            .and_then(|global| global.get().i32());
+        if let Some(heap_base) = exported_heap_base.map(|heap_base| heap_base as u32) {
+            tracing::debug!(target: "imports", "using the exported heap base {}", heap_base);
+            *state.allocator.borrow_mut() =
+                InstrumentedAllocator::new(executor.allocator, heap_base)
+                    .poison_freed(executor.poison_freed);
+        }
+
+        Ok(Self {
+            instance,
+            module: executor.module.clone(),
+            ctx: HostContext::new("", state.clone()),
+            state,
+            hooks: executor.hooks.clone(),
+            memory_limit: executor.memory_limit,
+            stack_size: executor.stack_size,
+            timeout: executor.timeout,
+            nan_canonicalization: executor.nan_canonicalization,
+            symbolizer: executor.symbolizer.clone(),
+        })
+    }
+
+    /// Where the allocator starts handing out memory.
+    pub fn heap_base(&self) -> u32 {
+        self.ctx.heap_base()
+    }
+
+    /// Looks up the exported function to call. wasmi has no [`Func`]s, use
+    /// [`Runtime::func_type`] to work under either backend.
+    pub fn func(&self, method_name: &str) -> Result<&Func, ExecError> {
+        self.func_type(method_name)?;
+        self.instance
+            .func(method_name)
+            .ok_or_else(|| ExecError::Unsupported {
+                what: "looking up a `Func`",
+                backend: self.instance.backend(),
+            })
+    }
+
+    /// The signature of the exported function.
+    pub fn func_type(&self, method_name: &str) -> Result<FuncType, ExecError> {
+        self.module
+            .exports()
+            .iter()
+            .find(|export| export.name() == method_name)
+            .and_then(|export| match export.ty() {
+                ExternType::Func(func_ty) => Some(func_ty.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| ExecError::MissingExport(method_name.to_string()))
+    }
+
+    /// Calls the export with the given params, running the call hooks around it.
+    pub fn invoke(&self, method_name: &str, params: &[Val]) -> Result<Box<[Val]>, ExecError> {
+        self.state.allocator.borrow_mut().begin_call();
+        self.invoke_started(method_name, params)
+    }
+
+    /// [`Runtime::invoke`] for a call whose allocator accounting has already begun.
+    fn invoke_started(&self, method_name: &str, params: &[Val]) -> Result<Box<[Val]>, ExecError> {
+        let span = tracing::debug_span!(
+            "call",
+            method = method_name,
+            backend = %self.instance.backend(),
+        );
+        let _entered = span.enter();
+        let func_ty = self.func_type(method_name)?;
+        let param_tys: Vec<_> = params.iter().map(Val::ty).collect();
+        if param_tys != func_ty.params() {
+            return Err(ExecError::SignatureMismatch {
+                method: method_name.to_string(),
+                expected: type_list(func_ty.params()),
+                actual: type_list(&param_tys),
+            });
+        }
+        let mut params = params.to_vec();
+        self.hooks.before_call(method_name, &mut params, &self.ctx);
+
+        self.state.host_error.borrow_mut().take();
+        self.state.panic_message.borrow_mut().take();
+        self.state.pending_host_call.borrow_mut().take();
+        self.refuel();
+        self.state
+            .deadline
+            .set(self.timeout.map(|timeout| Instant::now() + timeout));
+        let outcome = self
+            .instance
+            .call(method_name, &params)
+            .map_err(|trap| {
+                // A failing host function leaves a more precise error behind than the trap.
+                let err = self.state.host_error.borrow_mut().take();
+                self.out_of_fuel(err.unwrap_or_else(|| ExecError::from(trap)))
+                    .symbolize(&self.symbolizer)
+                    .with_panic_message(self.state.panic_message.borrow_mut().take())
+                    .with_stack_size(self.stack_size)
+            })
+            .and_then(|ret_values| {
+                self.ctx
+                    .memory()
+                    .with(|memory| check_memory_limit(memory, self.memory_limit))?;
+                Ok(ret_values)
+            })
+            .map_err(|err| match err {
+                ExecError::MemoryLimitExceeded { limit, pages, .. } => {
+                    ExecError::MemoryLimitExceeded {
+                        limit,
+                        pages,
+                        method: Some(method_name.to_string()),
+                    }
+                }
+                err => err,
+            });
+
+        if let Err(ref err) = outcome {
+            if err.is_trap() {
+                tracing::info!(
+                    kind = ?err.trap_kind(),
+                    frames = err.backtrace().len(),
+                    "the call trapped: {}",
+                    err
+                );
+            }
+        }
+        self.hooks.after_call(
+            method_name,
+            outcome.as_ref().map(|ret_values| &**ret_values),
+            &self.ctx,
+        );
+        outcome
+    }
+
+    /// Fills the fuel global up for the next call, if the module is metered.
+    fn refuel(&self) {
+        if let (Some(fuel), Some(global)) = (self.state.fuel, self.state.global(FUEL_GLOBAL)) {
+            global
+                .set(Val::I64(fuel as i64))
+                .expect("the fuel global is a mutable i64");
+        }
+    }
+
+    /// Tells the `unreachable` of an exhausted fuel charge from the runtime's own.
+    fn out_of_fuel(&self, err: ExecError) -> ExecError {
+        let exhausted = self
+            .state
+            .global(FUEL_GLOBAL)
+            .and_then(|global| global.get().i64())
+            .map_or(false, |remaining| remaining < 0);
+        match (err, self.state.fuel) {
+            (ExecError::Trap { backtrace, .. }, Some(limit)) if exhausted => {
+                ExecError::OutOfFuel { limit, backtrace }
+            }
+            (err, _) => err,
+        }
+    }
+
+    /// Calls the export and returns the bytes pointed to by its packed ptr/len result.
+    pub fn call(&self, method_name: &str, input_data: &[u8]) -> Result<Vec<u8>, ExecError> {
+        self.func_type(method_name)?;
+        // The input counts towards the call, so that leaking it shows up.
+        self.state.allocator.borrow_mut().begin_call();
+        let ptr = self.ctx.allocate_bytes(input_data)?;
+
+        let ret_values = self.invoke_started(
+            method_name,
+            &[Val::I32(ptr as i32), Val::I32(input_data.len() as i32)],
+        )?;
+        match &*ret_values {
+            [Val::I64(packed)] => {
+                let (ret_ptr, ret_len) = unpack_ptr_and_len(*packed as u64);
+                self.read_memory(ret_ptr, ret_len)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Calls the export with SCALE encoded `args` and decodes its result as `Ret`.
+    pub fn call_typed<Args: Encode, Ret: Decode>(
+        &self,
+        method_name: &str,
+        args: &Args,
+    ) -> Result<Ret, ExecError> {
+        let output = self.call(method_name, &args.encode())?;
+        Ret::decode(&mut &output[..]).map_err(|source| ExecError::Decode {
+            method: method_name.to_string(),
+            source,
+        })
+    }
+
+    /// The number of host functions invoked since instantiation.
+    pub fn host_calls(&self) -> usize {
+        self.state.host_calls.get()
+    }
+
+    /// Whether the NaNs of the module are canonicalized, see
+    /// [`ExecutorBuilder::nan_canonicalization`].
+    pub fn nan_canonicalization(&self) -> bool {
+        self.nan_canonicalization
+    }
+
+    /// The fuel the last call consumed, see [`HostContext::fuel_consumed`].
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        self.ctx.fuel_consumed()
+    }
+
+    /// Forgets every allocation, so that the next call starts with the heap as empty as a
+    /// fresh instance would. The rest of the memory and the globals are left as they are.
+    pub fn reset_allocator(&self) {
+        self.state.allocator.borrow_mut().reset();
+    }
+
+    /// The allocator activity during the last call.
+    pub fn allocator_stats(&self) -> AllocatorStats {
+        self.state.allocator.borrow().stats()
+    }
+
+    /// The allocations made during the last call and not freed yet.
+    pub fn outstanding_allocations(&self) -> Vec<Allocation> {
+        self.state.allocator.borrow().outstanding()
+    }
+
+    pub fn allocate(&self, size: u32) -> Result<u32, ExecError> {
+        self.ctx.allocate(size)
+    }
+
+    pub fn deallocate(&self, ptr: u32) -> Result<(), ExecError> {
+        self.ctx.deallocate(ptr)
+    }
+
+    pub fn memory(&self) -> &MemCtx {
+        self.ctx.memory()
+    }
+
+    /// Where `addr` falls in the layout of the module, see [`HostContext::locate`].
+    pub fn locate(&self, addr: u32) -> Region {
+        self.ctx.locate(addr)
+    }
+
+    pub fn read_memory(&self, ptr: u32, len: u32) -> Result<Vec<u8>, ExecError> {
+        self.memory().read(ptr, len)
+    }
+
+    pub fn write_memory(&self, ptr: u32, bytes: &[u8]) -> Result<(), ExecError> {
+        self.memory().write(ptr, bytes)
+    }
+}
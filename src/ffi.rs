@@ -0,0 +1,171 @@
+//! A C API over [`Executor`], declared in `include/repro.h`.
+//!
+//! Functions that can fail return null or a non-zero exit code, as in
+//! [`ExecError::exit_code`](crate::ExecError::exit_code), and leave a message behind for
+//! [`repro_last_error`]. A panic doesn't unwind into C, it fails the function too.
+
+use crate::{Executor, ExecutorBuilder};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::{ptr, slice};
+
+/// What [`repro_call`] returns if the harness panics, the exit code of a panicking binary.
+const PANIC_EXIT_CODE: i32 = 101;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, failing with `on_panic` if it panics.
+fn guard<R>(on_panic: R, f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        set_last_error(format!(
+            "the harness panicked: {}",
+            crate::panic_message(&*payload)
+        ));
+        on_panic
+    })
+}
+
+unsafe fn to_str<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_last_error(format!("{} is null", what));
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(format!("{} is not valid utf-8", what));
+            None
+        }
+    }
+}
+
+/// Loads the runtime at `wasm_path`. Returns null on failure.
+///
+/// # Safety
+///
+/// `wasm_path` must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn repro_executor_new(
+    wasm_path: *const c_char,
+    heap_base: u32,
+) -> *mut Executor {
+    guard(ptr::null_mut(), || {
+        let wasm_path = match to_str(wasm_path, "wasm_path") {
+            Some(wasm_path) => wasm_path,
+            None => return ptr::null_mut(),
+        };
+        match ExecutorBuilder::new()
+            .heap_base(heap_base)
+            .build_from_file(Path::new(wasm_path))
+        {
+            Ok(executor) => Box::into_raw(Box::new(executor)),
+            Err(err) => {
+                set_last_error(err.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// # Safety
+///
+/// `executor` must come from [`repro_executor_new`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn repro_executor_free(executor: *mut Executor) {
+    guard((), || {
+        if !executor.is_null() {
+            drop(Box::from_raw(executor));
+        }
+    })
+}
+
+/// Calls `method` on a fresh instance. On success returns 0 and stores the returned bytes
+/// in `output`/`output_len`, to be freed with [`repro_output_free`]. Otherwise returns the
+/// exit code the `repro` binary would exit with, 101 if the harness panics.
+///
+/// # Safety
+///
+/// `executor` must come from [`repro_executor_new`], `method` must be a nul terminated
+/// string, `input` must point to `input_len` bytes and `output`, `output_len` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn repro_call(
+    executor: *const Executor,
+    method: *const c_char,
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> i32 {
+    guard(PANIC_EXIT_CODE, || {
+        let nulls = [
+            (executor.is_null(), "executor"),
+            (input.is_null() && input_len != 0, "input"),
+            (output.is_null(), "output"),
+            (output_len.is_null(), "output_len"),
+        ];
+        if let Some((_, name)) = nulls.iter().find(|(is_null, _)| *is_null) {
+            set_last_error(format!("{} is null", name));
+            return 1;
+        }
+        let method = match to_str(method, "method") {
+            Some(method) => method,
+            None => return 1,
+        };
+        let input = match input_len {
+            0 => &[][..],
+            _ => slice::from_raw_parts(input, input_len),
+        };
+        match (*executor).call(method, input) {
+            Ok(bytes) => {
+                let bytes = bytes.into_boxed_slice();
+                *output_len = bytes.len();
+                *output = Box::into_raw(bytes) as *mut u8;
+                0
+            }
+            Err(err) => {
+                let exit_code = err.exit_code();
+                set_last_error(err.to_string());
+                exit_code
+            }
+        }
+    })
+}
+
+/// # Safety
+///
+/// `output` and `output_len` must come from a successful [`repro_call`].
+#[no_mangle]
+pub unsafe extern "C" fn repro_output_free(output: *mut u8, output_len: usize) {
+    guard((), || {
+        if !output.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                output, output_len,
+            )));
+        }
+    })
+}
+
+/// The message of the last failure on this thread, or null. It stays valid until the next
+/// failing call on this thread.
+#[no_mangle]
+pub extern "C" fn repro_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|last_error| {
+            last_error
+                .borrow()
+                .as_ref()
+                .map_or(ptr::null(), |message| message.as_ptr())
+        })
+    })
+}
@@ -0,0 +1,115 @@
+//! Fuel metering, which the pinned wasmtime lacks, by instrumenting the module.
+//!
+//! Every basic block, starting at the function entry and after every branch, charges its
+//! instructions up to the next branch from a mutable global, and traps with `unreachable` once
+//! it goes negative. The global is appended,
+//! so no function or global index of the original module shifts and the name section stays
+//! valid. The code offsets do shift, so the DWARF sections are dropped.
+
+use anyhow::anyhow;
+use parity_wasm::builder;
+use parity_wasm::elements::{
+    BlockType, ExportEntry, External, GlobalEntry, GlobalType, InitExpr, Instruction, Internal,
+    Module, Section, ValueType,
+};
+
+/// The name the fuel global is exported under.
+pub(crate) const FUEL_GLOBAL: &str = "__repro_fuel";
+/// The most fuel the global, an `i64`, holds.
+pub(crate) const MAX_FUEL: u64 = i64::MAX as u64;
+
+/// Whether the instruction ends a basic block.
+fn is_branch(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Block(_)
+            | Instruction::Loop(_)
+            | Instruction::If(_)
+            | Instruction::Else
+            | Instruction::End
+            | Instruction::Br(_)
+            | Instruction::BrIf(_)
+            | Instruction::BrTable(_)
+            | Instruction::Return
+            | Instruction::Unreachable
+    )
+}
+
+fn charge(global: u32, cost: usize) -> Vec<Instruction> {
+    vec![
+        Instruction::GetGlobal(global),
+        Instruction::I64Const(cost as i64),
+        Instruction::I64Sub,
+        Instruction::SetGlobal(global),
+        Instruction::GetGlobal(global),
+        Instruction::I64Const(0),
+        Instruction::I64LtS,
+        Instruction::If(BlockType::NoResult),
+        Instruction::Unreachable,
+        Instruction::End,
+    ]
+}
+
+/// The cost of the run of instructions starting at `start`, the branch ending it included.
+fn run_cost(instructions: &[Instruction], start: usize) -> usize {
+    instructions[start..]
+        .iter()
+        .position(is_branch)
+        .map_or(instructions.len() - start, |end| end + 1)
+}
+
+/// Charges every instruction exactly once, in the run it belongs to.
+fn instrument_body(instructions: &[Instruction], global: u32) -> Vec<Instruction> {
+    let mut instrumented = charge(global, run_cost(instructions, 0));
+    for (idx, instruction) in instructions.iter().enumerate() {
+        instrumented.push(instruction.clone());
+        if is_branch(instruction) && idx + 1 < instructions.len() {
+            instrumented.extend(charge(global, run_cost(instructions, idx + 1)));
+        }
+    }
+    instrumented
+}
+
+/// Drops the DWARF sections, which the code offsets of an instrumented module no longer match.
+pub(crate) fn strip_dwarf(module: &mut Module) {
+    module.sections_mut().retain(|section| match section {
+        Section::Custom(custom) => !custom.name().starts_with(".debug_"),
+        _ => true,
+    });
+}
+
+/// Adds the fuel global, exported as [`FUEL_GLOBAL`], and the charges to every function. The
+/// global starts out with `fuel`, which meters the start function too.
+pub(crate) fn instrument(code: &[u8], fuel: u64) -> anyhow::Result<Vec<u8>> {
+    let mut module: Module =
+        parity_wasm::deserialize_buffer(code).map_err(|err| anyhow!("{}", err))?;
+    strip_dwarf(&mut module);
+    let imported_globals = module.import_section().map_or(0, |imports| {
+        imports
+            .entries()
+            .iter()
+            .filter(|import| matches!(import.external(), External::Global(_)))
+            .count()
+    });
+    let globals = module
+        .global_section()
+        .map_or(0, |globals| globals.entries().len());
+    let global = (imported_globals + globals) as u32;
+    if let Some(code) = module.code_section_mut() {
+        for body in code.bodies_mut() {
+            let instructions = body.code_mut().elements_mut();
+            *instructions = instrument_body(instructions, global);
+        }
+    }
+    let module = builder::from_module(module)
+        .with_global(GlobalEntry::new(
+            GlobalType::new(ValueType::I64, true),
+            InitExpr::new(vec![Instruction::I64Const(fuel as i64), Instruction::End]),
+        ))
+        .with_export(ExportEntry::new(
+            FUEL_GLOBAL.to_string(),
+            Internal::Global(global),
+        ))
+        .build();
+    parity_wasm::serialize(module).map_err(|err| anyhow!("{}", err))
+}
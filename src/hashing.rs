@@ -0,0 +1,86 @@
+//! The `ext_hashing_*` host functions. Each hashes the data behind the fat pointer it is
+//! passed and returns a pointer to the hash, allocated on the runtime's heap.
+
+use crate::{ExecError, HostContext, HostRegistry};
+use sp_core::hashing;
+
+fn hash<H>(ctx: &HostContext, data: u64, f: fn(&[u8]) -> H) -> Result<u32, ExecError>
+where
+    H: AsRef<[u8]>,
+{
+    let data = ctx.read_packed(data)?;
+    ctx.allocate_bytes(f(&data).as_ref())
+}
+
+host_fn! {
+    fn ext_hashing_keccak_256_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::keccak_256)
+    }
+}
+
+host_fn! {
+    fn ext_hashing_sha2_256_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::sha2_256)
+    }
+}
+
+host_fn! {
+    fn ext_hashing_blake2_128_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::blake2_128)
+    }
+}
+
+host_fn! {
+    fn ext_hashing_blake2_256_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::blake2_256)
+    }
+}
+
+host_fn! {
+    fn ext_hashing_twox_64_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::twox_64)
+    }
+}
+
+host_fn! {
+    fn ext_hashing_twox_128_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::twox_128)
+    }
+}
+
+host_fn! {
+    fn ext_hashing_twox_256_version_1(ctx, data: u64) -> u32 {
+        hash(ctx, data, hashing::twox_256)
+    }
+}
+
+pub(crate) fn register(registry: &mut HostRegistry) {
+    registry.register_fn(
+        "ext_hashing_keccak_256_version_1",
+        ext_hashing_keccak_256_version_1,
+    );
+    registry.register_fn(
+        "ext_hashing_sha2_256_version_1",
+        ext_hashing_sha2_256_version_1,
+    );
+    registry.register_fn(
+        "ext_hashing_blake2_128_version_1",
+        ext_hashing_blake2_128_version_1,
+    );
+    registry.register_fn(
+        "ext_hashing_blake2_256_version_1",
+        ext_hashing_blake2_256_version_1,
+    );
+    registry.register_fn(
+        "ext_hashing_twox_64_version_1",
+        ext_hashing_twox_64_version_1,
+    );
+    registry.register_fn(
+        "ext_hashing_twox_128_version_1",
+        ext_hashing_twox_128_version_1,
+    );
+    registry.register_fn(
+        "ext_hashing_twox_256_version_1",
+        ext_hashing_twox_256_version_1,
+    );
+}
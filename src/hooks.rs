@@ -0,0 +1,52 @@
+use crate::{ExecError, HostContext};
+use std::rc::Rc;
+use wasmtime::Val;
+
+/// Called with the export name and its params right before the export is invoked.
+pub type BeforeCallHook = Rc<dyn Fn(&str, &mut [Val], &HostContext)>;
+/// Called with the export name and its outcome once the export returns.
+pub type AfterCallHook = Rc<dyn Fn(&str, Result<&[Val], &ExecError>, &HostContext)>;
+/// Called with the params and results of every host function that returned successfully.
+pub type HostCallHook = Rc<dyn Fn(&HostContext, &[Val], &[Val])>;
+/// Called with the params of every host function that failed and the message it traps with.
+pub type HostCallFailedHook = Rc<dyn Fn(&HostContext, &[Val], &str)>;
+
+/// Callbacks run around every wasm invocation and host call, in registration order.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub(crate) before_call: Vec<BeforeCallHook>,
+    pub(crate) after_call: Vec<AfterCallHook>,
+    pub(crate) host_call: Vec<HostCallHook>,
+    pub(crate) host_call_failed: Vec<HostCallFailedHook>,
+}
+
+impl Hooks {
+    pub(crate) fn before_call(&self, method: &str, params: &mut [Val], ctx: &HostContext) {
+        for hook in &self.before_call {
+            hook(method, params, ctx);
+        }
+    }
+
+    pub(crate) fn after_call(
+        &self,
+        method: &str,
+        outcome: Result<&[Val], &ExecError>,
+        ctx: &HostContext,
+    ) {
+        for hook in &self.after_call {
+            hook(method, outcome, ctx);
+        }
+    }
+
+    pub(crate) fn host_call(&self, ctx: &HostContext, params: &[Val], results: &[Val]) {
+        for hook in &self.host_call {
+            hook(ctx, params, results);
+        }
+    }
+
+    pub(crate) fn host_call_failed(&self, ctx: &HostContext, params: &[Val], message: &str) {
+        for hook in &self.host_call_failed {
+            hook(ctx, params, message);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! The host functions provided to the runtime.
+
+use crate::allocator::InstrumentedAllocator;
+use crate::layout::{self, Layout, Region};
+use crate::state::{InstanceState, LinearMemory};
+use crate::{
+    pack_ptr_and_len, unpack_ptr_and_len, Allocation, AllocatorStats, ExecError, TraceVal,
+};
+use parity_scale_codec::Decode;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasmtime::Val;
+
+/// Bounds checked access to the linear memory of an instance.
+#[derive(Clone)]
+pub struct MemCtx {
+    state: Rc<InstanceState>,
+}
+
+impl MemCtx {
+    pub(crate) fn new(state: Rc<InstanceState>) -> Self {
+        Self { state }
+    }
+
+    pub(crate) fn with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&LinearMemory) -> R,
+    {
+        self.state.with_memory(f)
+    }
+
+    /// Runs `f` over the contents of the linear memory, the one place handing it out
+    /// unchecked.
+    pub(crate) fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.with(|memory| memory.with_data(f))
+    }
+
+    /// The size of the linear memory in bytes.
+    pub fn len(&self) -> u32 {
+        self.with_data(|data| data.len() as u32)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The host address the linear memory starts at, which may change as it grows.
+    pub fn base(&self) -> usize {
+        self.with_data(|data| data.as_ptr() as usize)
+    }
+
+    /// The offset into the linear memory of a host address, if it points into it.
+    pub fn offset_of(&self, addr: usize) -> Option<u32> {
+        addr.checked_sub(self.base())
+            .filter(|&offset| offset < self.len() as usize)
+            .map(|offset| offset as u32)
+    }
+
+    pub fn read(&self, ptr: u32, len: u32) -> Result<Vec<u8>, ExecError> {
+        self.with_data(|data| {
+            data.get(ptr as usize..ptr as usize + len as usize)
+                .map(|bytes| bytes.to_vec())
+                .ok_or(ExecError::OutOfBounds { ptr, len })
+        })
+    }
+
+    pub fn write(&self, ptr: u32, bytes: &[u8]) -> Result<(), ExecError> {
+        self.with_data(|data| {
+            data.get_mut(ptr as usize..ptr as usize + bytes.len())
+                .ok_or(ExecError::OutOfBounds {
+                    ptr,
+                    len: bytes.len() as u32,
+                })?
+                .copy_from_slice(bytes);
+            Ok(())
+        })
+    }
+
+    pub fn read_string(&self, ptr: u32, len: u32) -> Result<String, ExecError> {
+        String::from_utf8(self.read(ptr, len)?).map_err(|_| ExecError::InvalidUtf8 { ptr, len })
+    }
+}
+
+/// The state a host function operates on.
+#[derive(Clone)]
+pub struct HostContext {
+    name: String,
+    memory: MemCtx,
+}
+
+impl HostContext {
+    pub(crate) fn new(name: &str, state: Rc<InstanceState>) -> Self {
+        Self {
+            name: name.to_string(),
+            memory: MemCtx::new(state),
+        }
+    }
+
+    fn allocator(&self) -> &RefCell<InstrumentedAllocator> {
+        &self.memory.state.allocator
+    }
+
+    /// The name of the import being called.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn memory(&self) -> &MemCtx {
+        &self.memory
+    }
+
+    /// The number of host functions the instance invoked since instantiation.
+    pub fn host_calls(&self) -> usize {
+        self.memory.state.host_calls.get()
+    }
+
+    pub fn allocate(&self, size: u32) -> Result<u32, ExecError> {
+        self.memory.with_data(|memory| {
+            self.allocator()
+                .borrow_mut()
+                .allocate(
+                    memory,
+                    size,
+                    Some(self.name.as_str()).filter(|name| !name.is_empty()),
+                )
+                .map_err(ExecError::AllocatorFailure)
+        })
+    }
+
+    pub fn deallocate(&self, ptr: u32) -> Result<(), ExecError> {
+        self.memory.with_data(|memory| {
+            self.allocator()
+                .borrow_mut()
+                .deallocate(memory, ptr)
+                .map_err(ExecError::AllocatorFailure)
+        })
+    }
+
+    /// Where the allocator starts handing out memory.
+    pub fn heap_base(&self) -> u32 {
+        self.allocator().borrow().heap_base()
+    }
+
+    /// The allocator activity during the current call.
+    pub fn allocator_stats(&self) -> AllocatorStats {
+        self.allocator().borrow().stats()
+    }
+
+    /// The allocations made during the current call and not freed yet.
+    pub fn outstanding_allocations(&self) -> Vec<Allocation> {
+        self.allocator().borrow().outstanding()
+    }
+
+    /// The exported globals of the instance and their current values. Globals of other than
+    /// numeric types are left out.
+    pub fn exported_globals(&self) -> Vec<(String, TraceVal)> {
+        self.memory
+            .state
+            .globals
+            .borrow()
+            .iter()
+            .filter_map(|(name, global)| match global.get() {
+                val @ Val::I32(_) | val @ Val::I64(_) | val @ Val::F32(_) | val @ Val::F64(_) => {
+                    Some((name.clone(), TraceVal::from(&val)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Where `addr` falls in the layout of the module: a data segment, the stack, the heap and
+    /// which allocation if any.
+    pub fn locate(&self, addr: u32) -> Region {
+        let state = &self.memory.state;
+        let allocator = self.allocator().borrow();
+        let data_end = state
+            .global("__data_end")
+            .and_then(|global| global.get().i32())
+            .map(|data_end| data_end as u32);
+        let layout = Layout {
+            data_segments: &state.data_segments,
+            data_end,
+            heap_base: allocator.heap_base(),
+            memory_size: self.memory.len(),
+            allocations: &allocator.live(),
+            header_size: allocator.header_size(),
+        };
+        layout::locate(&layout, addr)
+    }
+
+    /// The fuel the current or last call consumed so far, `None` unless the module is
+    /// metered, see [`ExecutorBuilder::fuel`](crate::ExecutorBuilder::fuel).
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        let state = &self.memory.state;
+        let remaining = state.global(crate::fuel::FUEL_GLOBAL)?.get().i64()?;
+        // The charge that runs out overdraws the global.
+        Some(state.fuel?.saturating_sub(remaining.max(0) as u64))
+    }
+
+    /// The host function that was called and didn't return successfully, if the last call
+    /// failed inside one.
+    pub fn pending_host_call(&self) -> Option<String> {
+        self.memory.state.pending_host_call.borrow().clone()
+    }
+
+    /// Blames the import for memory accesses gone wrong, so that the trap says who to look at.
+    fn blame(&self, err: ExecError) -> ExecError {
+        match err {
+            ExecError::OutOfBounds { ptr, len } => ExecError::OutOfBoundsInHostCall {
+                function: self.name.clone(),
+                ptr,
+                len,
+            },
+            ExecError::InvalidUtf8 { ptr, len } => ExecError::InvalidUtf8InHostCall {
+                function: self.name.clone(),
+                ptr,
+                len,
+            },
+            err => err,
+        }
+    }
+
+    pub fn read_memory(&self, ptr: u32, len: u32) -> Result<Vec<u8>, ExecError> {
+        self.memory.read(ptr, len).map_err(|err| self.blame(err))
+    }
+
+    pub fn write_memory(&self, ptr: u32, bytes: &[u8]) -> Result<(), ExecError> {
+        self.memory.write(ptr, bytes).map_err(|err| self.blame(err))
+    }
+
+    /// Copies `bytes` into a fresh allocation and returns its pointer.
+    pub fn allocate_bytes(&self, bytes: &[u8]) -> Result<u32, ExecError> {
+        let ptr = self.allocate(bytes.len() as u32)?;
+        self.write_memory(ptr, bytes)?;
+        Ok(ptr)
+    }
+
+    /// Reads the bytes behind a pointer and length packed into a `u64`.
+    pub fn read_packed(&self, packed: u64) -> Result<Vec<u8>, ExecError> {
+        let (ptr, len) = unpack_ptr_and_len(packed);
+        self.read_memory(ptr, len)
+    }
+
+    /// Copies `bytes` into a fresh allocation and returns its pointer and length packed
+    /// into a `u64`.
+    pub fn allocate_packed(&self, bytes: &[u8]) -> Result<u64, ExecError> {
+        let ptr = self.allocate_bytes(bytes)?;
+        Ok(pack_ptr_and_len(ptr, bytes.len() as u32))
+    }
+
+    /// Reads and decodes the SCALE encoded value behind a packed pointer and length.
+    pub fn read_decoded<T: Decode>(&self, packed: u64) -> Result<T, ExecError> {
+        let bytes = self.read_packed(packed)?;
+        T::decode(&mut &bytes[..]).map_err(|source| ExecError::HostArgDecode {
+            function: self.name.clone(),
+            source,
+        })
+    }
+
+    pub fn read_string(&self, ptr: u32, len: u32) -> Result<String, ExecError> {
+        self.memory
+            .read_string(ptr, len)
+            .map_err(|err| self.blame(err))
+    }
+}
+
+/// A host function the runtime can import.
+///
+/// `results` are zeroed before the call, so a function returning nothing meaningful can
+/// leave them be.
+pub trait HostFunction {
+    fn call(&self, ctx: &HostContext, params: &[Val], results: &mut [Val])
+        -> Result<(), ExecError>;
+}
+
+impl<F> HostFunction for F
+where
+    F: Fn(&HostContext, &[Val], &mut [Val]) -> Result<(), ExecError>,
+{
+    fn call(
+        &self,
+        ctx: &HostContext,
+        params: &[Val],
+        results: &mut [Val],
+    ) -> Result<(), ExecError> {
+        self(ctx, params, results)
+    }
+}
+
+/// Conversion of a host function param from a wasm value.
+pub trait FromVal: Sized {
+    fn from_val(val: &Val) -> Option<Self>;
+}
+
+/// Conversion of a host function result into a wasm value.
+pub trait IntoVal {
+    fn into_val(self) -> Val;
+}
+
+impl FromVal for i32 {
+    fn from_val(val: &Val) -> Option<Self> {
+        val.i32()
+    }
+}
+
+impl FromVal for u32 {
+    fn from_val(val: &Val) -> Option<Self> {
+        val.i32().map(|val| val as u32)
+    }
+}
+
+impl FromVal for i64 {
+    fn from_val(val: &Val) -> Option<Self> {
+        val.i64()
+    }
+}
+
+impl FromVal for u64 {
+    fn from_val(val: &Val) -> Option<Self> {
+        val.i64().map(|val| val as u64)
+    }
+}
+
+impl IntoVal for i32 {
+    fn into_val(self) -> Val {
+        Val::I32(self)
+    }
+}
+
+impl IntoVal for u32 {
+    fn into_val(self) -> Val {
+        Val::I32(self as i32)
+    }
+}
+
+impl IntoVal for i64 {
+    fn into_val(self) -> Val {
+        Val::I64(self)
+    }
+}
+
+impl IntoVal for u64 {
+    fn into_val(self) -> Val {
+        Val::I64(self as i64)
+    }
+}
+
+/// Maps import names to the host functions implementing them. Imports that are not
+/// registered are stubbed with functions returning zeroes, see
+/// [`Executor::stubbed_imports`](crate::Executor::stubbed_imports).
+#[derive(Clone, Default)]
+pub struct HostRegistry {
+    functions: HashMap<String, Rc<dyn HostFunction>>,
+}
+
+impl HostRegistry {
+    /// A registry without any host functions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the host functions this crate implements.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register_fn(
+            "ext_allocator_malloc_version_1",
+            ext_allocator_malloc_version_1,
+        );
+        registry.register_fn("ext_allocator_free_version_1", ext_allocator_free_version_1);
+        registry.register_fn("ext_logging_log_version_1", ext_logging_log_version_1);
+        crate::misc::register(&mut registry);
+        #[cfg(feature = "crypto")]
+        crate::crypto::register(&mut registry);
+        #[cfg(feature = "hashing")]
+        crate::hashing::register(&mut registry);
+        #[cfg(feature = "trie")]
+        crate::trie::register(&mut registry);
+        registry
+    }
+
+    /// Registers `function` under `name`, replacing any previous registration.
+    pub fn register(&mut self, name: &str, function: impl HostFunction + 'static) {
+        self.functions.insert(name.to_string(), Rc::new(function));
+    }
+
+    /// Same as [`HostRegistry::register`], but helps type inference along for closures.
+    pub fn register_fn<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&HostContext, &[Val], &mut [Val]) -> Result<(), ExecError> + 'static,
+    {
+        self.register(name, function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn HostFunction>> {
+        self.functions.get(name).cloned()
+    }
+
+    /// Takes over every registration of `other`, replacing those under the same name.
+    pub(crate) fn extend(&mut self, other: HostRegistry) {
+        self.functions.extend(other.functions);
+    }
+}
+
+/// What to link the function imports without a registered host function against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownImportPolicy {
+    /// A stub leaving the results zeroed.
+    Zero,
+    /// A function trapping with its name once called.
+    Trap,
+    /// Nothing, instantiation fails instead.
+    Error,
+}
+
+impl Default for UnknownImportPolicy {
+    fn default() -> Self {
+        UnknownImportPolicy::Zero
+    }
+}
+
+impl std::str::FromStr for UnknownImportPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(UnknownImportPolicy::Zero),
+            "trap" => Ok(UnknownImportPolicy::Trap),
+            "error" => Ok(UnknownImportPolicy::Error),
+            _ => Err(format!("unknown import policy `{}`", s)),
+        }
+    }
+}
+
+/// Stands in for imports without a registered host function under
+/// [`UnknownImportPolicy::Zero`].
+pub(crate) fn stub(
+    _ctx: &HostContext,
+    _params: &[Val],
+    _results: &mut [Val],
+) -> Result<(), ExecError> {
+    Ok(())
+}
+
+/// Stands in for imports without a registered host function under
+/// [`UnknownImportPolicy::Trap`].
+pub(crate) fn unimplemented(
+    ctx: &HostContext,
+    params: &[Val],
+    _results: &mut [Val],
+) -> Result<(), ExecError> {
+    Err(ExecError::UnimplementedHostFunction {
+        function: ctx.name().to_string(),
+        params: params.iter().map(describe_param).collect(),
+    })
+}
+
+/// Formats the param, spelling out the pointer and length that `i64`s usually pack.
+fn describe_param(val: &Val) -> String {
+    match *val {
+        Val::I32(val) => format!("{:#x}", val as u32),
+        Val::I64(val) => {
+            let (ptr, len) = unpack_ptr_and_len(val as u64);
+            format!("{:#x} (ptr {:#x}, len {})", val as u64, ptr, len)
+        }
+        ref val => format!("{:?}", val),
+    }
+}
+
+host_fn! {
+    fn ext_allocator_malloc_version_1(ctx, size: u32) -> u32 {
+        ctx.allocate(size)
+    }
+}
+
+host_fn! {
+    fn ext_allocator_free_version_1(ctx, ptr: u32) {
+        ctx.deallocate(ptr)
+    }
+}
+
+/// `sp_core::LogLevel::Error`, which crosses the boundary as its variant index.
+pub(crate) const LOG_LEVEL_ERROR: u32 = 0;
+
+host_fn! {
+    fn ext_logging_log_version_1(ctx, level: u32, target: u64, msg: u64) {
+        let (target_ptr, target_len) = unpack_ptr_and_len(target);
+        let (msg_ptr, msg_len) = unpack_ptr_and_len(msg);
+        let target = ctx.read_string(target_ptr, target_len)?;
+        let msg = ctx.read_string(msg_ptr, msg_len)?;
+        println!("{}: {}", target, msg);
+        // The panic handler of `sp-io` logs the message at the error level right before
+        // trapping.
+        if level == LOG_LEVEL_ERROR && target == "runtime" {
+            *ctx.memory.state.panic_message.borrow_mut() = Some(msg);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::scale::{self, ScaleType};
+use anyhow::anyhow;
+use parity_scale_codec::Encode;
+use repro::TraceVal;
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+/// The different ways of supplying the input data of a call. At most one may be given.
+#[derive(StructOpt)]
+pub struct InputOpts {
+    /// Input data, hex encoded (`0x` prefix is optional) [env: REPRO_INPUT], or `-` to read
+    /// the raw input data from stdin.
+    #[structopt(long = "input-hex", alias = "input", parse(try_from_str = parse_input_arg))]
+    pub input_hex: Option<InputArg>,
+    /// Read the raw input data from a file, `-` for stdin.
+    #[structopt(long, parse(from_os_str))]
+    pub input_file: Option<PathBuf>,
+    /// Use the SCALE encoding of the given `u32` as the input data.
+    #[structopt(long)]
+    pub input_scale_u32: Option<u32>,
+    /// SCALE encode the input data from JSON: a `{"<type>": value}` object, e.g.
+    /// `{"u32": 2}`, or an array of them, which are encoded one after another. The types are
+    /// the ones `--ret-type` takes.
+    #[structopt(long)]
+    pub input_json: Option<String>,
+    /// A JSON file with an array of the types of the `--input-json` values, which are then
+    /// given bare, e.g. `[2, "0x0102"]` for `["u32", "Vec<u8>"]`.
+    #[structopt(long, parse(from_os_str))]
+    pub input_schema: Option<PathBuf>,
+}
+
+impl InputOpts {
+    /// Returns the input data, or `None` if none of the options were given.
+    pub fn resolve(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        match (
+            &self.input_hex,
+            &self.input_file,
+            self.input_scale_u32,
+            &self.input_json,
+        ) {
+            (None, None, None, None) if self.input_schema.is_some() => {
+                Err(anyhow!("--input-schema needs --input-json"))
+            }
+            (None, None, None, None) => Ok(None),
+            (Some(InputArg::Hex(bytes)), None, None, None) => Ok(Some(bytes.clone())),
+            (Some(InputArg::Stdin), None, None, None) => read_stdin().map(Some),
+            (None, Some(path), None, None) if path.as_os_str() == "-" => read_stdin().map(Some),
+            (None, Some(path), None, None) => Ok(Some(fs::read(path)?)),
+            (None, None, Some(value), None) => Ok(Some(value.encode())),
+            (None, None, None, Some(json)) => {
+                encode_json(json, self.input_schema.as_deref()).map(Some)
+            }
+            _ => Err(anyhow!(
+                "only one of --input-hex, --input-file, --input-scale-u32 and --input-json can \
+                 be given"
+            )),
+        }
+    }
+}
+
+fn read_stdin() -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Encodes the `--input-json` values, typed by the schema or by themselves.
+fn encode_json(json: &str, schema: Option<&Path>) -> anyhow::Result<Vec<u8>> {
+    let value: Value = serde_json::from_str(json)?;
+    let typed = match schema {
+        Some(schema) => {
+            let types: Vec<String> = serde_json::from_slice(&fs::read(schema)?)?;
+            let values = match value {
+                Value::Array(values) if types.len() != 1 => values,
+                value => vec![value],
+            };
+            if values.len() != types.len() {
+                return Err(anyhow!(
+                    "the schema has {} types, but there are {} values",
+                    types.len(),
+                    values.len()
+                ));
+            }
+            types.into_iter().zip(values).collect::<Vec<_>>()
+        }
+        None => {
+            let values = match value {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Object(object) if object.len() == 1 => {
+                        Ok(object.into_iter().next().expect("the object has one entry"))
+                    }
+                    value => Err(anyhow!(
+                        "expected a {{\"<type>\": value}} object, got {}",
+                        value
+                    )),
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        }
+    };
+    let mut encoded = Vec::new();
+    for (ty, value) in typed {
+        let ty: ScaleType = ty.parse()?;
+        encoded.extend(scale::encode(&ty, &value)?);
+    }
+    Ok(encoded)
+}
+
+/// The value of `--input-hex`.
+pub enum InputArg {
+    Hex(Vec<u8>),
+    /// `-`, the raw input data is on stdin.
+    Stdin,
+}
+
+fn parse_input_arg(s: &str) -> Result<InputArg, hex::FromHexError> {
+    match s {
+        "-" => Ok(InputArg::Stdin),
+        s => parse_hex(s).map(|bytes| InputArg::Hex(bytes.0)),
+    }
+}
+
+/// Bytes parsed from a hex string.
+///
+/// A newtype so that structopt doesn't treat it as a repeated argument.
+pub struct HexBytes(pub Vec<u8>);
+
+pub fn parse_hex(s: &str) -> Result<HexBytes, hex::FromHexError> {
+    let s = s.trim_start_matches("0x");
+    hex::decode(s).map(HexBytes)
+}
+
+/// Parses either a decimal or a `0x` prefixed hexadecimal number.
+pub fn parse_num(s: &str) -> anyhow::Result<u32> {
+    let num = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16)?,
+        None => s.parse()?,
+    };
+    Ok(num)
+}
+
+/// Parses a wasm value given as `<type>:<value>`, e.g. `i32:5` or `f64:-0.5`.
+pub fn parse_arg(s: &str) -> anyhow::Result<TraceVal> {
+    let (ty, value) = match s.find(':') {
+        Some(colon) => (&s[..colon], &s[colon + 1..]),
+        None => return Err(anyhow!("expected `<type>:<value>`, got `{}`", s)),
+    };
+    let val = match ty {
+        "i32" => TraceVal::I32(value.parse()?),
+        "i64" => TraceVal::I64(value.parse()?),
+        "f32" => TraceVal::F32(value.parse::<f32>()?.to_bits()),
+        "f64" => TraceVal::F64(value.parse::<f64>()?.to_bits()),
+        _ => return Err(anyhow!("unknown value type `{}`", ty)),
+    };
+    Ok(val)
+}
+
+/// Formats a wasm value the way [`parse_arg`] takes it.
+pub fn format_arg(val: &TraceVal) -> String {
+    match *val {
+        TraceVal::I32(val) => format!("i32:{}", val),
+        TraceVal::I64(val) => format!("i64:{}", val),
+        TraceVal::F32(bits) => format!("f32:{}", f32::from_bits(bits)),
+        TraceVal::F64(bits) => format!("f64:{}", f64::from_bits(bits)),
+        TraceVal::Ref => "ref".to_string(),
+    }
+}
@@ -0,0 +1,278 @@
+use crate::input::format_arg;
+use anyhow::anyhow;
+use repro::{
+    module_info, preflight, Executor, ExecutorBuilder, Symbolizer, TraceVal, UnknownImportPolicy,
+    DEFAULT_HEAP_BASE,
+};
+use std::path::Path;
+use wasmtime::*;
+
+/// Prints every import and export of the module along with its type. Imports are marked
+/// with what the harness links them against, exported globals with their initial value when
+/// it is a constant. Then the memory map, the code statistics, the runtime APIs the runtime
+/// declares, and how the module was built: its custom sections, the toolchain from its
+/// `producers` section and whether it has DWARF.
+pub fn inspect(executor: &Executor, code: &[u8]) -> anyhow::Result<()> {
+    let module = executor.module();
+    let stubbed = executor.stubbed_imports();
+    println!("imports:");
+    for import in module.imports() {
+        let is_stubbed = stubbed
+            .iter()
+            .any(|stub| stub.module() == import.module() && stub.name() == import.name());
+        let linked = match import.ty() {
+            ExternType::Func(_) if !is_stubbed => "host function",
+            ExternType::Func(_) => match executor.unknown_import_policy() {
+                UnknownImportPolicy::Zero => "stub returning zeros",
+                UnknownImportPolicy::Trap => "stub trapping when called",
+                UnknownImportPolicy::Error => "unsatisfied",
+            },
+            _ => "unsatisfiable, only functions can be imported",
+        };
+        println!(
+            "  {}::{}: {} ({})",
+            import.module(),
+            import.name(),
+            describe(import.ty()),
+            linked
+        );
+    }
+    let globals = module_info::exported_global_inits(code)?;
+    println!("exports:");
+    for export in module.exports() {
+        let init = globals
+            .iter()
+            .find(|(name, _)| name == export.name())
+            .and_then(|(_, init)| init.as_ref());
+        match init {
+            Some(init) => println!(
+                "  {}: {} = {}",
+                export.name(),
+                describe(export.ty()),
+                format_arg(init)
+            ),
+            None => println!("  {}: {}", export.name(), describe(export.ty())),
+        }
+    }
+
+    print_memory_map(module, code)?;
+
+    print_code_stats(module, code)?;
+    crate::runtime_api::print_declared(executor, code)?;
+
+    let custom_sections = module_info::custom_sections(code)?;
+    println!("custom sections:");
+    for section in &custom_sections {
+        println!("  {}: {} bytes", section.name, section.len);
+    }
+    println!("producers:");
+    for field in module_info::producers(code)? {
+        let values: Vec<_> = field
+            .values
+            .iter()
+            .map(|(value, version)| format!("{} {}", value, version).trim_end().to_string())
+            .collect();
+        println!("  {}: {}", field.name, values.join(", "));
+    }
+    if module_info::has_dwarf(&custom_sections) {
+        println!("dwarf: present, backtraces have source locations");
+    } else {
+        println!("dwarf: absent, backtraces have no source locations");
+    }
+    Ok(())
+}
+
+/// How many of the largest functions the code statistics list.
+const LARGEST_FUNCTIONS: usize = 10;
+
+/// Prints how many functions there are, how much code they have and which are the largest.
+fn print_code_stats(module: &Module, code: &[u8]) -> anyhow::Result<()> {
+    let mut sizes = module_info::function_sizes(code)?;
+    let imported_funcs = module
+        .imports()
+        .iter()
+        .filter(|import| matches!(import.ty(), ExternType::Func(_)))
+        .count();
+    println!(
+        "code: {} functions, {} imported; {} imports, {} exports",
+        sizes.len(),
+        imported_funcs,
+        module.imports().len(),
+        module.exports().len()
+    );
+    println!(
+        "  {} bytes of instructions in {} bytes of function bodies",
+        sizes.iter().map(|size| size.code_len as u64).sum::<u64>(),
+        sizes.iter().map(|size| size.body_len as u64).sum::<u64>()
+    );
+    sizes.sort_by_key(|size| std::cmp::Reverse(size.body_len));
+    let symbolizer = Symbolizer::new(code);
+    println!("  largest functions:");
+    for size in sizes.iter().take(LARGEST_FUNCTIONS) {
+        println!(
+            "    #{} {}: {} bytes",
+            size.func_index,
+            symbolizer.name(size.func_index).unwrap_or("<unknown>"),
+            size.body_len
+        );
+    }
+    Ok(())
+}
+
+/// Prints the data segments and what each part of the initial linear memory is used for,
+/// going by where `__data_end` and `__heap_base` point.
+fn print_memory_map(module: &Module, code: &[u8]) -> anyhow::Result<()> {
+    let memory = module
+        .exports()
+        .iter()
+        .find_map(|export| match export.ty() {
+            ExternType::Memory(memory_ty) if export.name() == "memory" => Some(memory_ty),
+            _ => None,
+        });
+    let initial_size = match memory {
+        Some(memory_ty) => {
+            println!("memory: {} pages", describe_limits(memory_ty.limits()));
+            memory_ty.limits().min() as u64 * 0x10000
+        }
+        None => {
+            println!("memory: not exported");
+            0
+        }
+    };
+    let globals = module_info::exported_global_inits(code)?;
+    let static_address = |name: &str| {
+        globals.iter().find_map(|(global, init)| match init {
+            Some(TraceVal::I32(addr)) if global == name => Some(*addr as u32 as u64),
+            _ => None,
+        })
+    };
+    let data_end = static_address("__data_end");
+    let heap_base = static_address("__heap_base");
+
+    let segments = module_info::data_segments(code)?;
+    println!("data segments:");
+    let mut regions = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        match segment.offset {
+            Some(offset) => {
+                let end = offset as u64 + segment.len as u64;
+                println!(
+                    "  #{}: {:#x}..{:#x}, {} bytes",
+                    index, offset, end, segment.len
+                );
+                regions.push((offset as u64, end, format!("data segment #{}", index)));
+            }
+            None => println!(
+                "  #{}: {} bytes, placed by a global or passive",
+                index, segment.len
+            ),
+        }
+    }
+    regions.sort_by_key(|&(start, _, _)| start);
+    let data_top = regions.last().map_or(0, |&(_, end, _)| end);
+    let heap_start = heap_base.unwrap_or(DEFAULT_HEAP_BASE as u64);
+    match data_end {
+        Some(data_end) => {
+            regions.push((data_top, data_end, "static".to_string()));
+            regions.push((
+                data_end,
+                heap_start,
+                "stack, __data_end..__heap_base".to_string(),
+            ));
+        }
+        None => regions.push((data_top, heap_start, "static or stack".to_string())),
+    }
+    let heap = match heap_base {
+        Some(_) => "heap, from __heap_base".to_string(),
+        None => format!("heap, from the default heap base {}", DEFAULT_HEAP_BASE),
+    };
+    regions.push((heap_start, initial_size, heap));
+
+    println!("memory map:");
+    let mut cursor = 0;
+    for (start, end, what) in regions {
+        if start > cursor {
+            println!("  {:#010x}..{:#010x}: static", cursor, start);
+        }
+        if end > start {
+            println!("  {:#010x}..{:#010x}: {}", start, end, what);
+        }
+        cursor = cursor.max(end);
+    }
+    if heap_start > initial_size {
+        println!(
+            "  the heap starts at {:#x}, past the initial {:#x} bytes of memory",
+            heap_start, initial_size
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the wasm is valid and that the harness is able to instantiate it, and call
+/// `method_name` if given.
+pub fn validate(store: &Store, wasm: &Path, method_name: Option<&str>) -> anyhow::Result<()> {
+    let code = repro::read_wasm(wasm)?;
+    Module::validate(store, &code)?;
+    let executor = ExecutorBuilder::new().build(&code)?;
+
+    let violations = preflight::check(&executor, method_name);
+    for violation in &violations {
+        let severity = if violation.is_fatal() {
+            "error"
+        } else {
+            "warning"
+        };
+        println!("{}: {}: {}", wasm.display(), severity, violation);
+    }
+    match violations
+        .iter()
+        .filter(|violation| violation.is_fatal())
+        .count()
+    {
+        0 => {
+            println!("{}: ok", wasm.display());
+            Ok(())
+        }
+        fatal => Err(anyhow!("{} problem(s) found", fatal)),
+    }
+}
+
+pub fn describe(ty: &ExternType) -> String {
+    match ty {
+        ExternType::Func(func_ty) => format!("func {}", signature(func_ty)),
+        ExternType::Global(global_ty) => format!(
+            "global {:?} {:?}",
+            global_ty.mutability(),
+            global_ty.content()
+        ),
+        ExternType::Table(table_ty) => format!(
+            "table {:?} {}",
+            table_ty.element(),
+            describe_limits(table_ty.limits())
+        ),
+        ExternType::Memory(memory_ty) => {
+            format!("memory {} pages", describe_limits(memory_ty.limits()))
+        }
+    }
+}
+
+pub fn signature(func_ty: &FuncType) -> String {
+    let join = |tys: &[ValType]| {
+        tys.iter()
+            .map(|ty| format!("{:?}", ty))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "({}) -> ({})",
+        join(func_ty.params()),
+        join(func_ty.results())
+    )
+}
+
+fn describe_limits(limits: &Limits) -> String {
+    match limits.max() {
+        Some(max) => format!("{}..{}", limits.min(), max),
+        None => format!("{}..", limits.min()),
+    }
+}
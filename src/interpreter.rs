@@ -0,0 +1,220 @@
+//! The wasmi backend, for telling whether a trap comes from the runtime itself or from how
+//! wasmtime compiled it.
+//!
+//! The imports are implemented by the same host functions as under wasmtime, the values are
+//! converted on the way in and out.
+
+use crate::backend::ExecBackend;
+use crate::executor::HostCallable;
+use crate::state::{ExportedGlobal, LinearMemory};
+use crate::{Backend, ExecError, Executor};
+use anyhow::anyhow;
+use std::fmt;
+use wasmi::nan_preserving_float::{F32, F64};
+use wasmi::{
+    Error, Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalRef, ImportResolver,
+    MemoryDescriptor, MemoryRef, ModuleInstance, ModuleRef, RuntimeArgs, RuntimeValue, Signature,
+    TableDescriptor, TableRef, Trap, TrapKind,
+};
+use wasmtime::{Callable, Module, Val};
+
+#[derive(Debug)]
+struct HostError(String);
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl wasmi::HostError for HostError {}
+
+pub(crate) fn to_val(value: RuntimeValue) -> Val {
+    match value {
+        RuntimeValue::I32(value) => Val::I32(value),
+        RuntimeValue::I64(value) => Val::I64(value),
+        RuntimeValue::F32(value) => Val::F32(value.to_bits()),
+        RuntimeValue::F64(value) => Val::F64(value.to_bits()),
+    }
+}
+
+pub(crate) fn from_val(val: &Val) -> Result<RuntimeValue, ExecError> {
+    match *val {
+        Val::I32(value) => Ok(RuntimeValue::I32(value)),
+        Val::I64(value) => Ok(RuntimeValue::I64(value)),
+        Val::F32(bits) => Ok(RuntimeValue::F32(F32::from_bits(bits))),
+        Val::F64(bits) => Ok(RuntimeValue::F64(F64::from_bits(bits))),
+        _ => Err(ExecError::Unsupported {
+            what: "reference types",
+            backend: Backend::Wasmi,
+        }),
+    }
+}
+
+/// Dispatches the calls of imported functions to their host functions.
+struct Dispatch<'a>(&'a [HostCallable]);
+
+impl Externals for Dispatch<'_> {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let callable = &self.0[index];
+        let params: Vec<_> = args.as_ref().iter().map(|&arg| to_val(arg)).collect();
+        callable
+            .default_results()
+            .and_then(|mut results| {
+                callable.call(&params, &mut results)?;
+                let result = results.first().map(from_val).transpose();
+                result.map_err(|err| callable.fail(err))
+            })
+            .map_err(|trap| {
+                Trap::new(TrapKind::Host(Box::new(HostError(
+                    trap.message().to_string(),
+                ))))
+            })
+    }
+}
+
+/// Resolves the function imports to the index of their import, like [`Dispatch`] expects.
+struct Imports<'a>(&'a Module);
+
+impl Imports<'_> {
+    fn unsupported(module_name: &str, field_name: &str) -> Error {
+        let err = ExecError::ImportResolution {
+            module: module_name.to_string(),
+            name: field_name.to_string(),
+        };
+        Error::Instantiation(err.to_string())
+    }
+}
+
+impl ImportResolver for Imports<'_> {
+    fn resolve_func(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        signature: &Signature,
+    ) -> Result<FuncRef, Error> {
+        let index = self
+            .0
+            .imports()
+            .iter()
+            .position(|import| import.module() == module_name && import.name() == field_name)
+            .ok_or_else(|| Self::unsupported(module_name, field_name))?;
+        Ok(FuncInstance::alloc_host(signature.clone(), index))
+    }
+
+    fn resolve_global(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _: &GlobalDescriptor,
+    ) -> Result<GlobalRef, Error> {
+        Err(Self::unsupported(module_name, field_name))
+    }
+
+    fn resolve_memory(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _: &MemoryDescriptor,
+    ) -> Result<MemoryRef, Error> {
+        Err(Self::unsupported(module_name, field_name))
+    }
+
+    fn resolve_table(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _: &TableDescriptor,
+    ) -> Result<TableRef, Error> {
+        Err(Self::unsupported(module_name, field_name))
+    }
+}
+
+pub(crate) struct WasmiBackend {
+    instance: ModuleRef,
+    /// The host functions, by the index of their import.
+    imports: Vec<HostCallable>,
+    exports: Vec<String>,
+}
+
+impl ExecBackend for WasmiBackend {
+    fn instantiate(executor: &Executor, imports: Vec<HostCallable>) -> Result<Self, ExecError> {
+        let module = executor
+            .interpreted
+            .as_ref()
+            .expect("the wasmi backend is built with a wasmi module");
+        let instance = ModuleInstance::new(module, &Imports(&executor.module))
+            .map_err(|err| ExecError::Instantiation(anyhow!("{}", err)))?
+            .run_start(&mut Dispatch(&imports))
+            .map_err(|trap| {
+                ExecError::Instantiation(anyhow!("{}", trap_message(&Error::Trap(trap))))
+            })?;
+        let exports = executor
+            .module
+            .exports()
+            .iter()
+            .map(|export| export.name().to_string())
+            .collect();
+        Ok(Self {
+            instance,
+            imports,
+            exports,
+        })
+    }
+
+    fn backend(&self) -> Backend {
+        Backend::Wasmi
+    }
+
+    fn call(&self, method_name: &str, params: &[Val]) -> Result<Box<[Val]>, wasmtime::Trap> {
+        // The params match the signature, which wasmi can represent, unless a hook swapped them.
+        let args = params
+            .iter()
+            .map(from_val)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| wasmtime::Trap::new(err.to_string()))?;
+        self.instance
+            .invoke_export(method_name, &args, &mut Dispatch(&self.imports))
+            .map(|ret| ret.map(to_val).into_iter().collect())
+            .map_err(|err| wasmtime::Trap::new(trap_message(&err)))
+    }
+
+    fn exports(&self) -> Vec<String> {
+        self.exports.clone()
+    }
+
+    fn memory(&self) -> Option<LinearMemory> {
+        let memory = self.instance.export_by_name("memory")?.as_memory()?.clone();
+        Some(LinearMemory::Wasmi(memory))
+    }
+
+    fn global(&self, name: &str) -> Option<ExportedGlobal> {
+        let global = self.instance.export_by_name(name)?.as_global()?.clone();
+        Some(ExportedGlobal::Wasmi(global))
+    }
+}
+
+/// Words the trap like wasmtime does, so that [`ExecError::trap_kind`] works the same.
+fn trap_message(err: &Error) -> String {
+    let trap = match err {
+        Error::Trap(trap) => trap,
+        err => return err.to_string(),
+    };
+    let message = match trap.kind() {
+        TrapKind::Unreachable => "unreachable",
+        TrapKind::MemoryAccessOutOfBounds => "out of bounds memory access",
+        TrapKind::TableAccessOutOfBounds | TrapKind::ElemUninitialized => {
+            "out of bounds table access"
+        }
+        TrapKind::DivisionByZero => "integer divide by zero",
+        TrapKind::InvalidConversionToInt => "invalid conversion to integer",
+        TrapKind::StackOverflow => "call stack exhausted",
+        TrapKind::UnexpectedSignature => "indirect call type mismatch",
+        TrapKind::Host(err) => return err.to_string(),
+    };
+    format!("wasm trap: {}", message)
+}
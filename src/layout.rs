@@ -0,0 +1,104 @@
+//! Where an address of the linear memory falls in the layout of the module.
+
+use crate::module_info::DataSegment;
+use crate::Allocation;
+use std::fmt;
+
+/// The part of the linear memory an address belongs to.
+#[derive(Clone, Debug)]
+pub enum Region {
+    /// Initialized from a data segment, the index of which is given.
+    DataSegment {
+        index: usize,
+        offset: u32,
+    },
+    /// Between the end of the data and the heap base, where LLVM puts the shadow stack.
+    Stack,
+    /// Below the heap base, outside of the data and the stack.
+    Static,
+    /// The bookkeeping the allocator keeps right in front of an allocation.
+    AllocatorHeader(Allocation),
+    /// Inside a live allocation.
+    Allocation {
+        allocation: Allocation,
+        offset: u32,
+    },
+    /// Above the heap base, but not allocated at the moment.
+    FreeHeap,
+    OutOfBounds {
+        memory_size: u32,
+    },
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Region::DataSegment { index, offset } => {
+                write!(f, "data segment #{}, offset {}", index, offset)
+            }
+            Region::Stack => write!(f, "the stack"),
+            Region::Static => write!(f, "static memory"),
+            Region::AllocatorHeader(allocation) => {
+                write!(f, "the allocator header of {}", allocation)
+            }
+            Region::Allocation { allocation, offset } => {
+                write!(f, "offset {} of {}", offset, allocation)
+            }
+            Region::FreeHeap => write!(f, "unallocated heap"),
+            Region::OutOfBounds { memory_size } => {
+                write!(f, "out of bounds, the memory is {} bytes", memory_size)
+            }
+        }
+    }
+}
+
+/// What [`locate`] maps an address against.
+pub(crate) struct Layout<'a> {
+    pub(crate) data_segments: &'a [DataSegment],
+    pub(crate) data_end: Option<u32>,
+    pub(crate) heap_base: u32,
+    pub(crate) memory_size: u32,
+    pub(crate) allocations: &'a [Allocation],
+    /// The size of the allocator header in front of every allocation.
+    pub(crate) header_size: u32,
+}
+
+pub(crate) fn locate(layout: &Layout, addr: u32) -> Region {
+    if addr >= layout.memory_size {
+        return Region::OutOfBounds {
+            memory_size: layout.memory_size,
+        };
+    }
+    let segment = layout
+        .data_segments
+        .iter()
+        .enumerate()
+        .find_map(|(index, segment)| {
+            let start = segment.offset?;
+            let offset = addr
+                .checked_sub(start)
+                .filter(|&offset| offset < segment.len)?;
+            Some(Region::DataSegment { index, offset })
+        });
+    if let Some(region) = segment {
+        return region;
+    }
+    if addr < layout.heap_base {
+        return match layout.data_end {
+            Some(data_end) if addr >= data_end => Region::Stack,
+            _ => Region::Static,
+        };
+    }
+    for allocation in layout.allocations {
+        if addr >= allocation.ptr && addr - allocation.ptr < allocation.size.max(1) {
+            return Region::Allocation {
+                allocation: allocation.clone(),
+                offset: addr - allocation.ptr,
+            };
+        }
+        if addr < allocation.ptr && allocation.ptr - addr <= layout.header_size {
+            return Region::AllocatorHeader(allocation.clone());
+        }
+    }
+    Region::FreeHeap
+}
@@ -0,0 +1,76 @@
+//! A harness for calling exports of a substrate runtime under wasmtime, with just enough of
+//! the host API stubbed out to get them running.
+//!
+//! ```no_run
+//! let executor = repro::Executor::from_file("sc_runtime_test.wasm")?;
+//! executor.call("test_panic", &[])?;
+//! # Ok::<(), repro::ExecError>(())
+//! ```
+
+#[macro_use]
+mod macros;
+
+pub mod abi;
+mod allocator;
+mod backend;
+mod builder;
+#[cfg(feature = "crypto")]
+mod crypto;
+mod dwarf;
+#[cfg(unix)]
+mod dylib;
+mod error;
+mod executor;
+pub mod ffi;
+mod fuel;
+#[cfg(feature = "hashing")]
+mod hashing;
+mod hooks;
+mod host;
+mod interpreter;
+mod layout;
+mod misc;
+pub mod module_info;
+mod nan;
+#[cfg(unix)]
+pub mod native;
+mod offchain;
+#[cfg(unix)]
+mod plugin;
+pub mod preflight;
+#[cfg(feature = "scripting")]
+mod script;
+mod snapshot;
+mod state;
+mod storage;
+mod symbolizer;
+mod trace;
+#[cfg(feature = "trie")]
+mod trie;
+
+pub use abi::{pack_ptr_and_len, unpack_ptr_and_len};
+pub use allocator::{
+    Allocation, AllocatorKind, AllocatorStats, BuddyAllocator, BumpAllocator, HeapAllocator,
+};
+pub use backend::Backend;
+pub use builder::{read_wasm, ExecutorBuilder};
+pub use error::{ExecError, Frame, TrapKind};
+pub use executor::{new_store, on_stack, panic_message, Executor, Runtime, HOST_PANIC_PREFIX};
+pub use host::{
+    FromVal, HostContext, HostFunction, HostRegistry, IntoVal, MemCtx, UnknownImportPolicy,
+};
+pub use layout::Region;
+pub use offchain::{HttpResponse, OffchainConfig};
+#[cfg(unix)]
+pub use plugin::{load_plugin, PLUGIN_API_VERSION};
+#[cfg(feature = "scripting")]
+pub use script::register_script;
+pub use snapshot::{ChangedRange, MemorySnapshot};
+pub use storage::Storage;
+pub use symbolizer::Symbolizer;
+pub use trace::{HostCallRecord, HostCallTrace, TraceVal};
+#[doc(hidden)]
+pub use wasmtime;
+
+/// The heap base of `sc_runtime_test.wasm`.
+pub const DEFAULT_HEAP_BASE: u32 = 1055861;
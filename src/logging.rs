@@ -0,0 +1,73 @@
+//! Logging through `env_logger`, optionally holding back debug logs until a call fails.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Forwards to `env_logger` and keeps every record up to the debug level on the side.
+struct CapturingLogger {
+    inner: env_logger::Logger,
+    captured: Arc<Mutex<Vec<String>>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LevelFilter::Debug || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if record.level() <= LevelFilter::Debug {
+            let line = format!("[{} {}] {}", record.level(), record.target(), record.args());
+            self.captured.lock().unwrap().push(line);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// The debug logs held back, see [`init`].
+pub struct Captured(Arc<Mutex<Vec<String>>>);
+
+impl Captured {
+    /// Writes the logs captured so far to `path`, or stderr, and forgets them.
+    pub fn emit(&self, path: Option<&Path>) -> anyhow::Result<()> {
+        let lines = std::mem::take(&mut *self.0.lock().unwrap());
+        match path {
+            Some(path) => fs::write(path, lines.join("\n") + "\n")?,
+            None => lines.iter().for_each(|line| eprintln!("{}", line)),
+        }
+        Ok(())
+    }
+}
+
+fn builder(filter: Option<&str>) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::from_default_env();
+    if let Some(filter) = filter {
+        builder.parse_filters(filter);
+    }
+    builder
+}
+
+/// Initializes `env_logger` with `filter` taking precedence over `RUST_LOG`.
+pub fn init(filter: Option<&str>) {
+    builder(filter).init();
+}
+
+/// Like [`init`], but also captures every debug log, to be emitted only if the call fails.
+pub fn init_capturing(filter: Option<&str>) -> Captured {
+    let inner = builder(filter).build();
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    log::set_max_level(inner.filter().max(LevelFilter::Debug));
+    let logger = CapturingLogger {
+        inner,
+        captured: captured.clone(),
+    };
+    log::set_boxed_logger(Box::new(logger)).expect("the logger is initialized once");
+    Captured(captured)
+}
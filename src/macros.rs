@@ -0,0 +1,109 @@
+/// Defines a [`HostFunction`](crate::HostFunction) with a typed signature.
+///
+/// The params are converted from `Val`s with [`FromVal`](crate::FromVal) and the result
+/// back with [`IntoVal`](crate::IntoVal). The body evaluates to a
+/// `Result<_, ExecError>`, so `?` can be used in it.
+///
+/// ```
+/// repro::host_fn! {
+///     fn ext_misc_print_num_version_1(_ctx, value: u64) {
+///         println!("{}", value);
+///         Ok(())
+///     }
+/// }
+///
+/// let mut registry = repro::HostRegistry::with_builtins();
+/// registry.register("ext_misc_print_num_version_1", ext_misc_print_num_version_1);
+/// ```
+///
+/// Host functions that need state of their own can be written as closures instead:
+/// `host_fn!(move |ctx, key: u64| -> u64 { .. })`.
+#[macro_export]
+macro_rules! host_fn {
+    (@params $ctx:ident, $params:ident, $($arg:ident: $arg_ty:ty),*) => {
+        let mut params = $params.iter();
+        $(
+            let $arg: $arg_ty = params
+                .next()
+                .and_then($crate::FromVal::from_val)
+                .ok_or_else(|| $crate::ExecError::HostSignatureMismatch {
+                    function: $ctx.name().to_string(),
+                })?;
+        )*
+        if params.next().is_some() {
+            return Err($crate::ExecError::HostSignatureMismatch {
+                function: $ctx.name().to_string(),
+            });
+        }
+    };
+    (move |$ctx:ident $(, $arg:ident: $arg_ty:ty)* $(,)?| -> $ret:ty $body:block) => {
+        move |$ctx: &$crate::HostContext,
+              params: &[$crate::wasmtime::Val],
+              results: &mut [$crate::wasmtime::Val]|
+              -> Result<(), $crate::ExecError> {
+            $crate::host_fn!(@params $ctx, params, $($arg: $arg_ty),*);
+            #[allow(clippy::redundant_closure_call)]
+            let ret = (|| -> Result<$ret, $crate::ExecError> { $body })()?;
+            $crate::host_fn!(@ret $ctx, results, ret);
+            Ok(())
+        }
+    };
+    (move |$ctx:ident $(, $arg:ident: $arg_ty:ty)* $(,)?| $body:block) => {
+        move |$ctx: &$crate::HostContext,
+              params: &[$crate::wasmtime::Val],
+              _results: &mut [$crate::wasmtime::Val]|
+              -> Result<(), $crate::ExecError> {
+            $crate::host_fn!(@params $ctx, params, $($arg: $arg_ty),*);
+            $body
+        }
+    };
+    (@ret $ctx:ident, $results:ident, $ret:ident) => {
+        match $results.first_mut() {
+            Some(result) => *result = $crate::IntoVal::into_val($ret),
+            None => {
+                return Err($crate::ExecError::HostSignatureMismatch {
+                    function: $ctx.name().to_string(),
+                })
+            }
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident($ctx:ident $(, $arg:ident: $arg_ty:ty)* $(,)?)
+            -> $ret:ty $body:block
+    ) => {
+        $(#[$attr])*
+        $vis fn $name(
+            $ctx: &$crate::HostContext,
+            params: &[$crate::wasmtime::Val],
+            results: &mut [$crate::wasmtime::Val],
+        ) -> Result<(), $crate::ExecError> {
+            $crate::host_fn!(@params $ctx, params, $($arg: $arg_ty),*);
+            fn body(
+                $ctx: &$crate::HostContext,
+                $($arg: $arg_ty),*
+            ) -> Result<$ret, $crate::ExecError> $body
+            let ret = body($ctx, $($arg),*)?;
+            $crate::host_fn!(@ret $ctx, results, ret);
+            Ok(())
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident($ctx:ident $(, $arg:ident: $arg_ty:ty)* $(,)?) $body:block
+    ) => {
+        $(#[$attr])*
+        $vis fn $name(
+            $ctx: &$crate::HostContext,
+            params: &[$crate::wasmtime::Val],
+            _results: &mut [$crate::wasmtime::Val],
+        ) -> Result<(), $crate::ExecError> {
+            $crate::host_fn!(@params $ctx, params, $($arg: $arg_ty),*);
+            fn body(
+                $ctx: &$crate::HostContext,
+                $($arg: $arg_ty),*
+            ) -> Result<(), $crate::ExecError> $body
+            body($ctx, $($arg),*)
+        }
+    };
+}
@@ -1,196 +1,477 @@
 use anyhow::anyhow;
-use parity_scale_codec::Encode;
-use sp_allocator::FreeingBumpHeapAllocator;
-use sp_wasm_interface::Pointer;
-use std::cell::RefCell;
-use std::fs;
-use std::rc::Rc;
-use wasmtime::*;
-
-fn default_val(val_ty: &ValType) -> Val {
-    match *val_ty {
-        ValType::I32 => Val::I32(0),
-        ValType::I64 => Val::I64(0),
-        ValType::F32 => Val::F32(0),
-        ValType::F64 => Val::F64(0),
-        _ => todo!(),
-    }
-}
+use std::time::Instant;
+use std::{fs, process};
+use structopt::StructOpt;
+use wasmtime::{Config, Val};
 
-fn unpack_ptr_and_len(val: u64) -> (u32, u32) {
-    let ptr = (val & (!0u32 as u64)) as u32;
-    let len = (val >> 32) as u32;
+mod batch;
+mod bench;
+mod cli;
+mod config;
+mod coredump;
+#[cfg(target_os = "linux")]
+mod crash;
+mod crash_report;
+mod diff;
+mod disasm;
+mod input;
+mod inspect;
+mod logging;
+mod memdump;
+mod repl;
+mod report;
+mod runtime_api;
+mod scale;
+mod settings;
+mod watch;
+mod watchdog;
 
-    (ptr, len)
-}
+use cli::{Cli, OutputFormat};
+use config::{Session, WasmtimeFlags};
+use repro::{Backend, ExecError, Executor, ExecutorBuilder, HostCallTrace, TraceVal};
+use settings::Settings;
+use watchdog::Watchdog;
 
-fn read_string(memory: &[u8], ptr: u32, len: u32) -> String {
-    let ptr = ptr as usize;
-    let len = len as usize;
-    String::from_utf8(memory[ptr..(ptr + len)].to_vec()).unwrap()
+/// Sizes rayon's global pool, which wasmtime compiles on. It can only be sized once, before
+/// anything is compiled.
+fn size_compile_pool(wasmtime: &WasmtimeFlags) -> anyhow::Result<()> {
+    if let Some(threads) = wasmtime.compile_threads() {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    Ok(())
 }
 
-#[derive(Clone)]
-struct MemoryHolder {
-    inner: Rc<RefCell<Option<Memory>>>, // gross
+/// Resolves the settings of a subcommand, and sizes the compilation pool after them.
+fn resolve_settings(opts: &cli::RunOpts) -> anyhow::Result<Settings> {
+    let settings = opts.settings()?;
+    size_compile_pool(&settings.wasmtime)?;
+    Ok(settings)
 }
 
-impl MemoryHolder {
-    fn new() -> Self {
-        Self {
-            inner: Rc::new(RefCell::new(None)),
+fn run_session(session: &Session, watchdog: &Watchdog) -> anyhow::Result<()> {
+    let executor = watchdog
+        .watch(session.executor_builder()?)
+        .build_from_file(&session.wasm)?;
+    if session.reuse_instance {
+        let runtime = executor.instantiate()?;
+        for call in &session.calls {
+            runtime.reset_allocator();
+            runtime.call(&call.method, &call.input)?;
+        }
+    } else {
+        for call in &session.calls {
+            executor.call(&call.method, &call.input)?;
         }
     }
+    Ok(())
+}
+
+fn run_batch(opts: cli::BatchOpts, watchdog: &Watchdog) -> anyhow::Result<()> {
+    let unknown_import_policy = opts.unknown_import_policy();
+    let (builder, wasm, mut calls, reuse_instance) = match opts.manifest {
+        Some(ref manifest) => {
+            let mut session = Session::load(manifest)?;
+            settings::override_session(&mut session)?;
+            size_compile_pool(&session.wasmtime)?;
+            (
+                session.executor_builder()?,
+                session.wasm,
+                session.calls,
+                session.reuse_instance || opts.reuse_instance,
+            )
+        }
+        None => (
+            ExecutorBuilder::new(),
+            opts.wasm,
+            Vec::new(),
+            opts.reuse_instance,
+        ),
+    };
+    let builder = watchdog.watch(builder.unknown_import_policy(unknown_import_policy));
+    calls.extend(opts.calls);
+    let runtime = if reuse_instance {
+        Some(builder.clone().build_from_file(&wasm)?.instantiate()?)
+    } else {
+        None
+    };
 
-    fn set(&self, memory: Memory) {
-        *self.inner.borrow_mut() = Some(memory);
+    if opts.output == OutputFormat::Json {
+        let mut failed = 0;
+        for call in &calls {
+            let (record, _) = match runtime {
+                Some(ref runtime) => {
+                    runtime.reset_allocator();
+                    report::record_runtime_call(runtime, &call.method, &call.input)
+                }
+                None => report::record_call(&builder, &wasm, &call.method, &call.input),
+            };
+            record.print_json()?;
+            if !record.is_success() {
+                failed += 1;
+            }
+        }
+        return match failed {
+            0 => Ok(()),
+            failed => Err(anyhow!("{} of {} calls failed", failed, calls.len())),
+        };
     }
 
-    fn with<R, F>(&self, f: F) -> R
-    where
-        F: FnOnce(&Memory) -> R,
-    {
-        let guard = self.inner.borrow();
-        f(&*guard.as_ref().unwrap())
+    let statuses = batch::run(&calls, |call| match runtime {
+        Some(ref runtime) => {
+            runtime.reset_allocator();
+            runtime.call(&call.method, &call.input).map(drop)
+        }
+        None => builder
+            .clone()
+            .build_from_file(&wasm)?
+            .call(&call.method, &call.input)
+            .map(drop),
+    });
+    match batch::print_summary(&calls, &statuses) {
+        0 => Ok(()),
+        failed => Err(anyhow!("{} of {} calls failed", failed, calls.len())),
     }
 }
 
-struct DummyCallable {
-    name: String,
-    func_ty: FuncType,
-    allocator: Rc<RefCell<FreeingBumpHeapAllocator>>,
-    memory: MemoryHolder,
-}
+fn run_call(opts: cli::RunOpts, watchdog: &Watchdog) -> anyhow::Result<()> {
+    let settings = resolve_settings(&opts)?;
+    let builder = watchdog.watch(opts.executor_builder(&settings)?);
+    let method = settings.method()?;
+    if opts.dry_run {
+        let executor = builder.build_from_file(&settings.wasm)?;
+        executor.preflight(method)?;
+        let runtime = executor.instantiate()?;
+        let func_ty = runtime.func_type(method)?;
+        println!(
+            "{}: instantiated, `{}` {} is ready to be called",
+            settings.wasm.display(),
+            method,
+            inspect::signature(&func_ty)
+        );
+        return Ok(());
+    }
+
+    if !opts.args.is_empty() {
+        return invoke_with_args(builder, &settings, method, &opts.args, opts.output);
+    }
 
-impl DummyCallable {
-    fn handle_call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Trap> {
-        log::debug!(target: "host-call", " {}, params = {:?}", self.name, params);
-        results
-            .iter_mut()
-            .enumerate()
-            .for_each(|(idx, result)| *result = default_val(&self.func_ty.params()[idx]));
-        match &*self.name {
-            "ext_allocator_malloc_version_1" => {
-                let size = params[0].unwrap_i32() as u32;
-                let ptr = self.memory.with(|memory| {
-                    self.allocator
-                        .borrow_mut()
-                        .allocate(unsafe { memory.data_unchecked_mut() }, size)
-                        .map_err(|_| Trap::new("can't allocate"))
-                })?;
-                results[0] = Val::I32(usize::from(ptr) as i32);
+    let trace = HostCallTrace::new();
+    let builder = match opts.trace_host_calls {
+        Some(_) => builder.trace_host_calls(trace.clone()),
+        None => builder,
+    };
+    let result = match opts.output {
+        OutputFormat::Human => {
+            let start = Instant::now();
+            let executor = builder.clone().build_from_file(&settings.wasm);
+            eprintln!("compiled in {:?}", start.elapsed());
+            let result = executor.and_then(|executor| executor.call(method, &settings.input));
+            if let Ok(ref output) = result {
+                println!("returned {}", report::to_hex(output));
+                if let Some(ref ret_type) = opts.ret_type {
+                    // The hex above stands, the call itself succeeded.
+                    match scale::decode_all(ret_type, output) {
+                        Ok(decoded) => println!("as {}: {}", ret_type, decoded),
+                        Err(err) => eprintln!("{:#}", err),
+                    }
+                }
             }
-            "ext_allocator_free_version_1" => {
-                let ptr = params[0].unwrap_i32() as u32;
-                self.memory.with(|memory| {
-                    self.allocator
-                        .borrow_mut()
-                        .deallocate(unsafe { memory.data_unchecked_mut() }, Pointer::new(ptr))
-                        .map_err(|_| Trap::new("can't deallocate"))
-                })?;
+            result
+        }
+        OutputFormat::Json => {
+            let (mut record, result) =
+                report::record_call(&builder, &settings.wasm, method, &settings.input);
+            if let (Some(ret_type), Ok(output)) = (&opts.ret_type, &result) {
+                match scale::decode_all(ret_type, output) {
+                    Ok(decoded) => record.decoded = Some(decoded),
+                    Err(err) => eprintln!("{:#}", err),
+                }
             }
-            "ext_logging_log_version_1" => {
-                let (target_ptr, target_len) = unpack_ptr_and_len(params[1].unwrap_i64() as u64);
-                let (msg_ptr, msg_len) = unpack_ptr_and_len(params[2].unwrap_i64() as u64);
-                self.memory.with(|memory| unsafe {
-                    let target = read_string(memory.data_unchecked_mut(), target_ptr, target_len);
-                    let msg = read_string(memory.data_unchecked_mut(), msg_ptr, msg_len);
-                    println!("{}: {}", target, msg);
-                });
+            record.print_json()?;
+            result
+        }
+    };
+    #[cfg(unix)]
+    {
+        if let Some(ref native) = opts.native {
+            compare_native(native, &settings, method, &result)?;
+        }
+    }
+    let fall_back = opts.auto_fallback && opts.backend == Backend::Wasmtime;
+    let result = match result {
+        Err(err) if (opts.rerun_interpreted || fall_back) && err.is_trap() => {
+            // Without the hooks, so that the trace and the crash report are the wasmtime ones.
+            let builder = watchdog.watch(builder.without_hooks());
+            match rerun_interpreted(builder, &settings, method, &err) {
+                Some(output) if fall_back => {
+                    eprintln!("falling back to the result under wasmi");
+                    Ok(output)
+                }
+                _ => Err(err),
             }
-            _ => {}
         }
-        Ok(())
+        result => result,
+    };
+    if let (Some(path), Ok(output)) = (&opts.ret_out, &result) {
+        fs::write(path, output)?;
     }
+    // The trace matters the most when the call fails, so it is saved either way.
+    if let Some(ref path) = opts.trace_host_calls {
+        trace.save(path)?;
+    }
+    check_expectations(&opts, result)
 }
 
-impl Callable for DummyCallable {
-    fn call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Trap> {
-        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            Self::handle_call(self, params, results)
-        }))
-        .map_err(|_| Trap::new("trap"))
-        .and_then(|i| i)
+/// Holds the outcome against `--expect-ret` and `--expect-trap`.
+fn check_expectations(
+    opts: &cli::RunOpts,
+    result: Result<Vec<u8>, ExecError>,
+) -> anyhow::Result<()> {
+    match (&opts.expect_ret, &opts.expect_trap, result) {
+        (Some(expected), _, Ok(output)) if output == expected.0 => Ok(()),
+        (Some(expected), _, Ok(output)) => Err(anyhow!(
+            "expected the call to return {}, it returned {}",
+            report::to_hex(&expected.0),
+            report::to_hex(&output)
+        )),
+        (Some(expected), _, Err(err)) => Err(anyhow::Error::new(err).context(format!(
+            "expected the call to return {}",
+            report::to_hex(&expected.0)
+        ))),
+        (None, Some(expected), Err(err)) if err.is_trap() && err.to_string().contains(expected) => {
+            eprintln!("the call traps as expected: {}", err);
+            Ok(())
+        }
+        (None, Some(expected), Err(err)) => Err(anyhow::Error::new(err)
+            .context(format!("expected the call to trap with `{}`", expected))),
+        (None, Some(expected), Ok(output)) => Err(anyhow!(
+            "expected the call to trap with `{}`, it returned {}",
+            expected,
+            report::to_hex(&output)
+        )),
+        (None, None, result) => Ok(result.map(drop)?),
     }
 }
 
-fn perform_call(method_name: &str, input_data: &[u8]) -> anyhow::Result<()> {
-    let code = fs::read("sc_runtime_test.wasm")?;
-
-    let config = Config::new();
-    let engine = Engine::new(&config);
-
-    let store = Store::new(&engine);
-    let module = Module::new(&store, &code)?;
-
-    let heap_base = 1055861;
-    let allocator = Rc::new(RefCell::new(FreeingBumpHeapAllocator::new(heap_base)));
-
-    let memory = MemoryHolder::new();
-
-    let mut externs = vec![];
-    for import in module.imports() {
-        match *import.ty() {
-            ExternType::Func(ref func_ty) => {
-                let callable = DummyCallable {
-                    name: import.name().to_string(),
-                    func_ty: func_ty.clone(),
-                    allocator: allocator.clone(),
-                    memory: memory.clone(),
-                };
-                externs.push(Extern::Func(Func::new(
-                    &store,
-                    func_ty.clone(),
-                    Rc::new(callable),
-                )));
-            }
-            _ => return Err(anyhow!("can't provide non function import")),
+/// Calls an export outside of the runtime API convention, with explicit params.
+fn invoke_with_args(
+    builder: ExecutorBuilder,
+    settings: &Settings,
+    method: &str,
+    args: &[TraceVal],
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let runtime = builder.build_from_file(&settings.wasm)?.instantiate()?;
+    // Converted only now, on the thread performing the call: `Val` isn't `Send`.
+    let args: Vec<_> = args.iter().map(|&arg| Val::from(arg)).collect();
+    let results: Vec<_> = runtime
+        .invoke(method, &args)?
+        .iter()
+        .map(TraceVal::from)
+        .collect();
+    match output {
+        OutputFormat::Human => {
+            let results: Vec<_> = results.iter().map(input::format_arg).collect();
+            println!("returned ({})", results.join(", "));
         }
+        OutputFormat::Json => println!("{}", serde_json::to_string(&results)?),
     }
+    Ok(())
+}
 
-    let instance = Instance::new(&module, &externs)?;
-    memory.set(
-        instance
-            .get_export("memory")
-            .ok_or_else(|| anyhow!("`memory` should be exported"))?
-            .memory()
-            .ok_or_else(|| anyhow!("`memory` should be of memory kind"))?
-            .clone(),
-    );
-
-    let (ptr, len) = inject_input_data(&mut *allocator.borrow_mut(), &memory, input_data)?;
-
-    let _ret_values = instance
-        .get_export(method_name)
-        .ok_or_else(|| anyhow!("`{}` is not found", method_name))?
-        .func()
-        .ok_or_else(|| anyhow!("`{}` is not a function", method_name))?
-        .call(&[ptr, len])?;
+/// Performs the call that trapped again under wasmi, which tells codegen bugs from runtime
+/// ones, and returns its output if it doesn't trap there.
+fn rerun_interpreted(
+    builder: ExecutorBuilder,
+    settings: &Settings,
+    method: &str,
+    err: &ExecError,
+) -> Option<Vec<u8>> {
+    eprintln!("the call traps under wasmtime, performing it again under wasmi");
+    let interpreted = builder
+        .backend(Backend::Wasmi)
+        .build_from_file(&settings.wasm)
+        .and_then(|executor| executor.call(method, &settings.input));
+    match interpreted {
+        Ok(output) => {
+            eprintln!(
+                "under wasmi the call returns {} instead, the outcome diverges",
+                report::to_hex(&output)
+            );
+            return Some(output);
+        }
+        Err(interpreted) if interpreted.trap_kind() == err.trap_kind() => {
+            eprintln!("under wasmi the call traps the same way: {}", interpreted)
+        }
+        Err(interpreted) => eprintln!(
+            "under wasmi the call fails differently, the outcome diverges: {}",
+            interpreted
+        ),
+    }
+    None
+}
 
+/// Performs the call natively, which tells wasm codegen bugs from bugs in the runtime logic.
+#[cfg(unix)]
+fn compare_native(
+    native: &std::path::Path,
+    settings: &Settings,
+    method: &str,
+    result: &Result<Vec<u8>, ExecError>,
+) -> anyhow::Result<()> {
+    let output = repro::native::call(native, method, &settings.input)?;
+    match result {
+        Ok(wasm_output) if *wasm_output == output => {
+            eprintln!("natively the call returns the same output")
+        }
+        Ok(wasm_output) => eprintln!(
+            "natively the call returns {} instead of {}, the outcome diverges",
+            report::to_hex(&output),
+            report::to_hex(wasm_output)
+        ),
+        Err(_) => eprintln!(
+            "natively the call returns {}, the failure is specific to wasm",
+            report::to_hex(&output)
+        ),
+    }
     Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    env_logger::init();
-    perform_call("test_conditional_panic", &vec![2].encode())?;
-    perform_call("test_panic", &[])?;
-    Ok(())
+/// The deepest frames of a backtrace that get printed, the rest of a stack overflow is noise.
+const MAX_PRINTED_FRAMES: usize = 64;
+
+fn main() {
+    if let Err(err) = run(Cli::from_args()) {
+        eprintln!("Error: {:?}", err);
+        let exec_error = err.downcast_ref::<ExecError>();
+        if let Some(exec_error) = exec_error {
+            if let Some(kind) = exec_error.trap_kind() {
+                eprintln!("Trap kind: {}", kind);
+            }
+            let backtrace = exec_error.backtrace();
+            for (idx, frame) in backtrace.iter().take(MAX_PRINTED_FRAMES).enumerate() {
+                eprintln!("  {}: {}", idx, frame);
+            }
+            if backtrace.len() > MAX_PRINTED_FRAMES {
+                eprintln!("  ... {} more", backtrace.len() - MAX_PRINTED_FRAMES);
+            }
+        }
+        let exit_code = exec_error.map_or(1, ExecError::exit_code);
+        process::exit(exit_code);
+    }
 }
 
-fn inject_input_data(
-    allocator: &mut FreeingBumpHeapAllocator,
-    memory: &MemoryHolder,
-    data: &[u8],
-) -> anyhow::Result<(Val, Val)> {
-    memory.with(|memory| unsafe {
-        let ptr = allocator.allocate(memory.data_unchecked_mut(), data.len() as u32)?;
-        let ptr = usize::from(ptr);
-
-        let dst = &mut memory.data_unchecked_mut()[ptr..(ptr + data.len())];
-        dst.copy_from_slice(data);
-        Ok((
-            Val::I32(ptr as u32 as i32),
-            Val::I32(data.len() as u32 as i32),
-        ))
-    })
+fn run(cli: Cli) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    crash::install()?;
+    match cli {
+        Cli::Run(opts) => {
+            let captured = if opts.log_on_failure {
+                Some(logging::init_capturing(None))
+            } else {
+                logging::init(None);
+                None
+            };
+            let failure_log = opts.failure_log.clone();
+            let watchdog = Watchdog::new(opts.timeout, opts.output);
+            let result = watchdog.run(opts.stack_size, {
+                let watchdog = watchdog.clone();
+                move || run_call(opts, &watchdog)
+            });
+            if let (Some(captured), Err(_)) = (captured, &result) {
+                captured.emit(failure_log.as_deref())?;
+            }
+            result
+        }
+        Cli::Session { config } => {
+            let mut session = Session::load(&config)?;
+            settings::override_session(&mut session)?;
+            size_compile_pool(&session.wasmtime)?;
+            logging::init(session.logging.filter.as_deref());
+            let watchdog = Watchdog::new(session.timeout, OutputFormat::Human);
+            watchdog.run(session.stack_size, {
+                let watchdog = watchdog.clone();
+                move || run_session(&session, &watchdog)
+            })
+        }
+        Cli::Batch(opts) => {
+            logging::init(None);
+            let timeout = match (opts.timeout, &opts.manifest) {
+                (None, Some(manifest)) => Session::load(manifest)?.timeout,
+                (timeout, _) => timeout,
+            };
+            let watchdog = Watchdog::new(timeout, opts.output);
+            watchdog.run(None, {
+                let watchdog = watchdog.clone();
+                move || run_batch(opts, &watchdog)
+            })
+        }
+        Cli::Repl { wasm } => {
+            logging::init(None);
+            let runtime = Executor::from_file(&wasm)?.instantiate()?;
+            repl::run(&runtime)
+        }
+        Cli::Inspect { wasm } => {
+            logging::init(None);
+            let code = repro::read_wasm(&wasm)?;
+            inspect::inspect(&ExecutorBuilder::new().build(&code)?, &code)
+        }
+        Cli::Validate { wasm, method } => {
+            logging::init(None);
+            inspect::validate(&repro::new_store(&Config::new()), &wasm, method.as_deref())
+        }
+        Cli::Disasm { wasm, func } => disasm::disasm(&repro::read_wasm(&wasm)?, &func),
+        Cli::Analyze { dump } => coredump::analyze(&dump),
+        Cli::Precompile { run } => {
+            logging::init(None);
+            let settings = resolve_settings(&run)?;
+            if !settings.wasmtime.cache {
+                return Err(anyhow!("precompiling needs the cache, drop --no-cache"));
+            }
+            bench::precompile(run.executor_builder(&settings)?, &settings.wasm)
+        }
+        Cli::Diff { run, backends } => {
+            logging::init(None);
+            let settings = resolve_settings(&run)?;
+            diff::diff(
+                &run.executor_builder(&settings)?,
+                &backends,
+                &settings.wasm,
+                settings.method()?,
+                &settings.input,
+            )
+        }
+        Cli::Version { run } => {
+            logging::init(None);
+            let settings = resolve_settings(&run)?;
+            runtime_api::version(run.executor_builder(&settings)?, &settings.wasm)
+        }
+        Cli::Block { run, call } => {
+            logging::init(None);
+            let settings = resolve_settings(&run)?;
+            runtime_api::block(run.executor_builder(&settings)?, &settings.wasm, &call)
+        }
+        Cli::Metadata { run, out } => {
+            logging::init(None);
+            let settings = resolve_settings(&run)?;
+            runtime_api::metadata(
+                run.executor_builder(&settings)?,
+                &settings.wasm,
+                out.as_deref(),
+            )
+        }
+        Cli::Bench { run, iterations } => {
+            logging::init(None);
+            let settings = resolve_settings(&run)?;
+            bench::bench(
+                &run.executor_builder(&settings)?,
+                &settings.wasm,
+                settings.method()?,
+                &settings.input,
+                iterations,
+            )
+        }
+    }
 }
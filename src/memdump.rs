@@ -0,0 +1,49 @@
+use repro::module_info::{self, DataSegment};
+use repro::{ExecutorBuilder, HostContext};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Describes the raw dump next to it.
+#[derive(Serialize)]
+struct Index<'a> {
+    method: &'a str,
+    heap_base: u32,
+    memory_size: u32,
+    data_segments: &'a [DataSegment],
+}
+
+/// Dumps the linear memory to `memdump-<method>.bin`, along with a `memdump-<method>.json`
+/// index, whenever a call traps.
+pub fn on_trap(builder: ExecutorBuilder, wasm: &Path) -> anyhow::Result<ExecutorBuilder> {
+    let data_segments = module_info::data_segments(&repro::read_wasm(wasm)?)?;
+    Ok(builder.on_after_call(move |method, outcome, ctx| {
+        let trapped = match outcome {
+            Err(err) => err.is_trap(),
+            Ok(_) => false,
+        };
+        if trapped {
+            if let Err(err) = dump(method, ctx, &data_segments) {
+                log::warn!("failed to dump the memory: {:#}", err);
+            }
+        }
+    }))
+}
+
+fn dump(method: &str, ctx: &HostContext, data_segments: &[DataSegment]) -> anyhow::Result<()> {
+    let memory = ctx.memory();
+    let path = format!("memdump-{}.bin", method);
+    fs::write(&path, memory.read(0, memory.len())?)?;
+    let index = Index {
+        method,
+        heap_base: ctx.heap_base(),
+        memory_size: memory.len(),
+        data_segments,
+    };
+    fs::write(
+        format!("memdump-{}.json", method),
+        serde_json::to_string_pretty(&index)?,
+    )?;
+    eprintln!("memory dumped to {}", path);
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! The `ext_misc_*` host functions.
+
+use crate::{ExecutorBuilder, HostRegistry};
+use parity_scale_codec::Encode;
+
+host_fn! {
+    fn ext_misc_print_num_version_1(_ctx, value: u64) {
+        tracing::debug!(target: "runtime", "{}", value);
+        Ok(())
+    }
+}
+
+host_fn! {
+    fn ext_misc_print_utf8_version_1(ctx, utf8: u64) {
+        let data = ctx.read_packed(utf8)?;
+        if let Ok(message) = std::str::from_utf8(&data) {
+            tracing::debug!(target: "runtime", "{}", message);
+        }
+        Ok(())
+    }
+}
+
+host_fn! {
+    fn ext_misc_print_hex_version_1(ctx, data: u64) {
+        tracing::debug!(target: "runtime", "{}", hex::encode(ctx.read_packed(data)?));
+        Ok(())
+    }
+}
+
+host_fn! {
+    /// Instantiates the given runtime on the side and calls its `Core_version`.
+    fn ext_misc_runtime_version_version_1(ctx, wasm: u64) -> u64 {
+        let code = ctx.read_packed(wasm)?;
+        let version = ExecutorBuilder::new()
+            .build(&code)
+            .and_then(|executor| executor.call("Core_version", &[]));
+        if let Err(ref err) = version {
+            tracing::debug!(target: "runtime", "can't get the version of the runtime: {}", err);
+        }
+        ctx.allocate_packed(&version.ok().encode())
+    }
+}
+
+pub(crate) fn register(registry: &mut HostRegistry) {
+    registry.register_fn("ext_misc_print_num_version_1", ext_misc_print_num_version_1);
+    registry.register_fn(
+        "ext_misc_print_utf8_version_1",
+        ext_misc_print_utf8_version_1,
+    );
+    registry.register_fn("ext_misc_print_hex_version_1", ext_misc_print_hex_version_1);
+    registry.register_fn(
+        "ext_misc_runtime_version_version_1",
+        ext_misc_runtime_version_version_1,
+    );
+}
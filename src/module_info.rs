@@ -0,0 +1,237 @@
+//! Facts about a module that wasmtime doesn't expose, read from its binary.
+
+use crate::TraceVal;
+use anyhow::anyhow;
+use serde::Serialize;
+use wasmparser::{
+    BinaryReaderError, DataKind, ExternalKind, ImportSectionEntryType, InitExpr, ModuleReader,
+    Operator, SectionCode,
+};
+
+/// A data segment of the module.
+#[derive(Clone, Debug, Serialize)]
+pub struct DataSegment {
+    /// Where the segment is copied to, `None` for passive segments and offsets computed from
+    /// globals.
+    pub offset: Option<u32>,
+    pub len: u32,
+}
+
+fn parse_error(err: BinaryReaderError) -> anyhow::Error {
+    anyhow!("{} at offset {:#x}", err.message, err.offset)
+}
+
+/// The constant an initializer expression evaluates to, `None` if it reads a global.
+fn const_value(init_expr: &InitExpr) -> anyhow::Result<Option<TraceVal>> {
+    let mut operators = init_expr.get_operators_reader();
+    Ok(match operators.read().map_err(parse_error)? {
+        Operator::I32Const { value } => Some(TraceVal::I32(value)),
+        Operator::I64Const { value } => Some(TraceVal::I64(value)),
+        Operator::F32Const { value } => Some(TraceVal::F32(value.bits())),
+        Operator::F64Const { value } => Some(TraceVal::F64(value.bits())),
+        _ => None,
+    })
+}
+
+pub fn data_segments(code: &[u8]) -> anyhow::Result<Vec<DataSegment>> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    let mut segments = Vec::new();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        if !matches!(section.code, SectionCode::Data) {
+            continue;
+        }
+        for data in section.get_data_section_reader().map_err(parse_error)? {
+            let data = data.map_err(parse_error)?;
+            let offset = match data.kind {
+                DataKind::Active { init_expr, .. } => match const_value(&init_expr)? {
+                    Some(TraceVal::I32(value)) => Some(value as u32),
+                    _ => None,
+                },
+                DataKind::Passive => None,
+            };
+            segments.push(DataSegment {
+                offset,
+                len: data.data.len() as u32,
+            });
+        }
+    }
+    Ok(segments)
+}
+
+/// The initial value of every exported global, `None` for the ones only known once the module
+/// is instantiated: imported globals and the ones initialized from them.
+pub fn exported_global_inits(code: &[u8]) -> anyhow::Result<Vec<(String, Option<TraceVal>)>> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    let mut inits = Vec::new();
+    let mut exports = Vec::new();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        match section.code {
+            SectionCode::Import => {
+                for import in section.get_import_section_reader().map_err(parse_error)? {
+                    let import = import.map_err(parse_error)?;
+                    if matches!(import.ty, ImportSectionEntryType::Global(_)) {
+                        inits.push(None);
+                    }
+                }
+            }
+            SectionCode::Global => {
+                for global in section.get_global_section_reader().map_err(parse_error)? {
+                    let global = global.map_err(parse_error)?;
+                    inits.push(const_value(&global.init_expr)?);
+                }
+            }
+            SectionCode::Export => {
+                for export in section.get_export_section_reader().map_err(parse_error)? {
+                    let export = export.map_err(parse_error)?;
+                    if matches!(export.kind, ExternalKind::Global) {
+                        exports.push((export.field.to_string(), export.index as usize));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(exports
+        .into_iter()
+        .map(|(name, index)| (name, inits.get(index).copied().flatten()))
+        .collect())
+}
+
+/// A custom section of the module.
+#[derive(Clone, Debug, Serialize)]
+pub struct CustomSection {
+    pub name: String,
+    pub len: u32,
+}
+
+pub fn custom_sections(code: &[u8]) -> anyhow::Result<Vec<CustomSection>> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    let mut sections = Vec::new();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        if let SectionCode::Custom { name, .. } = section.code {
+            sections.push(CustomSection {
+                name: name.to_string(),
+                len: section.get_binary_reader().bytes_remaining() as u32,
+            });
+        }
+    }
+    Ok(sections)
+}
+
+/// Whether the module has the DWARF sections backtraces get source locations from.
+pub fn has_dwarf(sections: &[CustomSection]) -> bool {
+    sections
+        .iter()
+        .any(|section| section.name.starts_with(".debug_"))
+}
+
+/// A field of the `producers` section: the languages, tools or SDKs the module was built
+/// with, and their versions.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProducersField {
+    pub name: String,
+    pub values: Vec<(String, String)>,
+}
+
+/// The `producers` section, empty if the toolchain didn't emit one.
+pub fn producers(code: &[u8]) -> anyhow::Result<Vec<ProducersField>> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    let mut fields = Vec::new();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        if !matches!(
+            section.code,
+            SectionCode::Custom {
+                name: "producers",
+                ..
+            }
+        ) {
+            continue;
+        }
+        let mut data = section.get_binary_reader();
+        for _ in 0..data.read_var_u32().map_err(parse_error)? {
+            let name = data.read_string().map_err(parse_error)?.to_string();
+            let mut values = Vec::new();
+            for _ in 0..data.read_var_u32().map_err(parse_error)? {
+                let value = data.read_string().map_err(parse_error)?;
+                let version = data.read_string().map_err(parse_error)?;
+                values.push((value.to_string(), version.to_string()));
+            }
+            fields.push(ProducersField { name, values });
+        }
+    }
+    Ok(fields)
+}
+
+/// The contents of the custom section `name`.
+pub fn custom_section<'a>(code: &'a [u8], name: &str) -> anyhow::Result<Option<&'a [u8]>> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        match section.code {
+            SectionCode::Custom {
+                name: section_name, ..
+            } if section_name == name => {
+                let mut data = section.get_binary_reader();
+                let len = data.bytes_remaining();
+                return Ok(Some(data.read_bytes(len).map_err(parse_error)?));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// The size of a function defined by the module.
+#[derive(Clone, Debug, Serialize)]
+pub struct FunctionSize {
+    /// The index in the function index space, which counts the imported functions first.
+    pub func_index: u32,
+    /// The size of the whole body, declarations of the locals included.
+    pub body_len: u32,
+    /// The size of the instructions of the body.
+    pub code_len: u32,
+}
+
+/// The sizes of the functions the module defines, in the order of their indices.
+pub fn function_sizes(code: &[u8]) -> anyhow::Result<Vec<FunctionSize>> {
+    let mut reader = ModuleReader::new(code).map_err(parse_error)?;
+    let mut imported_funcs = 0;
+    let mut sizes = Vec::new();
+    while !reader.eof() {
+        let section = reader.read().map_err(parse_error)?;
+        match section.code {
+            SectionCode::Import => {
+                for import in section.get_import_section_reader().map_err(parse_error)? {
+                    let import = import.map_err(parse_error)?;
+                    if matches!(import.ty, ImportSectionEntryType::Function(_)) {
+                        imported_funcs += 1;
+                    }
+                }
+            }
+            SectionCode::Code => {
+                for (idx, body) in section
+                    .get_code_section_reader()
+                    .map_err(parse_error)?
+                    .into_iter()
+                    .enumerate()
+                {
+                    let body = body.map_err(parse_error)?;
+                    let reader = body.get_binary_reader();
+                    let end = reader.original_position() + reader.bytes_remaining();
+                    let operators = body.get_operators_reader().map_err(parse_error)?;
+                    sizes.push(FunctionSize {
+                        func_index: imported_funcs + idx as u32,
+                        body_len: reader.bytes_remaining() as u32,
+                        code_len: (end - operators.original_position()) as u32,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(sizes)
+}
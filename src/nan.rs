@@ -0,0 +1,83 @@
+//! NaN canonicalization by instrumenting the module, as the pinned wasmtime doesn't let
+//! cranelift's `enable_nan_canonicalization` through.
+//!
+//! Every float operation that may produce a NaN gets followed by a `select` replacing any NaN
+//! with the canonical one, through a scratch local appended to the function's locals.
+
+use crate::fuel::strip_dwarf;
+use anyhow::anyhow;
+use parity_wasm::elements::{Instruction, Local, Module, Type, ValueType};
+
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// The type of the result, if the instruction is an arithmetic one producing a float.
+fn float_result(instruction: &Instruction) -> Option<ValueType> {
+    use Instruction::*;
+    match instruction {
+        F32Add | F32Sub | F32Mul | F32Div | F32Sqrt | F32Min | F32Max | F32Ceil | F32Floor
+        | F32Trunc | F32Nearest | F32DemoteF64 => Some(ValueType::F32),
+        F64Add | F64Sub | F64Mul | F64Div | F64Sqrt | F64Min | F64Max | F64Ceil | F64Floor
+        | F64Trunc | F64Nearest | F64PromoteF32 => Some(ValueType::F64),
+        _ => None,
+    }
+}
+
+fn canonicalize(ty: ValueType, scratch: u32) -> Vec<Instruction> {
+    let (nan, ne) = match ty {
+        ValueType::F32 => (Instruction::F32Const(CANONICAL_NAN_F32), Instruction::F32Ne),
+        _ => (Instruction::F64Const(CANONICAL_NAN_F64), Instruction::F64Ne),
+    };
+    vec![
+        Instruction::SetLocal(scratch),
+        nan,
+        Instruction::GetLocal(scratch),
+        Instruction::GetLocal(scratch),
+        Instruction::GetLocal(scratch),
+        ne,
+        Instruction::Select,
+    ]
+}
+
+/// Canonicalizes the NaNs every function of `code` produces.
+pub(crate) fn canonicalize_nans(code: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut module: Module =
+        parity_wasm::deserialize_buffer(code).map_err(|err| anyhow!("{}", err))?;
+    strip_dwarf(&mut module);
+    let types = module
+        .type_section()
+        .map_or(Vec::new(), |types| types.types().to_vec());
+    let params = module.function_section().map_or(Vec::new(), |functions| {
+        functions
+            .entries()
+            .iter()
+            .map(|func| match types.get(func.type_ref() as usize) {
+                Some(Type::Function(ty)) => ty.params().len() as u32,
+                None => 0,
+            })
+            .collect()
+    });
+    if let Some(code) = module.code_section_mut() {
+        for (body, params) in code.bodies_mut().iter_mut().zip(params) {
+            let locals = params + body.locals().iter().map(Local::count).sum::<u32>();
+            let (scratch_f32, scratch_f64) = (locals, locals + 1);
+            body.locals_mut().push(Local::new(1, ValueType::F32));
+            body.locals_mut().push(Local::new(1, ValueType::F64));
+            let instructions = body.code_mut().elements_mut();
+            let mut canonicalized = Vec::with_capacity(instructions.len());
+            for instruction in instructions.drain(..) {
+                let ty = float_result(&instruction);
+                canonicalized.push(instruction);
+                if let Some(ty) = ty {
+                    let scratch = match ty {
+                        ValueType::F32 => scratch_f32,
+                        _ => scratch_f64,
+                    };
+                    canonicalized.extend(canonicalize(ty, scratch));
+                }
+            }
+            *instructions = canonicalized;
+        }
+    }
+    parity_wasm::serialize(module).map_err(|err| anyhow!("{}", err))
+}
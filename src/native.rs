@@ -0,0 +1,33 @@
+//! Calling the entry points of a native build of the runtime, which tells a bug in how the
+//! runtime is compiled to wasm from one in its logic.
+//!
+//! The library exports every entry point as
+//! `uint8_t *method(const uint8_t *input, size_t input_len, size_t *output_len)`, returning
+//! the output allocated with `malloc`, or null for no output. The native code doesn't go
+//! through the host functions, so only the outputs can be compared.
+
+use crate::dylib::Library;
+use anyhow::anyhow;
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::slice;
+
+type EntryPoint =
+    unsafe extern "C" fn(input: *const u8, input_len: usize, output_len: *mut usize) -> *mut u8;
+
+/// Calls the entry point `method_name` of the library at `library`.
+pub fn call(library: &Path, method_name: &str, input_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let fail = |message: String| anyhow!("{}: {}", library.display(), message);
+    let library = Library::open(library).map_err(fail)?;
+    let entry_point = library.symbol(&CString::new(method_name)?).map_err(fail)?;
+    let entry_point: EntryPoint = unsafe { std::mem::transmute(entry_point) };
+    let mut output_len = 0;
+    let output = unsafe { entry_point(input_data.as_ptr(), input_data.len(), &mut output_len) };
+    if output.is_null() {
+        return Ok(Vec::new());
+    }
+    let bytes = unsafe { slice::from_raw_parts(output, output_len) }.to_vec();
+    unsafe { libc::free(output as *mut c_void) };
+    Ok(bytes)
+}
@@ -0,0 +1,217 @@
+//! The `ext_offchain_*` host functions, answering with canned values from an
+//! [`OffchainConfig`].
+
+use crate::HostRegistry;
+use parity_scale_codec::Encode;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// What the offchain host functions respond with.
+#[derive(Clone, Default)]
+pub struct OffchainConfig {
+    pub is_validator: bool,
+    /// Milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub random_seed: [u8; 32],
+    /// Initial contents of the local storage. Persistent and local storage share it.
+    pub local_storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Handed out in order to the HTTP requests the runtime starts. Once they run out,
+    /// starting a request fails.
+    pub http_responses: Vec<HttpResponse>,
+}
+
+#[derive(Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(Vec<u8>, Vec<u8>)>,
+    pub body: Vec<u8>,
+}
+
+/// Mirrors `sp_core::offchain::HttpError`.
+#[allow(dead_code)]
+#[derive(Encode)]
+enum HttpError {
+    DeadlineReached = 1,
+    IoError = 2,
+    Invalid = 3,
+}
+
+/// Mirrors `sp_core::offchain::HttpRequestStatus`.
+#[allow(dead_code)]
+#[derive(Encode)]
+enum HttpRequestStatus {
+    DeadlineReached,
+    IoError,
+    Invalid,
+    Finished(u16),
+}
+
+struct Request {
+    response: HttpResponse,
+    body_read: usize,
+}
+
+struct Offchain {
+    config: OffchainConfig,
+    local_storage: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+    /// Indexed by the request id.
+    requests: RefCell<Vec<Request>>,
+}
+
+impl Offchain {
+    fn with_request<R>(&self, id: u32, f: impl FnOnce(&mut Request) -> R) -> Option<R> {
+        self.requests.borrow_mut().get_mut(id as usize).map(f)
+    }
+}
+
+pub(crate) fn register(config: &OffchainConfig, registry: &mut HostRegistry) {
+    let offchain = Rc::new(Offchain {
+        config: config.clone(),
+        local_storage: RefCell::new(config.local_storage.clone()),
+        requests: RefCell::new(Vec::new()),
+    });
+
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_is_validator_version_1",
+        host_fn!(move |_ctx| -> u32 { Ok(state.config.is_validator as u32) }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_timestamp_version_1",
+        host_fn!(move |_ctx| -> u64 { Ok(state.config.timestamp) }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_random_seed_version_1",
+        host_fn!(move |ctx| -> u32 { ctx.allocate_bytes(&state.config.random_seed) }),
+    );
+
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_local_storage_set_version_1",
+        host_fn!(move |ctx, _kind: u32, key: u64, value: u64| {
+            let (key, value) = (ctx.read_packed(key)?, ctx.read_packed(value)?);
+            state.local_storage.borrow_mut().insert(key, value);
+            Ok(())
+        }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_local_storage_get_version_1",
+        host_fn!(move |ctx, _kind: u32, key: u64| -> u64 {
+            let key = ctx.read_packed(key)?;
+            let value = state.local_storage.borrow().get(&key).cloned();
+            ctx.allocate_packed(&value.encode())
+        }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_local_storage_compare_and_set_version_1",
+        host_fn!(
+            move |ctx, _kind: u32, key: u64, old_value: u64, new_value: u64| -> u32 {
+                let key = ctx.read_packed(key)?;
+                let old_value: Option<Vec<u8>> = ctx.read_decoded(old_value)?;
+                let new_value = ctx.read_packed(new_value)?;
+                let mut local_storage = state.local_storage.borrow_mut();
+                if local_storage.get(&key) != old_value.as_ref() {
+                    return Ok(0);
+                }
+                local_storage.insert(key, new_value);
+                Ok(1)
+            }
+        ),
+    );
+
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_http_request_start_version_1",
+        host_fn!(move |ctx, method: u64, uri: u64, _meta: u64| -> u64 {
+            let method = String::from_utf8_lossy(&ctx.read_packed(method)?).into_owned();
+            let uri = String::from_utf8_lossy(&ctx.read_packed(uri)?).into_owned();
+            let mut requests = state.requests.borrow_mut();
+            let id = match state.config.http_responses.get(requests.len()) {
+                Some(response) => {
+                    let id = requests.len();
+                    tracing::debug!(target: "offchain", "request #{}: {} {}", id, method, uri);
+                    requests.push(Request {
+                        response: response.clone(),
+                        body_read: 0,
+                    });
+                    Ok(id as u16)
+                }
+                None => {
+                    tracing::debug!(target: "offchain", "no response left for {} {}", method, uri);
+                    Err(())
+                }
+            };
+            ctx.allocate_packed(&id.encode())
+        }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_http_request_add_header_version_1",
+        host_fn!(move |ctx, id: u32, _name: u64, _value: u64| -> u64 {
+            let result = state.with_request(id, |_| ()).ok_or(());
+            ctx.allocate_packed(&result.encode())
+        }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_http_request_write_body_version_1",
+        host_fn!(move |ctx, id: u32, _chunk: u64, _deadline: u64| -> u64 {
+            let result = state.with_request(id, |_| ()).ok_or(HttpError::Invalid);
+            ctx.allocate_packed(&result.encode())
+        }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_http_response_wait_version_1",
+        host_fn!(move |ctx, ids: u64, _deadline: u64| -> u64 {
+            let ids: Vec<u16> = ctx.read_decoded(ids)?;
+            let statuses = ids
+                .into_iter()
+                .map(|id| {
+                    state
+                        .with_request(id.into(), |request| {
+                            HttpRequestStatus::Finished(request.response.status)
+                        })
+                        .unwrap_or(HttpRequestStatus::Invalid)
+                })
+                .collect::<Vec<_>>();
+            ctx.allocate_packed(&statuses.encode())
+        }),
+    );
+    let state = offchain.clone();
+    registry.register_fn(
+        "ext_offchain_http_response_headers_version_1",
+        host_fn!(move |ctx, id: u32| -> u64 {
+            let headers = state
+                .with_request(id, |request| request.response.headers.clone())
+                .unwrap_or_default();
+            ctx.allocate_packed(&headers.encode())
+        }),
+    );
+    let state = offchain;
+    registry.register_fn(
+        "ext_offchain_http_response_read_body_version_1",
+        host_fn!(move |ctx, id: u32, buffer: u64, _deadline: u64| -> u64 {
+            let (buffer_ptr, buffer_len) = crate::unpack_ptr_and_len(buffer);
+            let chunk = state.with_request(id, |request| {
+                let rest = &request.response.body[request.body_read..];
+                let chunk = rest[..rest.len().min(buffer_len as usize)].to_vec();
+                request.body_read += chunk.len();
+                chunk
+            });
+            let result = match chunk {
+                Some(chunk) => {
+                    ctx.write_memory(buffer_ptr, &chunk)?;
+                    Ok(chunk.len() as u32)
+                }
+                None => Err(HttpError::Invalid),
+            };
+            ctx.allocate_packed(&result.encode())
+        }),
+    );
+}
@@ -0,0 +1,242 @@
+//! Host functions implemented by a shared library, declared in `include/repro_plugin.h`.
+//!
+//! The library exports `repro_plugin_init`, which is handed a table of the harness functions
+//! and registers its host functions through it. The host functions get the same table, to
+//! access the memory and the allocator of the instance. The library doesn't link against the
+//! harness, so it keeps working across builds of it as long as the table has the
+//! [`PLUGIN_API_VERSION`] it was written for.
+//!
+//! Params and results cross the boundary as the bits of the wasm values, zero extended to 64.
+//! A host function returns 0 on success, anything else fails the call, as does a failing
+//! call back into the harness.
+
+use crate::dylib::Library;
+use crate::{ExecError, HostContext, HostFunction, HostRegistry};
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::rc::Rc;
+use std::slice;
+use wasmtime::Val;
+
+/// The version of the table plugins are handed, bumped on every incompatible change.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+type PluginHostFn = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    api: *const HostApi,
+    ctx: *const PluginContext,
+    params: *const u64,
+    params_len: usize,
+    results: *mut u64,
+    results_len: usize,
+) -> i32;
+
+type InitFn = unsafe extern "C" fn(api: *const HostApi, registrar: *mut Registrar) -> i32;
+
+/// `ReproHostApi` in `include/repro_plugin.h`, which is maintained by hand.
+#[repr(C)]
+struct HostApi {
+    version: u32,
+    register_fn:
+        unsafe extern "C" fn(*mut Registrar, *const c_char, PluginHostFn, *mut c_void) -> i32,
+    read_memory: unsafe extern "C" fn(*const PluginContext, u32, *mut u8, u32) -> i32,
+    write_memory: unsafe extern "C" fn(*const PluginContext, u32, *const u8, u32) -> i32,
+    allocate: unsafe extern "C" fn(*const PluginContext, u32, *mut u32) -> i32,
+    deallocate: unsafe extern "C" fn(*const PluginContext, u32) -> i32,
+}
+
+static HOST_API: HostApi = HostApi {
+    version: PLUGIN_API_VERSION,
+    register_fn,
+    read_memory,
+    write_memory,
+    allocate,
+    deallocate,
+};
+
+/// What `repro_plugin_init` registers.
+#[derive(Default)]
+struct Registrar {
+    functions: Vec<(String, PluginHostFn, *mut c_void)>,
+    error: Option<String>,
+}
+
+/// The host context of the call, and the first failure of a call back into the harness.
+struct PluginContext {
+    ctx: HostContext,
+    error: RefCell<Option<ExecError>>,
+}
+
+impl PluginContext {
+    fn report(&self, result: Result<(), ExecError>) -> i32 {
+        match result {
+            Ok(()) => 0,
+            Err(err) => {
+                self.error.borrow_mut().get_or_insert(err);
+                1
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn register_fn(
+    registrar: *mut Registrar,
+    name: *const c_char,
+    function: PluginHostFn,
+    user_data: *mut c_void,
+) -> i32 {
+    let registrar = &mut *registrar;
+    match CStr::from_ptr(name).to_str() {
+        Ok(name) => {
+            registrar
+                .functions
+                .push((name.to_string(), function, user_data));
+            0
+        }
+        Err(_) => {
+            registrar
+                .error
+                .get_or_insert_with(|| "a host function name is not valid utf-8".to_string());
+            1
+        }
+    }
+}
+
+unsafe extern "C" fn read_memory(
+    ctx: *const PluginContext,
+    ptr: u32,
+    buf: *mut u8,
+    len: u32,
+) -> i32 {
+    let ctx = &*ctx;
+    ctx.report(ctx.ctx.read_memory(ptr, len).map(|bytes| {
+        if len > 0 {
+            slice::from_raw_parts_mut(buf, len as usize).copy_from_slice(&bytes);
+        }
+    }))
+}
+
+unsafe extern "C" fn write_memory(
+    ctx: *const PluginContext,
+    ptr: u32,
+    buf: *const u8,
+    len: u32,
+) -> i32 {
+    let ctx = &*ctx;
+    let bytes = match len {
+        0 => &[][..],
+        _ => slice::from_raw_parts(buf, len as usize),
+    };
+    ctx.report(ctx.ctx.write_memory(ptr, bytes))
+}
+
+unsafe extern "C" fn allocate(ctx: *const PluginContext, size: u32, ptr: *mut u32) -> i32 {
+    let ctx = &*ctx;
+    ctx.report(ctx.ctx.allocate(size).map(|allocated| *ptr = allocated))
+}
+
+unsafe extern "C" fn deallocate(ctx: *const PluginContext, ptr: u32) -> i32 {
+    let ctx = &*ctx;
+    ctx.report(ctx.ctx.deallocate(ptr))
+}
+
+fn to_bits(val: &Val) -> u64 {
+    match *val {
+        Val::I32(val) => val as u32 as u64,
+        Val::I64(val) => val as u64,
+        Val::F32(bits) => bits as u64,
+        Val::F64(bits) => bits,
+        _ => 0,
+    }
+}
+
+fn from_bits(bits: u64, result: &Val) -> Val {
+    match result {
+        Val::I32(_) => Val::I32(bits as u32 as i32),
+        Val::F32(_) => Val::F32(bits as u32),
+        Val::F64(_) => Val::F64(bits),
+        _ => Val::I64(bits as i64),
+    }
+}
+
+/// A host function of a plugin, which keeps the library loaded.
+struct PluginFn {
+    function: PluginHostFn,
+    user_data: *mut c_void,
+    _library: Rc<Library>,
+}
+
+impl HostFunction for PluginFn {
+    fn call(
+        &self,
+        ctx: &HostContext,
+        params: &[Val],
+        results: &mut [Val],
+    ) -> Result<(), ExecError> {
+        let params: Vec<_> = params.iter().map(to_bits).collect();
+        let mut bits = vec![0; results.len()];
+        let plugin_ctx = PluginContext {
+            ctx: ctx.clone(),
+            error: RefCell::new(None),
+        };
+        let code = unsafe {
+            (self.function)(
+                self.user_data,
+                &HOST_API,
+                &plugin_ctx,
+                params.as_ptr(),
+                params.len(),
+                bits.as_mut_ptr(),
+                bits.len(),
+            )
+        };
+        if let Some(err) = plugin_ctx.error.into_inner() {
+            return Err(err);
+        }
+        if code != 0 {
+            return Err(ExecError::PluginCall {
+                function: ctx.name().to_string(),
+                code,
+            });
+        }
+        for (result, bits) in results.iter_mut().zip(bits) {
+            *result = from_bits(bits, result);
+        }
+        Ok(())
+    }
+}
+
+/// Loads the shared library at `path` and registers the host functions its
+/// `repro_plugin_init` does, overriding the ones already registered under the same names.
+pub fn load_plugin(registry: &mut HostRegistry, path: &Path) -> Result<(), ExecError> {
+    let fail = |message: String| ExecError::Plugin {
+        path: path.to_path_buf(),
+        message,
+    };
+    let library = Rc::new(Library::open(path).map_err(fail)?);
+    let init =
+        CStr::from_bytes_with_nul(b"repro_plugin_init\0").expect("the name is nul terminated");
+    let init: InitFn = unsafe { std::mem::transmute(library.symbol(init).map_err(fail)?) };
+    let mut registrar = Registrar::default();
+    let code = unsafe { init(&HOST_API, &mut registrar) };
+    if let Some(message) = registrar.error {
+        return Err(fail(message));
+    }
+    if code != 0 {
+        return Err(fail(format!("`repro_plugin_init` returned {}", code)));
+    }
+    for (name, function, user_data) in registrar.functions {
+        tracing::debug!(target: "imports", "`{}` is implemented by {}", name, path.display());
+        registry.register(
+            &name,
+            PluginFn {
+                function,
+                user_data,
+                _library: library.clone(),
+            },
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! What the harness expects of a runtime, checked before instantiating it so that every
+//! problem is reported at once rather than the first one instantiation runs into.
+
+use crate::executor::type_list;
+use crate::{Executor, UnknownImportPolicy};
+use thiserror::Error;
+use wasmtime::{ExternType, ValType};
+
+/// A way the module doesn't fit the harness.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum Violation {
+    #[error("`memory` is imported from `{module}::{name}`, the harness needs it exported")]
+    ImportedMemory { module: String, name: String },
+    #[error("`memory` is not exported")]
+    MissingMemory,
+    #[error("can't provide import `{module}::{name}`, only function imports are supported")]
+    UnsupportedImport { module: String, name: String },
+    #[error("no host function is registered for import `{module}::{name}`")]
+    UnknownImport { module: String, name: String },
+    #[error("`{0}` is not exported as a function")]
+    MissingExport(String),
+    #[error("`{method}` is ({params}) -> ({results}), not (I32, I32) -> (I64)")]
+    NotRuntimeApi {
+        method: String,
+        params: String,
+        results: String,
+    },
+    #[error("`__heap_base` is not exported as an i32 global, the heap starts at {0} instead")]
+    MissingHeapBase(u32),
+}
+
+impl Violation {
+    /// Whether the call can't go ahead, rather than go ahead differently than it should.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, Violation::MissingHeapBase(_))
+    }
+}
+
+/// Everything about the module that doesn't fit the harness, including `method_name` not
+/// following the runtime API convention of taking the input as a pointer and a length and
+/// returning the output as both packed into an i64.
+pub fn check(executor: &Executor, method_name: Option<&str>) -> Vec<Violation> {
+    let module = executor.module();
+    let mut violations = Vec::new();
+    for import in module.imports() {
+        let module = import.module().to_string();
+        let name = import.name().to_string();
+        match import.ty() {
+            ExternType::Func(_) => {}
+            ExternType::Memory(_) => violations.push(Violation::ImportedMemory { module, name }),
+            _ => violations.push(Violation::UnsupportedImport { module, name }),
+        }
+    }
+    if executor.unknown_import_policy() == UnknownImportPolicy::Error {
+        violations.extend(executor.stubbed_imports().into_iter().map(|import| {
+            Violation::UnknownImport {
+                module: import.module().to_string(),
+                name: import.name().to_string(),
+            }
+        }));
+    }
+
+    let export = |name: &str| {
+        module
+            .exports()
+            .iter()
+            .find(|export| export.name() == name)
+            .map(|export| export.ty())
+    };
+    let imports_memory = violations
+        .iter()
+        .any(|violation| matches!(violation, Violation::ImportedMemory { .. }));
+    if !matches!(export("memory"), Some(ExternType::Memory(_))) && !imports_memory {
+        violations.push(Violation::MissingMemory);
+    }
+    if let Some(method_name) = method_name {
+        match export(method_name) {
+            Some(ExternType::Func(func_ty)) => {
+                if *func_ty.params() != [ValType::I32, ValType::I32]
+                    || *func_ty.results() != [ValType::I64]
+                {
+                    violations.push(Violation::NotRuntimeApi {
+                        method: method_name.to_string(),
+                        params: type_list(func_ty.params()),
+                        results: type_list(func_ty.results()),
+                    });
+                }
+            }
+            _ => violations.push(Violation::MissingExport(method_name.to_string())),
+        }
+    }
+    match export("__heap_base") {
+        Some(ExternType::Global(global_ty)) if *global_ty.content() == ValType::I32 => {}
+        _ => violations.push(Violation::MissingHeapBase(executor.heap_base)),
+    }
+    violations
+}
@@ -0,0 +1,78 @@
+use crate::input::{parse_hex, parse_num};
+use crate::report::to_hex;
+use anyhow::anyhow;
+use repro::Runtime;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  call <method> [input hex]   call an export with the given input
+  mem read <ptr> <len>        dump a range of linear memory
+  mem write <ptr> <hex>       overwrite a range of linear memory
+  alloc <size>                allocate on the runtime heap
+  free <ptr>                  free a heap allocation
+  help                        print this message
+  quit                        leave the repl";
+
+pub fn run(runtime: &Runtime) -> anyhow::Result<()> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => break,
+            ["help"] => println!("{}", HELP),
+            words => {
+                if let Err(err) = execute(runtime, words) {
+                    println!("error: {:#}", err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn execute(runtime: &Runtime, words: &[&str]) -> anyhow::Result<()> {
+    match words {
+        ["call", method] => call(runtime, method, &[]),
+        ["call", method, input] => call(runtime, method, &parse_hex(input)?.0),
+        ["mem", "read", ptr, len] => {
+            let ptr = parse_num(ptr)?;
+            let bytes = runtime.read_memory(ptr, parse_num(len)?)?;
+            print_hexdump(ptr, &bytes);
+            Ok(())
+        }
+        ["mem", "write", ptr, bytes] => runtime.write_memory(parse_num(ptr)?, &parse_hex(bytes)?.0),
+        ["alloc", size] => {
+            let ptr = runtime.allocate(parse_num(size)?)?;
+            println!("{:#x}", ptr);
+            Ok(())
+        }
+        ["free", ptr] => runtime.deallocate(parse_num(ptr)?),
+        _ => Err(anyhow!("unknown command, try `help`")),
+    }
+}
+
+fn call(runtime: &Runtime, method: &str, input: &[u8]) -> anyhow::Result<()> {
+    let output = runtime.call(method, input)?;
+    println!("returned {}", to_hex(&output));
+    Ok(())
+}
+
+fn print_hexdump(base: u32, bytes: &[u8]) {
+    for (idx, chunk) in bytes.chunks(16).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!("{:08x}  {}", base as usize + idx * 16, hex);
+    }
+}
@@ -0,0 +1,129 @@
+use repro::{ExecError, ExecutorBuilder, Frame, Runtime, TrapKind};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+
+/// Machine readable outcome of a single call, printed by `--output json`.
+#[derive(Serialize)]
+pub struct CallRecord {
+    pub method: String,
+    /// Hex encoded input data.
+    pub input: String,
+    /// Hex encoded bytes returned by the call, if it succeeded.
+    pub output: Option<String>,
+    /// The output decoded as the `--ret-type`.
+    pub decoded: Option<serde_json::Value>,
+    pub trap: Option<String>,
+    pub trap_kind: Option<TrapKind>,
+    /// The panic message and location the runtime logged before trapping.
+    pub panic: Option<String>,
+    /// Any failure that is not a trap, e.g. a link error or a missing export.
+    pub error: Option<String>,
+    pub backtrace: Vec<Frame>,
+    pub host_calls: usize,
+    /// The fuel the call consumed, if it was metered.
+    pub fuel_consumed: Option<u64>,
+    /// Whether NaNs were canonicalized, which decides if two records are comparable.
+    pub nan_canonicalization: bool,
+    /// How long compiling the module took, if it was compiled for this call.
+    pub compile_ms: Option<u64>,
+}
+
+impl CallRecord {
+    fn new(method: &str, input: &[u8]) -> Self {
+        Self {
+            method: method.to_string(),
+            input: to_hex(input),
+            output: None,
+            decoded: None,
+            trap: None,
+            trap_kind: None,
+            panic: None,
+            error: None,
+            backtrace: Vec::new(),
+            host_calls: 0,
+            fuel_consumed: None,
+            nan_canonicalization: false,
+            compile_ms: None,
+        }
+    }
+
+    fn set_result(&mut self, result: &Result<Vec<u8>, ExecError>) {
+        match result {
+            Ok(output) => self.output = Some(to_hex(output)),
+            Err(err @ ExecError::Trap { .. })
+            | Err(err @ ExecError::StackOverflow { .. })
+            | Err(err @ ExecError::OutOfFuel { .. })
+            | Err(err @ ExecError::HostPanic { .. }) => {
+                self.trap = Some(err.to_string());
+                self.panic = err.panic_message().map(str::to_string);
+                self.backtrace = err.backtrace().to_vec();
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        self.trap_kind = result.as_ref().err().and_then(ExecError::trap_kind);
+    }
+
+    /// The record of a call the watchdog gave up on, which can't report itself.
+    pub fn abandoned(method: &str, input: &[u8], err: &ExecError) -> Self {
+        let mut record = Self::new(method, input);
+        record.error = Some(err.to_string());
+        record
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.output.is_some()
+    }
+
+    pub fn print_json(&self) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string(self)?);
+        Ok(())
+    }
+}
+
+/// Loads and instantiates the runtime and performs the call, recording whatever happens.
+pub fn record_call(
+    builder: &ExecutorBuilder,
+    wasm: &Path,
+    method_name: &str,
+    input_data: &[u8],
+) -> (CallRecord, Result<Vec<u8>, ExecError>) {
+    let start = Instant::now();
+    let executor = builder.clone().build_from_file(wasm);
+    let compile_ms = start.elapsed().as_millis() as u64;
+    let runtime = executor.and_then(|executor| {
+        executor.preflight(method_name)?;
+        executor.instantiate()
+    });
+    let (mut record, result) = match runtime {
+        Ok(runtime) => record_runtime_call(&runtime, method_name, input_data),
+        Err(err) => {
+            let mut record = CallRecord::new(method_name, input_data);
+            let result = Err(err);
+            record.set_result(&result);
+            (record, result)
+        }
+    };
+    record.compile_ms = Some(compile_ms);
+    (record, result)
+}
+
+/// Performs the call against an existing instance, recording whatever happens.
+pub fn record_runtime_call(
+    runtime: &Runtime,
+    method_name: &str,
+    input_data: &[u8],
+) -> (CallRecord, Result<Vec<u8>, ExecError>) {
+    let mut record = CallRecord::new(method_name, input_data);
+    let host_calls_before = runtime.host_calls();
+    let result = runtime.call(method_name, input_data);
+    record.host_calls = runtime.host_calls() - host_calls_before;
+    record.fuel_consumed = runtime.fuel_consumed();
+    record.nan_canonicalization = runtime.nan_canonicalization();
+    record.set_result(&result);
+    (record, result)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
@@ -0,0 +1,209 @@
+//! Calls of the runtime APIs every substrate runtime implements.
+
+use crate::cli::BlockCall;
+use crate::report::to_hex;
+use anyhow::anyhow;
+use parity_scale_codec::Decode;
+use repro::{module_info, Executor, ExecutorBuilder, Runtime};
+use std::fs;
+use std::path::Path;
+
+/// The magic number `RuntimeMetadataPrefixed` starts with, `meta` in little endian.
+const METADATA_MAGIC: u32 = 0x6174_656d;
+
+/// The ids of the runtime APIs substrate itself declares, the blake2-64 of their names.
+const KNOWN_APIS: &[([u8; 8], &str)] = &[
+    ([0xdf, 0x6a, 0xcb, 0x68, 0x99, 0x07, 0x60, 0x9b], "Core"),
+    ([0x37, 0xe3, 0x97, 0xfc, 0x7c, 0x91, 0xf5, 0xe4], "Metadata"),
+    (
+        [0x40, 0xfe, 0x3a, 0xd4, 0x01, 0xf8, 0x95, 0x9a],
+        "BlockBuilder",
+    ),
+    (
+        [0xd2, 0xbc, 0x98, 0x97, 0xee, 0xd0, 0x8f, 0x15],
+        "TaggedTransactionQueue",
+    ),
+    (
+        [0xf7, 0x8b, 0x27, 0x8b, 0xe5, 0x3f, 0x45, 0x4c],
+        "OffchainWorkerApi",
+    ),
+    (
+        [0xab, 0x3c, 0x05, 0x72, 0x29, 0x1f, 0xeb, 0x8b],
+        "SessionKeys",
+    ),
+    (
+        [0xed, 0x99, 0xc5, 0xac, 0xb2, 0x5e, 0xed, 0xf5],
+        "GrandpaApi",
+    ),
+    ([0xcb, 0xca, 0x25, 0xe3, 0x9f, 0x14, 0x23, 0x87], "BabeApi"),
+    ([0xdd, 0x71, 0x8d, 0x5c, 0xc5, 0x32, 0x62, 0xd4], "AuraApi"),
+    (
+        [0xbc, 0x9d, 0x89, 0x90, 0x4f, 0x5b, 0x92, 0x3f],
+        "AccountNonceApi",
+    ),
+    (
+        [0x68, 0x7a, 0xd4, 0x4a, 0xd3, 0x7f, 0x03, 0xc2],
+        "AuthorityDiscoveryApi",
+    ),
+];
+
+/// `sp_version::RuntimeVersion` as of the pinned substrate.
+#[derive(Decode)]
+pub struct RuntimeVersion {
+    pub spec_name: String,
+    pub impl_name: String,
+    pub authoring_version: u32,
+    pub spec_version: u32,
+    pub impl_version: u32,
+    pub apis: Vec<([u8; 8], u32)>,
+}
+
+/// The name of the runtime API with the given id, if substrate declares it.
+pub fn api_name(id: &[u8; 8]) -> Option<&'static str> {
+    KNOWN_APIS
+        .iter()
+        .find(|(known, _)| known == id)
+        .map(|&(_, name)| name)
+}
+
+fn print_api(id: &[u8; 8], api_version: u32) {
+    match api_name(id) {
+        Some(name) => println!("  0x{} {} v{}", hex::encode(id), name, api_version),
+        None => println!("  0x{} v{}", hex::encode(id), api_version),
+    }
+}
+
+fn print_version(version: &RuntimeVersion) {
+    println!(
+        "{} {} ({} {}, authoring version {})",
+        version.spec_name,
+        version.spec_version,
+        version.impl_name,
+        version.impl_version,
+        version.authoring_version
+    );
+    println!("runtime APIs:");
+    for (id, api_version) in &version.apis {
+        print_api(id, *api_version);
+    }
+}
+
+/// Calls `Core_version` and prints what the runtime declares itself to be.
+pub fn version(builder: ExecutorBuilder, wasm: &Path) -> anyhow::Result<()> {
+    let output = builder.build_from_file(wasm)?.call("Core_version", &[])?;
+    let version = RuntimeVersion::decode(&mut &output[..])
+        .map_err(|err| anyhow!("can't decode the runtime version: {}", err))?;
+    print_version(&version);
+    Ok(())
+}
+
+/// The base name and version of a versioned host function, `ext_storage_get_version_1`.
+fn split_version(name: &str) -> Option<(&str, u32)> {
+    let idx = name.rfind("_version_")?;
+    let version = name[idx + "_version_".len()..].parse().ok()?;
+    Some((&name[..idx], version))
+}
+
+/// Prints what the runtime declares in its `runtime_version` custom section, or failing that
+/// the APIs of its `runtime_apis` one, without calling into it. Then warns about the host
+/// functions it imports in a newer version than the harness implements.
+pub fn print_declared(executor: &Executor, code: &[u8]) -> anyhow::Result<()> {
+    if let Some(section) = module_info::custom_section(code, "runtime_version")? {
+        let version = RuntimeVersion::decode(&mut &section[..])
+            .map_err(|err| anyhow!("can't decode the `runtime_version` section: {}", err))?;
+        print_version(&version);
+    } else if let Some(section) = module_info::custom_section(code, "runtime_apis")? {
+        // Every API adds its id and little endian version to the section.
+        if section.len() % 12 != 0 {
+            return Err(anyhow!(
+                "the `runtime_apis` section is {} bytes, not a multiple of 12",
+                section.len()
+            ));
+        }
+        println!("runtime APIs:");
+        for entry in section.chunks(12) {
+            let mut id = [0; 8];
+            id.copy_from_slice(&entry[..8]);
+            let mut api_version = [0; 4];
+            api_version.copy_from_slice(&entry[8..]);
+            print_api(&id, u32::from_le_bytes(api_version));
+        }
+    } else {
+        println!("runtime APIs: not declared in custom sections, `version` asks the runtime");
+    }
+
+    for import in executor.module().imports() {
+        let (base, version) = match split_version(import.name()) {
+            Some(split) if !executor.implements(import.name()) => split,
+            _ => continue,
+        };
+        let implemented = (1..version)
+            .rev()
+            .find(|older| executor.implements(&format!("{}_version_{}", base, older)));
+        if let Some(implemented) = implemented {
+            println!(
+                "warning: the runtime imports `{}`, the harness only implements up to version {}",
+                import.name(),
+                implemented
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the metadata through `Metadata_metadata`, printing its version and optionally
+/// saving it.
+///
+/// The metadata of this substrate describes the pallets only, not the runtime APIs, so it
+/// can't type the input of a call. `--input-json` takes the types explicitly instead.
+pub fn metadata(builder: ExecutorBuilder, wasm: &Path, out: Option<&Path>) -> anyhow::Result<()> {
+    let output = builder
+        .build_from_file(wasm)?
+        .call("Metadata_metadata", &[])?;
+    let metadata = Vec::<u8>::decode(&mut &output[..])
+        .map_err(|err| anyhow!("can't decode the opaque metadata: {}", err))?;
+    let (magic, version) = match metadata.get(..5) {
+        Some(prefix) => (
+            u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]),
+            prefix[4],
+        ),
+        None => return Err(anyhow!("the metadata is only {} bytes", metadata.len())),
+    };
+    if magic != METADATA_MAGIC {
+        return Err(anyhow!(
+            "the metadata starts with {:#010x}, not `meta`",
+            magic
+        ));
+    }
+    println!("metadata v{}, {} bytes", version, metadata.len());
+    if let Some(out) = out {
+        fs::write(out, &metadata)?;
+    }
+    Ok(())
+}
+
+fn initialize_block(runtime: &Runtime, header: &Path) -> anyhow::Result<()> {
+    runtime.call("Core_initialize_block", &fs::read(header)?)?;
+    println!("initialized the block of {}", header.display());
+    Ok(())
+}
+
+/// Performs the block import or authoring calls.
+pub fn block(builder: ExecutorBuilder, wasm: &Path, call: &BlockCall) -> anyhow::Result<()> {
+    let runtime = builder.build_from_file(wasm)?.instantiate()?;
+    match call {
+        BlockCall::Execute { block } => {
+            runtime.call("Core_execute_block", &fs::read(block)?)?;
+            println!("executed {}", block.display());
+        }
+        BlockCall::Initialize { header } => initialize_block(&runtime, header)?,
+        BlockCall::ApplyExtrinsic { header, extrinsics } => {
+            initialize_block(&runtime, header)?;
+            for extrinsic in extrinsics {
+                let output = runtime.call("BlockBuilder_apply_extrinsic", &fs::read(extrinsic)?)?;
+                println!("applied {}: {}", extrinsic.display(), to_hex(&output));
+            }
+        }
+    }
+    Ok(())
+}
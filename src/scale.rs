@@ -0,0 +1,392 @@
+//! SCALE values of a type given by name, e.g. `Vec<(u32, Option<bool>)>`, as JSON.
+//!
+//! Byte vectors and arrays are hex strings and 128-bit integers decimal strings, the rest
+//! maps onto JSON the obvious way. When encoding, integers can be strings as well and byte
+//! vectors arrays. `Result`s are `{"Ok": ..}` or `{"Err": ..}`.
+
+use anyhow::anyhow;
+use parity_scale_codec::{Compact, Decode, Encode, Input};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScaleType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    /// A compact encoded unsigned integer of the inner type.
+    Compact(Box<ScaleType>),
+    Str,
+    Vec(Box<ScaleType>),
+    Option(Box<ScaleType>),
+    Result(Box<ScaleType>, Box<ScaleType>),
+    Tuple(Vec<ScaleType>),
+    Array(Box<ScaleType>, usize),
+}
+
+impl fmt::Display for ScaleType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScaleType::Bool => f.write_str("bool"),
+            ScaleType::U8 => f.write_str("u8"),
+            ScaleType::U16 => f.write_str("u16"),
+            ScaleType::U32 => f.write_str("u32"),
+            ScaleType::U64 => f.write_str("u64"),
+            ScaleType::U128 => f.write_str("u128"),
+            ScaleType::I8 => f.write_str("i8"),
+            ScaleType::I16 => f.write_str("i16"),
+            ScaleType::I32 => f.write_str("i32"),
+            ScaleType::I64 => f.write_str("i64"),
+            ScaleType::I128 => f.write_str("i128"),
+            ScaleType::Compact(inner) => write!(f, "Compact<{}>", inner),
+            ScaleType::Str => f.write_str("String"),
+            ScaleType::Vec(item) => write!(f, "Vec<{}>", item),
+            ScaleType::Option(inner) => write!(f, "Option<{}>", inner),
+            ScaleType::Result(ok, err) => write!(f, "Result<{}, {}>", ok, err),
+            ScaleType::Tuple(items) => {
+                let items: Vec<_> = items.iter().map(ToString::to_string).collect();
+                match items.len() {
+                    1 => write!(f, "({},)", items[0]),
+                    _ => write!(f, "({})", items.join(", ")),
+                }
+            }
+            ScaleType::Array(item, len) => write!(f, "[{}; {}]", item, len),
+        }
+    }
+}
+
+impl FromStr for ScaleType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { rest: s };
+        let ty = parser.ty()?;
+        match parser.rest.trim() {
+            "" => Ok(ty),
+            rest => Err(anyhow!("unexpected `{}` after the type", rest)),
+        }
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> anyhow::Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(anyhow!("expected `{}` at `{}`", token, self.rest))
+        }
+    }
+
+    fn ident(&mut self) -> &'a str {
+        self.rest = self.rest.trim_start();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or_else(|| self.rest.len());
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        ident
+    }
+
+    fn generic(&mut self) -> anyhow::Result<Box<ScaleType>> {
+        self.expect("<")?;
+        let inner = self.ty()?;
+        self.expect(">")?;
+        Ok(Box::new(inner))
+    }
+
+    fn ty(&mut self) -> anyhow::Result<ScaleType> {
+        if self.eat("(") {
+            let mut items = Vec::new();
+            while !self.eat(")") {
+                items.push(self.ty()?);
+                if !self.eat(",") {
+                    self.expect(")")?;
+                    break;
+                }
+            }
+            return Ok(ScaleType::Tuple(items));
+        }
+        if self.eat("[") {
+            let item = self.ty()?;
+            self.expect(";")?;
+            let len = self.ident().parse()?;
+            self.expect("]")?;
+            return Ok(ScaleType::Array(Box::new(item), len));
+        }
+        let ty = match self.ident() {
+            "bool" => ScaleType::Bool,
+            "u8" => ScaleType::U8,
+            "u16" => ScaleType::U16,
+            "u32" => ScaleType::U32,
+            "u64" => ScaleType::U64,
+            "u128" => ScaleType::U128,
+            "i8" => ScaleType::I8,
+            "i16" => ScaleType::I16,
+            "i32" => ScaleType::I32,
+            "i64" => ScaleType::I64,
+            "i128" => ScaleType::I128,
+            "String" | "str" => ScaleType::Str,
+            "Compact" => {
+                let inner = self.generic()?;
+                match *inner {
+                    ScaleType::U8
+                    | ScaleType::U16
+                    | ScaleType::U32
+                    | ScaleType::U64
+                    | ScaleType::U128 => ScaleType::Compact(inner),
+                    _ => return Err(anyhow!("only unsigned integers are compact, not {}", inner)),
+                }
+            }
+            "Vec" => ScaleType::Vec(self.generic()?),
+            "Option" => ScaleType::Option(self.generic()?),
+            "Result" => {
+                self.expect("<")?;
+                let ok = self.ty()?;
+                self.expect(",")?;
+                let err = self.ty()?;
+                self.expect(">")?;
+                ScaleType::Result(Box::new(ok), Box::new(err))
+            }
+            "" => return Err(anyhow!("expected a type at `{}`", self.rest)),
+            name => return Err(anyhow!("unknown type `{}`", name)),
+        };
+        Ok(ty)
+    }
+}
+
+fn decode_len(input: &mut &[u8]) -> Result<usize, parity_scale_codec::Error> {
+    Ok(Compact::<u32>::decode(input)?.0 as usize)
+}
+
+fn decode_items(
+    item: &ScaleType,
+    len: usize,
+    input: &mut &[u8],
+) -> Result<Value, parity_scale_codec::Error> {
+    if *item == ScaleType::U8 {
+        if len > input.len() {
+            return Err("not enough data to fill the byte vector".into());
+        }
+        let mut bytes = vec![0; len];
+        input.read(&mut bytes)?;
+        return Ok(Value::String(format!("0x{}", hex::encode(bytes))));
+    }
+    let items = (0..len)
+        .map(|_| decode(item, input))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Array(items))
+}
+
+/// Decodes one value of `ty` off the front of `input`.
+pub fn decode(ty: &ScaleType, input: &mut &[u8]) -> Result<Value, parity_scale_codec::Error> {
+    let value = match ty {
+        ScaleType::Bool => json!(bool::decode(input)?),
+        ScaleType::U8 => json!(u8::decode(input)?),
+        ScaleType::U16 => json!(u16::decode(input)?),
+        ScaleType::U32 => json!(u32::decode(input)?),
+        ScaleType::U64 => json!(u64::decode(input)?),
+        ScaleType::U128 => json!(u128::decode(input)?.to_string()),
+        ScaleType::I8 => json!(i8::decode(input)?),
+        ScaleType::I16 => json!(i16::decode(input)?),
+        ScaleType::I32 => json!(i32::decode(input)?),
+        ScaleType::I64 => json!(i64::decode(input)?),
+        ScaleType::I128 => json!(i128::decode(input)?.to_string()),
+        ScaleType::Compact(inner) => {
+            let value = Compact::<u128>::decode(input)?.0;
+            match **inner {
+                ScaleType::U128 => json!(value.to_string()),
+                _ => json!(value as u64),
+            }
+        }
+        ScaleType::Str => json!(String::decode(input)?),
+        ScaleType::Vec(item) => {
+            let len = decode_len(input)?;
+            decode_items(item, len, input)?
+        }
+        // `Option<bool>` is a single byte of its own.
+        ScaleType::Option(inner) if **inner == ScaleType::Bool => match input.read_byte()? {
+            0 => Value::Null,
+            1 => json!(true),
+            2 => json!(false),
+            _ => return Err("invalid Option<bool>".into()),
+        },
+        ScaleType::Option(inner) => match input.read_byte()? {
+            0 => Value::Null,
+            1 => decode(inner, input)?,
+            _ => return Err("invalid Option".into()),
+        },
+        ScaleType::Result(ok, err) => match input.read_byte()? {
+            0 => json!({ "Ok": decode(ok, input)? }),
+            1 => json!({ "Err": decode(err, input)? }),
+            _ => return Err("invalid Result".into()),
+        },
+        ScaleType::Tuple(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| decode(item, input))
+                .collect::<Result<_, _>>()?,
+        ),
+        ScaleType::Array(item, len) => decode_items(item, *len, input)?,
+    };
+    Ok(value)
+}
+
+/// Decodes `bytes` as exactly one value of `ty`.
+pub fn decode_all(ty: &ScaleType, bytes: &[u8]) -> anyhow::Result<Value> {
+    let mut input = bytes;
+    let value = decode(ty, &mut input)
+        .map_err(|err| anyhow!("can't decode the output as {}: {}", ty, err))?;
+    match input.len() {
+        0 => Ok(value),
+        left => Err(anyhow!(
+            "{} bytes are left over after decoding the output as {}",
+            left,
+            ty
+        )),
+    }
+}
+
+fn expected(ty: &ScaleType, value: &Value) -> anyhow::Error {
+    anyhow!("expected a {}, got {}", ty, value)
+}
+
+fn to_u128(ty: &ScaleType, value: &Value) -> anyhow::Result<u128> {
+    match value {
+        Value::Number(number) => number.as_u64().map(u128::from),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| expected(ty, value))
+}
+
+fn to_i128(ty: &ScaleType, value: &Value) -> anyhow::Result<i128> {
+    match value {
+        Value::Number(number) => number.as_i64().map(i128::from),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| expected(ty, value))
+}
+
+fn encode_uint<T: TryFrom<u128> + Encode>(
+    ty: &ScaleType,
+    value: &Value,
+) -> anyhow::Result<Vec<u8>> {
+    let int =
+        T::try_from(to_u128(ty, value)?).map_err(|_| anyhow!("{} overflows a {}", value, ty))?;
+    Ok(int.encode())
+}
+
+fn encode_int<T: TryFrom<i128> + Encode>(ty: &ScaleType, value: &Value) -> anyhow::Result<Vec<u8>> {
+    let int =
+        T::try_from(to_i128(ty, value)?).map_err(|_| anyhow!("{} overflows a {}", value, ty))?;
+    Ok(int.encode())
+}
+
+/// The items of a vector or array: a hex string for bytes, an array otherwise.
+fn encode_items(
+    ty: &ScaleType,
+    item: &ScaleType,
+    value: &Value,
+) -> anyhow::Result<(usize, Vec<u8>)> {
+    if let (ScaleType::U8, Value::String(s)) = (item, value) {
+        let bytes = hex::decode(s.trim_start_matches("0x"))?;
+        return Ok((bytes.len(), bytes));
+    }
+    let items = value.as_array().ok_or_else(|| expected(ty, value))?;
+    let mut encoded = Vec::new();
+    for value in items {
+        encoded.extend(encode(item, value)?);
+    }
+    Ok((items.len(), encoded))
+}
+
+/// Encodes the JSON `value` as a `ty`, the inverse of [`decode`].
+pub fn encode(ty: &ScaleType, value: &Value) -> anyhow::Result<Vec<u8>> {
+    let encoded = match ty {
+        ScaleType::Bool => value.as_bool().ok_or_else(|| expected(ty, value))?.encode(),
+        ScaleType::U8 => encode_uint::<u8>(ty, value)?,
+        ScaleType::U16 => encode_uint::<u16>(ty, value)?,
+        ScaleType::U32 => encode_uint::<u32>(ty, value)?,
+        ScaleType::U64 => encode_uint::<u64>(ty, value)?,
+        ScaleType::U128 => encode_uint::<u128>(ty, value)?,
+        ScaleType::I8 => encode_int::<i8>(ty, value)?,
+        ScaleType::I16 => encode_int::<i16>(ty, value)?,
+        ScaleType::I32 => encode_int::<i32>(ty, value)?,
+        ScaleType::I64 => encode_int::<i64>(ty, value)?,
+        ScaleType::I128 => encode_int::<i128>(ty, value)?,
+        ScaleType::Compact(_) => Compact(to_u128(ty, value)?).encode(),
+        ScaleType::Str => value.as_str().ok_or_else(|| expected(ty, value))?.encode(),
+        ScaleType::Vec(item) => {
+            let (len, items) = encode_items(ty, item, value)?;
+            let mut encoded = Compact(len as u32).encode();
+            encoded.extend(items);
+            encoded
+        }
+        ScaleType::Option(inner) if **inner == ScaleType::Bool => match value {
+            Value::Null => vec![0],
+            Value::Bool(true) => vec![1],
+            Value::Bool(false) => vec![2],
+            _ => return Err(expected(ty, value)),
+        },
+        ScaleType::Option(inner) => match value {
+            Value::Null => vec![0],
+            value => {
+                let mut encoded = vec![1];
+                encoded.extend(encode(inner, value)?);
+                encoded
+            }
+        },
+        ScaleType::Result(ok, err) => {
+            let (variant, ty, value) = match (value.get("Ok"), value.get("Err")) {
+                (Some(value), None) => (0, ok, value),
+                (None, Some(value)) => (1, err, value),
+                _ => return Err(expected(ty, value)),
+            };
+            let mut encoded = vec![variant];
+            encoded.extend(encode(ty, value)?);
+            encoded
+        }
+        ScaleType::Tuple(items) => match value.as_array() {
+            Some(values) if values.len() == items.len() => {
+                let mut encoded = Vec::new();
+                for (item, value) in items.iter().zip(values) {
+                    encoded.extend(encode(item, value)?);
+                }
+                encoded
+            }
+            _ => return Err(expected(ty, value)),
+        },
+        ScaleType::Array(item, len) => match encode_items(ty, item, value)? {
+            (items, encoded) if items == *len => encoded,
+            _ => return Err(expected(ty, value)),
+        },
+    };
+    Ok(encoded)
+}
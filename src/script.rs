@@ -0,0 +1,173 @@
+//! Host functions implemented by a Rhai script.
+//!
+//! Every function the script defines becomes the host function of the same name. Its params
+//! are passed as integers and it must evaluate to an integer, which becomes the result if the
+//! host function has one. The script can use the following helpers:
+//!
+//! - `mem_read(ptr, len)`: the memory at `ptr..ptr + len`, hex encoded,
+//! - `mem_write(ptr, hex)`: writes the hex encoded bytes at `ptr`,
+//! - `read_str(ptr, len)`: the memory at `ptr..ptr + len` as a string,
+//! - `alloc(size)`: allocates `size` bytes and returns the pointer,
+//! - `alloc_hex(hex)`: copies the hex encoded bytes into a fresh allocation and returns the
+//!   pointer and length packed into one integer,
+//! - `pack(ptr, len)`, `ptr_of(packed)`, `len_of(packed)`: packing of pointers and lengths.
+//!
+//! ```rhai
+//! fn ext_offchain_timestamp_version_1() { 1579000000000 }
+//!
+//! fn ext_storage_get_version_1(key) {
+//!     print("get " + mem_read(ptr_of(key), len_of(key)));
+//!     alloc_hex("00")
+//! }
+//! ```
+
+use crate::{pack_ptr_and_len, unpack_ptr_and_len, ExecError, HostContext, HostRegistry};
+use rhai::{Engine, RegisterFn, Scope};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasmtime::Val;
+
+/// State the helpers operate on while a scripted host function runs.
+#[derive(Default)]
+struct Current {
+    ctx: Option<HostContext>,
+    /// The first failure of a helper, reported once the script returns.
+    error: Option<ExecError>,
+}
+
+type Shared = Rc<RefCell<Current>>;
+
+fn helper<R: Default>(current: &Shared, f: impl FnOnce(&HostContext) -> Result<R, ExecError>) -> R {
+    let mut current = current.borrow_mut();
+    let result = match current.ctx {
+        Some(ref ctx) => f(ctx),
+        None => return R::default(),
+    };
+    result.unwrap_or_else(|err| {
+        current.error.get_or_insert(err);
+        R::default()
+    })
+}
+
+fn decode_hex(function: &str, hex: &str) -> Result<Vec<u8>, ExecError> {
+    hex::decode(hex.trim_start_matches("0x")).map_err(|err| ExecError::Script {
+        function: function.to_string(),
+        message: format!("invalid hex `{}`: {}", hex, err),
+    })
+}
+
+fn register_helpers(engine: &mut Engine, current: &Shared) {
+    let state = current.clone();
+    engine.register_fn("mem_read", move |ptr: i64, len: i64| -> String {
+        helper(&state, |ctx| {
+            ctx.read_memory(ptr as u32, len as u32).map(hex::encode)
+        })
+    });
+    let state = current.clone();
+    engine.register_fn("mem_write", move |ptr: i64, bytes: String| -> i64 {
+        helper(&state, |ctx| {
+            ctx.write_memory(ptr as u32, &decode_hex(ctx.name(), &bytes)?)
+                .map(|()| 0)
+        })
+    });
+    let state = current.clone();
+    engine.register_fn("read_str", move |ptr: i64, len: i64| -> String {
+        helper(&state, |ctx| ctx.read_string(ptr as u32, len as u32))
+    });
+    let state = current.clone();
+    engine.register_fn("alloc", move |size: i64| -> i64 {
+        helper(&state, |ctx| ctx.allocate(size as u32).map(i64::from))
+    });
+    let state = current.clone();
+    engine.register_fn("alloc_hex", move |bytes: String| -> i64 {
+        helper(&state, |ctx| {
+            ctx.allocate_packed(&decode_hex(ctx.name(), &bytes)?)
+                .map(|packed| packed as i64)
+        })
+    });
+    engine.register_fn("pack", |ptr: i64, len: i64| -> i64 {
+        pack_ptr_and_len(ptr as u32, len as u32) as i64
+    });
+    engine.register_fn("ptr_of", |packed: i64| -> i64 {
+        unpack_ptr_and_len(packed as u64).0.into()
+    });
+    engine.register_fn("len_of", |packed: i64| -> i64 {
+        unpack_ptr_and_len(packed as u64).1.into()
+    });
+}
+
+/// The names of the functions the script defines.
+fn defined_functions(script: &str) -> Vec<String> {
+    script
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| line.starts_with("fn "))
+        .filter_map(|line| line["fn ".len()..].split('(').next())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn param_to_int(val: &Val) -> i64 {
+    match *val {
+        Val::I32(val) => val.into(),
+        Val::I64(val) => val,
+        Val::F32(bits) => bits.into(),
+        Val::F64(bits) => bits as i64,
+        _ => 0,
+    }
+}
+
+fn int_to_result(val: i64, result: &Val) -> Val {
+    match result {
+        Val::I32(_) => Val::I32(val as i32),
+        Val::F32(_) => Val::F32(val as u32),
+        Val::F64(_) => Val::F64(val as u64),
+        _ => Val::I64(val),
+    }
+}
+
+/// Registers a host function for every function defined by `script`.
+pub fn register_script(registry: &mut HostRegistry, script: &str) {
+    let current = Shared::default();
+    let mut engine = Engine::new();
+    register_helpers(&mut engine, &current);
+    let engine = Rc::new(RefCell::new(engine));
+    let script: Rc<str> = script.into();
+
+    for function in defined_functions(&script) {
+        let (engine, current, script) = (engine.clone(), current.clone(), script.clone());
+        registry.register_fn(
+            &function.clone(),
+            move |ctx: &HostContext, params: &[Val], results: &mut [Val]| {
+                let mut scope = Scope::new();
+                let mut args = Vec::new();
+                for (idx, param) in params.iter().enumerate() {
+                    scope.push(format!("p{}", idx), param_to_int(param));
+                    args.push(format!("p{}", idx));
+                }
+                let source = format!("{}\n{}({})", script, function, args.join(", "));
+
+                *current.borrow_mut() = Current {
+                    ctx: Some(ctx.clone()),
+                    error: None,
+                };
+                let ret = engine
+                    .borrow_mut()
+                    .eval_with_scope::<i64>(&mut scope, &source);
+                let current = std::mem::take(&mut *current.borrow_mut());
+                if let Some(err) = current.error {
+                    return Err(err);
+                }
+                let ret = ret.map_err(|err| ExecError::Script {
+                    function: function.clone(),
+                    message: err.to_string(),
+                })?;
+                if let Some(result) = results.first_mut() {
+                    *result = int_to_result(ret, result);
+                }
+                Ok(())
+            },
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Resolution of the knobs used to perform a call.
+//!
+//! Every knob is looked up in the following places, the first one that provides a value wins:
+//!
+//! 1. the command line,
+//! 2. the `REPRO_WASM`, `REPRO_HEAP_BASE`, `REPRO_METHOD` and `REPRO_INPUT` environment
+//!    variables,
+//! 3. the session file passed with `--config`,
+//! 4. the built-in defaults.
+
+use crate::config::{Offchain, Session, WasmtimeFlags, DEFAULT_HEAP_BASE, DEFAULT_WASM};
+use crate::input::parse_hex;
+use anyhow::{anyhow, Context};
+use repro::{AllocatorKind, ExecutorBuilder};
+use std::env::{self, VarError};
+use std::path::{Path, PathBuf};
+
+/// Values provided by a single source. `None` means the source doesn't say anything.
+#[derive(Default)]
+pub struct Layer {
+    pub wasm: Option<PathBuf>,
+    pub heap_base: Option<u32>,
+    pub method: Option<String>,
+    pub input: Option<Vec<u8>>,
+}
+
+impl Layer {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let heap_base = env_var("REPRO_HEAP_BASE")?
+            .map(|s| s.parse())
+            .transpose()
+            .context("invalid REPRO_HEAP_BASE")?;
+        let input = env_var("REPRO_INPUT")?
+            .map(|s| parse_hex(&s).map(|bytes| bytes.0))
+            .transpose()
+            .context("invalid REPRO_INPUT")?;
+        Ok(Self {
+            wasm: env::var_os("REPRO_WASM").map(PathBuf::from),
+            heap_base,
+            method: env_var("REPRO_METHOD")?,
+            input,
+        })
+    }
+
+    /// The session provides the wasm and heap base, and its first call the method and input.
+    fn from_session(session: &Session) -> Self {
+        let first_call = session.calls.first();
+        Self {
+            wasm: Some(session.wasm.clone()),
+            heap_base: Some(session.heap_base),
+            method: first_call.map(|call| call.method.clone()),
+            input: first_call.map(|call| call.input.clone()),
+        }
+    }
+
+    /// Fills in whatever this layer lacks from a lower precedence one.
+    pub fn or(self, lower: Layer) -> Layer {
+        Layer {
+            wasm: self.wasm.or(lower.wasm),
+            heap_base: self.heap_base.or(lower.heap_base),
+            method: self.method.or(lower.method),
+            input: self.input.or(lower.input),
+        }
+    }
+}
+
+pub struct Settings {
+    pub wasm: PathBuf,
+    pub heap_base: u32,
+    pub method: Option<String>,
+    pub input: Vec<u8>,
+    pub allocator: AllocatorKind,
+    pub wasmtime: WasmtimeFlags,
+    pub offchain: Offchain,
+}
+
+impl Settings {
+    pub fn executor_builder(&self) -> anyhow::Result<ExecutorBuilder> {
+        Ok(ExecutorBuilder::new()
+            .wasmtime_config(self.wasmtime.to_config()?)
+            .nan_canonicalization(self.wasmtime.nan_canonicalization)
+            .heap_base(self.heap_base)
+            .allocator(self.allocator)
+            .offchain(self.offchain.to_config()?))
+    }
+
+    pub fn method(&self) -> anyhow::Result<&str> {
+        self.method
+            .as_deref()
+            .ok_or_else(|| anyhow!("no method given, pass `--method` or set REPRO_METHOD"))
+    }
+}
+
+/// Resolves the settings given the command line layer and an optional session file.
+pub fn resolve(cli: Layer, config: Option<&Path>) -> anyhow::Result<Settings> {
+    let (file, allocator, wasmtime, offchain) = match config {
+        Some(path) => {
+            let session = Session::load(path)?;
+            (
+                Layer::from_session(&session),
+                session.allocator,
+                session.wasmtime,
+                session.offchain,
+            )
+        }
+        None => (
+            Layer::default(),
+            AllocatorKind::default(),
+            WasmtimeFlags::default(),
+            Offchain::default(),
+        ),
+    };
+    let layer = cli.or(Layer::from_env()?).or(file);
+    Ok(Settings {
+        wasm: layer.wasm.unwrap_or_else(|| PathBuf::from(DEFAULT_WASM)),
+        heap_base: layer.heap_base.unwrap_or(DEFAULT_HEAP_BASE),
+        method: layer.method,
+        input: layer.input.unwrap_or_default(),
+        allocator,
+        wasmtime,
+        offchain,
+    })
+}
+
+/// Lets the environment override the wasm and heap base of a session file.
+pub fn override_session(session: &mut Session) -> anyhow::Result<()> {
+    let env = Layer::from_env()?;
+    if let Some(wasm) = env.wasm {
+        session.wasm = wasm;
+    }
+    if let Some(heap_base) = env.heap_base {
+        session.heap_base = heap_base;
+    }
+    Ok(())
+}
+
+fn env_var(name: &str) -> anyhow::Result<Option<String>> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(err) => Err(anyhow!("{}: {}", name, err)),
+    }
+}
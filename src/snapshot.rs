@@ -0,0 +1,66 @@
+use crate::MemCtx;
+use std::fmt;
+
+/// Differences closer than this are reported as one range.
+const MERGE_GAP: usize = 8;
+
+/// A copy of the linear memory from `base` upwards.
+#[derive(Clone)]
+pub struct MemorySnapshot {
+    base: u32,
+    bytes: Vec<u8>,
+}
+
+/// A run of bytes that differ between two snapshots.
+#[derive(Clone, Debug)]
+pub struct ChangedRange {
+    pub offset: u32,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+impl MemorySnapshot {
+    pub fn take(memory: &MemCtx, base: u32) -> Self {
+        let bytes = memory
+            .read(base, memory.len().saturating_sub(base))
+            .unwrap_or_default();
+        Self { base, bytes }
+    }
+
+    /// The ranges changed between `self` and the later snapshot `after`. Memory grown in
+    /// between counts as zeroes before.
+    pub fn diff(&self, after: &MemorySnapshot) -> Vec<ChangedRange> {
+        let before_at = |idx: usize| self.bytes.get(idx).copied().unwrap_or(0);
+        let mut changed: Vec<(usize, usize)> = Vec::new();
+        for (idx, &byte) in after.bytes.iter().enumerate() {
+            if byte == before_at(idx) {
+                continue;
+            }
+            match changed.last_mut() {
+                Some((_, end)) if idx - *end <= MERGE_GAP => *end = idx + 1,
+                _ => changed.push((idx, idx + 1)),
+            }
+        }
+        changed
+            .into_iter()
+            .map(|(start, end)| ChangedRange {
+                offset: self.base + start as u32,
+                before: (start..end).map(before_at).collect(),
+                after: after.bytes[start..end].to_vec(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for ChangedRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#x}..+{}: {} -> {}",
+            self.offset,
+            self.after.len(),
+            hex::encode(&self.before),
+            hex::encode(&self.after)
+        )
+    }
+}
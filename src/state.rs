@@ -0,0 +1,141 @@
+use crate::allocator::InstrumentedAllocator;
+use crate::backend::ExecBackend;
+use crate::interpreter::{from_val, to_val};
+use crate::module_info::DataSegment;
+use crate::ExecError;
+use anyhow::anyhow;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Instant;
+use wasmi::{GlobalRef, MemoryRef};
+use wasmtime::{Global, Memory, Val};
+
+/// The linear memory of an instance, under whichever backend runs it.
+pub(crate) enum LinearMemory {
+    Wasmtime(Memory),
+    Wasmi(MemoryRef),
+}
+
+impl LinearMemory {
+    /// The size in wasm pages.
+    pub(crate) fn pages(&self) -> u32 {
+        match self {
+            LinearMemory::Wasmtime(memory) => memory.size(),
+            LinearMemory::Wasmi(memory) => memory.current_size().0 as u32,
+        }
+    }
+
+    pub(crate) fn with_data<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        match self {
+            LinearMemory::Wasmtime(memory) => f(unsafe { memory.data_unchecked_mut() }),
+            LinearMemory::Wasmi(memory) => memory.with_direct_access_mut(f),
+        }
+    }
+}
+
+/// An exported global, under whichever backend runs the instance.
+#[derive(Clone)]
+pub(crate) enum ExportedGlobal {
+    Wasmtime(Global),
+    Wasmi(GlobalRef),
+}
+
+impl ExportedGlobal {
+    pub(crate) fn get(&self) -> Val {
+        match self {
+            ExportedGlobal::Wasmtime(global) => global.get(),
+            ExportedGlobal::Wasmi(global) => to_val(global.get()),
+        }
+    }
+
+    pub(crate) fn set(&self, val: Val) -> anyhow::Result<()> {
+        match self {
+            ExportedGlobal::Wasmtime(global) => global.set(val),
+            ExportedGlobal::Wasmi(global) => global
+                .set(from_val(&val)?)
+                .map_err(|err| anyhow!("{}", err)),
+        }
+    }
+}
+
+/// The host side state of an instance, shared by all of its imports.
+///
+/// wasmtime offers neither a `Caller` handing host functions the exports of the calling
+/// instance nor a `Linker` defining them after instantiation, so the imports exist before the
+/// memory they operate on does. It is bound once, right after instantiation.
+pub(crate) struct InstanceState {
+    memory: RefCell<Option<LinearMemory>>,
+    pub(crate) globals: RefCell<Vec<(String, ExportedGlobal)>>,
+    pub(crate) data_segments: Rc<Vec<DataSegment>>,
+    /// The fuel every call starts with, if the module is metered.
+    pub(crate) fuel: Option<u64>,
+    pub(crate) allocator: RefCell<InstrumentedAllocator>,
+    /// The number of host functions invoked since instantiation.
+    pub(crate) host_calls: Cell<usize>,
+    /// The error of the failing host function, more precise than the trap it turns into.
+    pub(crate) host_error: RefCell<Option<ExecError>>,
+    /// The last error the runtime logged, which is where its panic handler puts the message.
+    pub(crate) panic_message: RefCell<Option<String>>,
+    /// When the current call times out, checked on every host call.
+    pub(crate) deadline: Cell<Option<Instant>>,
+    /// The host function being called, left behind by the one that didn't return.
+    pub(crate) pending_host_call: RefCell<Option<String>>,
+}
+
+impl InstanceState {
+    pub(crate) fn new(
+        allocator: InstrumentedAllocator,
+        data_segments: Rc<Vec<DataSegment>>,
+        fuel: Option<u64>,
+    ) -> Rc<Self> {
+        Rc::new(Self {
+            memory: RefCell::new(None),
+            globals: RefCell::new(Vec::new()),
+            data_segments,
+            fuel,
+            allocator: RefCell::new(allocator),
+            host_calls: Cell::new(0),
+            host_error: RefCell::new(None),
+            panic_message: RefCell::new(None),
+            pending_host_call: RefCell::new(None),
+            deadline: Cell::new(None),
+        })
+    }
+
+    /// Resolves the exported memory and globals of the instance this is the state of.
+    pub(crate) fn bind(&self, instance: &dyn ExecBackend) -> Result<(), ExecError> {
+        let memory = instance.memory().ok_or(ExecError::MissingMemory)?;
+        *self.memory.borrow_mut() = Some(memory);
+        *self.globals.borrow_mut() = instance
+            .exports()
+            .into_iter()
+            .filter_map(|name| {
+                let global = instance.global(&name)?;
+                Some((name, global))
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// The exported global `name`.
+    pub(crate) fn global(&self, name: &str) -> Option<ExportedGlobal> {
+        self.globals
+            .borrow()
+            .iter()
+            .find(|(global_name, _)| global_name == name)
+            .map(|(_, global)| global.clone())
+    }
+
+    pub(crate) fn with_memory<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&LinearMemory) -> R,
+    {
+        let memory = self.memory.borrow();
+        f(memory
+            .as_ref()
+            .expect("the memory is bound right after instantiation"))
+    }
+}
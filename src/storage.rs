@@ -0,0 +1,288 @@
+use crate::HostRegistry;
+use parity_scale_codec::Encode;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::rc::Rc;
+
+type Map = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Prefix of the keys under which the roots of the default child tries are kept.
+#[cfg(feature = "trie")]
+const CHILD_STORAGE_KEY_PREFIX: &[u8] = b":child_storage:default:";
+
+/// In-memory storage backing the `ext_storage_*` and `ext_default_child_storage_*` host
+/// functions.
+///
+/// It is shared by all the instances of an executor, so changes made by one call are seen by
+/// the next. Keep a clone around to look at the contents after the calls.
+#[derive(Clone, Default)]
+pub struct Storage {
+    top: Rc<RefCell<Map>>,
+    /// Keyed by the child storage key, without the prefix.
+    children: Rc<RefCell<BTreeMap<Vec<u8>, Map>>>,
+}
+
+fn next_key(map: &Map, key: &[u8]) -> Option<Vec<u8>> {
+    map.range::<[u8], _>((Bound::Excluded(key), Bound::Unbounded))
+        .next()
+        .map(|(key, _)| key.clone())
+}
+
+impl Storage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.top.borrow().get(key).cloned()
+    }
+
+    pub fn set(&self, key: Vec<u8>, value: Vec<u8>) {
+        self.top.borrow_mut().insert(key, value);
+    }
+
+    pub fn clear(&self, key: &[u8]) {
+        self.top.borrow_mut().remove(key);
+    }
+
+    /// The first key after `key` in lexicographic order.
+    pub fn next_key(&self, key: &[u8]) -> Option<Vec<u8>> {
+        next_key(&self.top.borrow(), key)
+    }
+
+    /// A snapshot of everything in the top level storage.
+    pub fn contents(&self) -> Map {
+        self.top.borrow().clone()
+    }
+
+    /// The trie root of the top level storage, including the roots of the child tries.
+    #[cfg(feature = "trie")]
+    pub fn root(&self) -> Vec<u8> {
+        let mut contents = self.contents();
+        for (storage_key, child) in self.children.borrow().iter() {
+            if !child.is_empty() {
+                let prefixed_key = [CHILD_STORAGE_KEY_PREFIX, storage_key].concat();
+                contents.insert(prefixed_key, crate::trie::root(child.clone()).to_vec());
+            }
+        }
+        crate::trie::root(contents).to_vec()
+    }
+
+    /// Without the `trie` feature there is no trie to compute the root with, so it is all
+    /// zeroes.
+    #[cfg(not(feature = "trie"))]
+    pub fn root(&self) -> Vec<u8> {
+        vec![0; 32]
+    }
+
+    pub fn child_get(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+        self.children
+            .borrow()
+            .get(storage_key)
+            .and_then(|child| child.get(key).cloned())
+    }
+
+    pub fn child_set(&self, storage_key: Vec<u8>, key: Vec<u8>, value: Vec<u8>) {
+        self.children
+            .borrow_mut()
+            .entry(storage_key)
+            .or_default()
+            .insert(key, value);
+    }
+
+    pub fn child_clear(&self, storage_key: &[u8], key: &[u8]) {
+        if let Some(child) = self.children.borrow_mut().get_mut(storage_key) {
+            child.remove(key);
+        }
+    }
+
+    pub fn child_clear_prefix(&self, storage_key: &[u8], prefix: &[u8]) {
+        if let Some(child) = self.children.borrow_mut().get_mut(storage_key) {
+            child.retain(|key, _| !key.starts_with(prefix));
+        }
+    }
+
+    /// Removes the whole child trie.
+    pub fn kill_child(&self, storage_key: &[u8]) {
+        self.children.borrow_mut().remove(storage_key);
+    }
+
+    pub fn child_next_key(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+        self.children
+            .borrow()
+            .get(storage_key)
+            .and_then(|child| next_key(child, key))
+    }
+
+    /// A snapshot of the child trie, empty if it doesn't exist.
+    pub fn child_contents(&self, storage_key: &[u8]) -> Map {
+        self.children
+            .borrow()
+            .get(storage_key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The storage keys of the child tries.
+    pub fn children(&self) -> Vec<Vec<u8>> {
+        self.children.borrow().keys().cloned().collect()
+    }
+
+    /// The trie root of the child trie.
+    #[cfg(feature = "trie")]
+    pub fn child_root(&self, storage_key: &[u8]) -> Vec<u8> {
+        crate::trie::root(self.child_contents(storage_key)).to_vec()
+    }
+
+    #[cfg(not(feature = "trie"))]
+    pub fn child_root(&self, _storage_key: &[u8]) -> Vec<u8> {
+        vec![0; 32]
+    }
+
+    pub(crate) fn register(&self, registry: &mut HostRegistry) {
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_storage_get_version_1",
+            host_fn!(move |ctx, key: u64| -> u64 {
+                let key = ctx.read_packed(key)?;
+                ctx.allocate_packed(&storage.get(&key).encode())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_storage_set_version_1",
+            host_fn!(move |ctx, key: u64, value: u64| {
+                storage.set(ctx.read_packed(key)?, ctx.read_packed(value)?);
+                Ok(())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_storage_clear_version_1",
+            host_fn!(move |ctx, key: u64| {
+                storage.clear(&ctx.read_packed(key)?);
+                Ok(())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_storage_exists_version_1",
+            host_fn!(move |ctx, key: u64| -> u32 {
+                Ok(storage.get(&ctx.read_packed(key)?).is_some() as u32)
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_storage_next_key_version_1",
+            host_fn!(move |ctx, key: u64| -> u64 {
+                let key = ctx.read_packed(key)?;
+                ctx.allocate_packed(&storage.next_key(&key).encode())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_storage_root_version_1",
+            host_fn!(move |ctx| -> u64 { ctx.allocate_packed(&storage.root()) }),
+        );
+        registry.register_fn(
+            "ext_storage_changes_root_version_1",
+            host_fn!(move |ctx, _parent_hash: u64| -> u64 {
+                // Changes tries are not supported, same as a chain that has them disabled.
+                ctx.allocate_packed(&None::<Vec<u8>>.encode())
+            }),
+        );
+        self.register_child(registry);
+    }
+
+    fn register_child(&self, registry: &mut HostRegistry) {
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_get_version_1",
+            host_fn!(move |ctx, storage_key: u64, key: u64| -> u64 {
+                let (storage_key, key) = (ctx.read_packed(storage_key)?, ctx.read_packed(key)?);
+                ctx.allocate_packed(&storage.child_get(&storage_key, &key).encode())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_read_version_1",
+            host_fn!(
+                move |ctx, storage_key: u64, key: u64, value_out: u64, offset: u32| -> u64 {
+                    let (storage_key, key) = (ctx.read_packed(storage_key)?, ctx.read_packed(key)?);
+                    let read = match storage.child_get(&storage_key, &key) {
+                        Some(value) => {
+                            let (out_ptr, out_len) = crate::unpack_ptr_and_len(value_out);
+                            let rest = value.get(offset as usize..).unwrap_or_default();
+                            let written = rest.len().min(out_len as usize);
+                            ctx.write_memory(out_ptr, &rest[..written])?;
+                            Some(rest.len() as u32)
+                        }
+                        None => None,
+                    };
+                    ctx.allocate_packed(&read.encode())
+                }
+            ),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_set_version_1",
+            host_fn!(move |ctx, storage_key: u64, key: u64, value: u64| {
+                storage.child_set(
+                    ctx.read_packed(storage_key)?,
+                    ctx.read_packed(key)?,
+                    ctx.read_packed(value)?,
+                );
+                Ok(())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_clear_version_1",
+            host_fn!(move |ctx, storage_key: u64, key: u64| {
+                storage.child_clear(&ctx.read_packed(storage_key)?, &ctx.read_packed(key)?);
+                Ok(())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_clear_prefix_version_1",
+            host_fn!(move |ctx, storage_key: u64, prefix: u64| {
+                storage
+                    .child_clear_prefix(&ctx.read_packed(storage_key)?, &ctx.read_packed(prefix)?);
+                Ok(())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_storage_kill_version_1",
+            host_fn!(move |ctx, storage_key: u64| {
+                storage.kill_child(&ctx.read_packed(storage_key)?);
+                Ok(())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_exists_version_1",
+            host_fn!(move |ctx, storage_key: u64, key: u64| -> u32 {
+                let (storage_key, key) = (ctx.read_packed(storage_key)?, ctx.read_packed(key)?);
+                Ok(storage.child_get(&storage_key, &key).is_some() as u32)
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_next_key_version_1",
+            host_fn!(move |ctx, storage_key: u64, key: u64| -> u64 {
+                let (storage_key, key) = (ctx.read_packed(storage_key)?, ctx.read_packed(key)?);
+                ctx.allocate_packed(&storage.child_next_key(&storage_key, &key).encode())
+            }),
+        );
+        let storage = self.clone();
+        registry.register_fn(
+            "ext_default_child_storage_root_version_1",
+            host_fn!(move |ctx, storage_key: u64| -> u64 {
+                ctx.allocate_packed(&storage.child_root(&ctx.read_packed(storage_key)?))
+            }),
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Names and source locations of the functions of a module, taken from its `name` and DWARF
+//! custom sections.
+
+use crate::Frame;
+use std::collections::HashMap;
+use wasmparser::{BinaryReaderError, ModuleReader, Name, SectionCode};
+
+/// Resolves function indices to their demangled names and the `file:line` they are declared
+/// at.
+#[derive(Default)]
+pub struct Symbolizer {
+    names: HashMap<u32, String>,
+    locations: HashMap<u32, String>,
+}
+
+impl Symbolizer {
+    /// Parses the name and DWARF sections of `code`. Functions they don't cover, or all of them
+    /// if a section fails to parse, are left without a name or location.
+    pub fn new(code: &[u8]) -> Self {
+        let names = function_names(code).unwrap_or_else(|err| {
+            tracing::warn!(
+                "can't parse the name section at offset {:#x}: {}",
+                err.offset,
+                err.message
+            );
+            HashMap::new()
+        });
+        let locations = crate::dwarf::function_locations(code).unwrap_or_else(|err| {
+            tracing::warn!("can't parse the DWARF sections: {:#}", err);
+            HashMap::new()
+        });
+        Self { names, locations }
+    }
+
+    pub fn name(&self, func_index: u32) -> Option<&str> {
+        self.names.get(&func_index).map(String::as_str)
+    }
+
+    /// Where the function is declared, as `file:line`.
+    pub fn location(&self, func_index: u32) -> Option<&str> {
+        self.locations.get(&func_index).map(String::as_str)
+    }
+
+    /// Fills in the names and source locations of the frames.
+    pub fn symbolize(&self, frames: &mut [Frame]) {
+        for frame in frames {
+            frame.name = self.name(frame.func_index).map(str::to_string);
+            frame.location = self.location(frame.func_index).map(str::to_string);
+        }
+    }
+}
+
+fn function_names(code: &[u8]) -> Result<HashMap<u32, String>, BinaryReaderError> {
+    let mut reader = ModuleReader::new(code)?;
+    let mut names = HashMap::new();
+    while !reader.eof() {
+        let section = reader.read()?;
+        if !matches!(section.code, SectionCode::Custom { name: "name", .. }) {
+            continue;
+        }
+        for name in section.get_name_section_reader()? {
+            if let Name::Function(functions) = name? {
+                let mut map = functions.get_map()?;
+                for _ in 0..map.get_count() {
+                    let naming = map.read()?;
+                    let demangled = format!("{:#}", rustc_demangle::demangle(naming.name));
+                    names.insert(naming.index, demangled);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
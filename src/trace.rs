@@ -0,0 +1,181 @@
+//! Recording of host calls, see [`ExecutorBuilder::trace_host_calls`].
+//!
+//! [`ExecutorBuilder::trace_host_calls`]: crate::ExecutorBuilder::trace_host_calls
+
+use crate::{unpack_ptr_and_len, ExecError, HostContext};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wasmtime::{AnyRef, Val};
+
+/// Pointers longer than this are not decoded.
+const MAX_DECODED_LEN: u32 = 1024;
+
+/// A wasm value as it appears in a trace. Floats are kept as their bits.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceVal {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    /// A reference, or any other value that isn't a number. What it refers to isn't recorded.
+    Ref,
+}
+
+impl From<&Val> for TraceVal {
+    fn from(val: &Val) -> Self {
+        match *val {
+            Val::I32(val) => TraceVal::I32(val),
+            Val::I64(val) => TraceVal::I64(val),
+            Val::F32(val) => TraceVal::F32(val),
+            Val::F64(val) => TraceVal::F64(val),
+            _ => TraceVal::Ref,
+        }
+    }
+}
+
+impl From<TraceVal> for Val {
+    fn from(val: TraceVal) -> Self {
+        match val {
+            TraceVal::I32(val) => Val::I32(val),
+            TraceVal::I64(val) => Val::I64(val),
+            TraceVal::F32(val) => Val::F32(val),
+            TraceVal::F64(val) => Val::F64(val),
+            TraceVal::Ref => Val::AnyRef(AnyRef::null()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HostCallRecord {
+    pub function: String,
+    pub params: Vec<TraceVal>,
+    /// For every `i64` param, the memory behind the pointer and length it packs: as a string
+    /// if it is valid utf-8, hex otherwise.
+    pub decoded: Vec<Option<String>>,
+    pub results: Vec<TraceVal>,
+    /// The message the host call trapped with, in which case `results` is empty.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Microseconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+impl HostCallRecord {
+    fn new(ctx: &HostContext, params: &[Val], outcome: Result<&[Val], &str>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_micros() as u64);
+        Self {
+            function: ctx.name().to_string(),
+            params: params.iter().map(TraceVal::from).collect(),
+            decoded: params.iter().map(|param| decode(ctx, param)).collect(),
+            results: outcome
+                .map(|results| results.iter().map(TraceVal::from).collect())
+                .unwrap_or_default(),
+            error: outcome.err().map(str::to_string),
+            timestamp,
+        }
+    }
+}
+
+/// The memory behind the pointer and length an `i64` param packs, see
+/// [`HostCallRecord::decoded`].
+pub(crate) fn decode(ctx: &HostContext, param: &Val) -> Option<String> {
+    let (ptr, len) = unpack_ptr_and_len(param.i64()? as u64);
+    if len > MAX_DECODED_LEN {
+        return None;
+    }
+    let bytes = ctx.read_memory(ptr, len).ok()?;
+    Some(String::from_utf8(bytes).unwrap_or_else(|err| hex::encode(err.as_bytes())))
+}
+
+/// The host calls performed by the runtime, in order. Unlike the executor it is `Send`, so
+/// that the records can be read from another thread while a call is stuck.
+#[derive(Clone, Default)]
+pub struct HostCallTrace {
+    records: Arc<Mutex<Vec<HostCallRecord>>>,
+}
+
+impl HostCallTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&self, ctx: &HostContext, params: &[Val], outcome: Result<&[Val], &str>) {
+        self.records
+            .lock()
+            .unwrap()
+            .push(HostCallRecord::new(ctx, params, outcome));
+    }
+
+    pub fn records(&self) -> Vec<HostCallRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// The last `count` records, or all of them if there are fewer.
+    pub fn last(&self, count: usize) -> Vec<HostCallRecord> {
+        let records = self.records.lock().unwrap();
+        records[records.len().saturating_sub(count)..].to_vec()
+    }
+
+    /// Writes the records as a JSON array.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let records = self.records.lock().unwrap();
+        fs::write(path, serde_json::to_vec_pretty(&*records)?)?;
+        Ok(())
+    }
+
+    /// Reads the records written by [`HostCallTrace::save`].
+    pub fn load(path: &Path) -> anyhow::Result<Vec<HostCallRecord>> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+}
+
+/// Feeds recorded results back to the runtime, checking that it makes the same host calls
+/// in the same order.
+pub(crate) struct Replay {
+    records: Vec<HostCallRecord>,
+    next: Cell<usize>,
+}
+
+impl Replay {
+    pub(crate) fn new(records: Vec<HostCallRecord>) -> Self {
+        Self {
+            records,
+            next: Cell::new(0),
+        }
+    }
+
+    /// The record of the next host call, which must match the one being made.
+    pub(crate) fn next(
+        &self,
+        function: &str,
+        params: &[Val],
+    ) -> Result<&HostCallRecord, ExecError> {
+        let index = self.next.get();
+        let params = params.iter().map(TraceVal::from).collect::<Vec<_>>();
+        let actual = format!("{}{:?}", function, params);
+        let record = self
+            .records
+            .get(index)
+            .ok_or_else(|| ExecError::ReplayDivergence {
+                index,
+                expected: "the end of the trace".to_string(),
+                actual: actual.clone(),
+            })?;
+        if record.function != function || record.params != params {
+            return Err(ExecError::ReplayDivergence {
+                index,
+                expected: format!("{}{:?}", record.function, record.params),
+                actual,
+            });
+        }
+        self.next.set(index + 1);
+        Ok(record)
+    }
+}
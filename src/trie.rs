@@ -0,0 +1,37 @@
+//! The `ext_trie_*` host functions, computing roots of blake2-256 Patricia-Merkle tries.
+
+use crate::HostRegistry;
+use sp_core::Blake2Hasher;
+use sp_trie::{trie_types::Layout, TrieConfiguration};
+
+pub(crate) fn root(input: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> [u8; 32] {
+    Layout::<Blake2Hasher>::trie_root(input).into()
+}
+
+fn ordered_root(input: Vec<Vec<u8>>) -> [u8; 32] {
+    Layout::<Blake2Hasher>::ordered_trie_root(input).into()
+}
+
+host_fn! {
+    fn ext_trie_blake2_256_root_version_1(ctx, input: u64) -> u32 {
+        let input: Vec<(Vec<u8>, Vec<u8>)> = ctx.read_decoded(input)?;
+        ctx.allocate_bytes(&root(input))
+    }
+}
+
+host_fn! {
+    fn ext_trie_blake2_256_ordered_root_version_1(ctx, input: u64) -> u32 {
+        ctx.allocate_bytes(&ordered_root(ctx.read_decoded(input)?))
+    }
+}
+
+pub(crate) fn register(registry: &mut HostRegistry) {
+    registry.register_fn(
+        "ext_trie_blake2_256_root_version_1",
+        ext_trie_blake2_256_root_version_1,
+    );
+    registry.register_fn(
+        "ext_trie_blake2_256_ordered_root_version_1",
+        ext_trie_blake2_256_ordered_root_version_1,
+    );
+}
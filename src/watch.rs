@@ -0,0 +1,86 @@
+use crate::input::parse_num;
+use repro::{ExecutorBuilder, HostContext};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// A memory range given as `start..end`, decimal or `0x` prefixed hex.
+#[derive(Clone, Copy, Debug)]
+pub struct WatchRange {
+    start: u32,
+    end: u32,
+}
+
+fn parse_addr(s: &str) -> Result<u32, String> {
+    parse_num(s).map_err(|err| format!("invalid address `{}`: {}", s, err))
+}
+
+impl FromStr for WatchRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bounds = s.splitn(2, "..");
+        let (start, end) = match (bounds.next(), bounds.next()) {
+            (Some(start), Some(end)) => (parse_addr(start)?, parse_addr(end)?),
+            _ => return Err(format!("expected `start..end`, got `{}`", s)),
+        };
+        if start >= end {
+            return Err(format!("`{}` is empty", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// What a watched range looked like when last checked.
+struct Watched {
+    range: WatchRange,
+    bytes: Option<Vec<u8>>,
+}
+
+impl Watched {
+    fn read(&self, ctx: &HostContext) -> Option<Vec<u8>> {
+        ctx.read_memory(self.range.start, self.range.end - self.range.start)
+            .ok()
+    }
+}
+
+/// Checks the watched ranges on every host call and logs the window they changed in: after
+/// the previous host call returned, up to the end of the current one.
+pub fn watch(builder: ExecutorBuilder, ranges: &[WatchRange]) -> ExecutorBuilder {
+    let watched = Rc::new(RefCell::new(
+        ranges
+            .iter()
+            .map(|&range| Watched { range, bytes: None })
+            .collect::<Vec<_>>(),
+    ));
+    let previous_call = Rc::new(RefCell::new(String::new()));
+
+    let (start_watched, start_previous) = (watched.clone(), previous_call.clone());
+    builder
+        .on_before_call(move |method, _, ctx| {
+            for watched in start_watched.borrow_mut().iter_mut() {
+                watched.bytes = watched.read(ctx);
+            }
+            *start_previous.borrow_mut() = format!("the start of `{}`", method);
+        })
+        .on_host_call(move |ctx, _, _| {
+            let mut previous_call = previous_call.borrow_mut();
+            for watched in watched.borrow_mut().iter_mut() {
+                let bytes = watched.read(ctx);
+                if bytes != watched.bytes {
+                    log::warn!(
+                        target: "watch",
+                        "{:#x}..{:#x} changed between {} and the end of `{}`: {} -> {}",
+                        watched.range.start,
+                        watched.range.end,
+                        previous_call,
+                        ctx.name(),
+                        watched.bytes.as_deref().map_or("?".to_string(), hex::encode),
+                        bytes.as_deref().map_or("?".to_string(), hex::encode),
+                    );
+                    watched.bytes = bytes;
+                }
+            }
+            *previous_call = format!("the end of `{}`", ctx.name());
+        })
+}
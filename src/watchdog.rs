@@ -0,0 +1,135 @@
+//! Gives up on calls that outlive their timeout.
+//!
+//! The pinned wasmtime can't interrupt wasm code: the executor checks the deadline on every
+//! host call, but a loop that makes none runs on. So the work is performed on a thread of its
+//! own, and the calling thread reports the timeout once a call is overdue, abandoning the
+//! thread, which keeps spinning until the process exits.
+
+use crate::cli::OutputFormat;
+use crate::crash_report;
+use crate::report::CallRecord;
+use repro::{ExecError, ExecutorBuilder};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use wasmtime::Val;
+
+/// How long past its timeout a call gets before it is given up on, for the executor to report
+/// the timeout itself if the call makes a host call meanwhile.
+const GRACE: Duration = Duration::from_secs(1);
+/// How often the calling thread checks on the call.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The call in progress.
+struct Running {
+    method: String,
+    input: Vec<u8>,
+    started: Instant,
+}
+
+#[derive(Clone)]
+pub struct Watchdog {
+    timeout: Option<Duration>,
+    output: OutputFormat,
+    running: Arc<Mutex<Option<Running>>>,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Option<Duration>, output: OutputFormat) -> Self {
+        Self {
+            timeout,
+            output,
+            running: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Times every call the executor performs, from the moment it starts, so that compiling
+    /// and instantiating don't count against the timeout.
+    pub fn watch(&self, builder: ExecutorBuilder) -> ExecutorBuilder {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return builder,
+        };
+        let started = self.running.clone();
+        let finished = self.running.clone();
+        builder
+            .timeout(timeout)
+            .on_before_call(move |method, params, ctx| {
+                let input = match *params {
+                    [Val::I32(ptr), Val::I32(len)] => {
+                        ctx.read_memory(ptr as u32, len as u32).unwrap_or_default()
+                    }
+                    _ => Vec::new(),
+                };
+                *started.lock().unwrap() = Some(Running {
+                    method: method.to_string(),
+                    input,
+                    started: Instant::now(),
+                });
+            })
+            .on_after_call(move |_, _, _| *finished.lock().unwrap() = None)
+    }
+
+    /// Performs `f` on a thread with a native stack of `stack_size` bytes, or the default one,
+    /// and returns its outcome, or [`ExecError::Timeout`] if a call it makes is overdue.
+    ///
+    /// The timeout is reported the way the call would have reported it: as a JSON record with
+    /// `--output json`, and in the crash report if it is enabled.
+    pub fn run<F, R>(&self, stack_size: Option<usize>, f: F) -> anyhow::Result<R>
+    where
+        F: FnOnce() -> anyhow::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        if self.timeout.is_none() && stack_size.is_none() {
+            return f();
+        }
+        let on_thread = move || {
+            // The crash handler needs a stack of its own on every thread.
+            #[cfg(target_os = "linux")]
+            crate::crash::install_alt_stack()?;
+            f()
+        };
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return repro::on_stack(stack_size.unwrap(), on_thread)?,
+        };
+        let (sender, receiver) = mpsc::channel();
+        let mut thread = thread::Builder::new().name("wasm".to_string());
+        if let Some(stack_size) = stack_size {
+            thread = thread.stack_size(stack_size);
+        }
+        thread.spawn(move || {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(on_thread)));
+        })?;
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(outcome)) => return outcome,
+                Ok(Err(payload)) => panic::resume_unwind(payload),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => unreachable!("the thread always sends"),
+            }
+            let running = self.running.lock().unwrap();
+            if let Some(ref running) = *running {
+                if running.started.elapsed() > timeout + GRACE {
+                    let err = ExecError::Timeout { timeout };
+                    self.report(running, &err)?;
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+
+    fn report(&self, running: &Running, err: &ExecError) -> anyhow::Result<()> {
+        eprintln!(
+            "`{}` is still running {:?} past its timeout, abandoning it",
+            running.method, GRACE
+        );
+        if self.output == OutputFormat::Json {
+            CallRecord::abandoned(&running.method, &running.input, err).print_json()?;
+        }
+        crash_report::on_failure(err);
+        Ok(())
+    }
+}
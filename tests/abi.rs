@@ -0,0 +1,91 @@
+//! Round trips of the pointer and length packing, over the edge values and random ones.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use repro::abi::{
+    checked_pack, pack_i64, pack_ptr_and_len, unpack_i64, unpack_ptr_and_len, unpack_range,
+};
+
+const EDGES: &[u32] = &[0, 1, 2, 0x7fff_ffff, 0x8000_0000, u32::MAX - 1, u32::MAX];
+
+const RANDOM_CASES: usize = 10_000;
+
+/// Every pair of edge values, then random pairs from a fixed seed.
+fn pairs() -> Vec<(u32, u32)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let edges = EDGES
+        .iter()
+        .flat_map(|&ptr| EDGES.iter().map(move |&len| (ptr, len)));
+    let random = (0..RANDOM_CASES).map(|_| (rng.gen(), rng.gen()));
+    edges.chain(random).collect()
+}
+
+#[test]
+fn pack_then_unpack_round_trips() {
+    for (ptr, len) in pairs() {
+        assert_eq!(unpack_ptr_and_len(pack_ptr_and_len(ptr, len)), (ptr, len));
+        assert_eq!(unpack_i64(pack_i64(ptr, len)), (ptr, len));
+    }
+}
+
+#[test]
+fn unpack_then_pack_round_trips() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let edges = [
+        0,
+        1,
+        u32::MAX as u64,
+        1 << 32,
+        i64::MAX as u64,
+        1 << 63,
+        u64::MAX,
+    ];
+    let random = (0..RANDOM_CASES).map(|_| rng.gen::<u64>());
+    for val in edges.iter().copied().chain(random) {
+        let (ptr, len) = unpack_ptr_and_len(val);
+        assert_eq!(pack_ptr_and_len(ptr, len), val);
+        assert_eq!(pack_i64(ptr, len), val as i64);
+    }
+}
+
+#[test]
+fn ptr_is_the_low_half() {
+    assert_eq!(pack_ptr_and_len(0x1234, 0), 0x1234);
+    assert_eq!(pack_ptr_and_len(0, 0x1234), 0x1234 << 32);
+    assert_eq!(unpack_i64(-1), (u32::MAX, u32::MAX));
+}
+
+#[test]
+fn checked_pack_agrees_with_pack_when_it_fits() {
+    for (ptr, len) in pairs() {
+        assert_eq!(
+            checked_pack(ptr as usize, len as usize),
+            Some(pack_ptr_and_len(ptr, len))
+        );
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn checked_pack_rejects_what_doesnt_fit() {
+    let too_large = u32::MAX as usize + 1;
+    assert_eq!(checked_pack(too_large, 0), None);
+    assert_eq!(checked_pack(0, too_large), None);
+    assert_eq!(checked_pack(usize::MAX, usize::MAX), None);
+}
+
+#[test]
+fn unpack_range_covers_ptr_to_ptr_plus_len() {
+    for (ptr, len) in pairs() {
+        let range = unpack_range(pack_ptr_and_len(ptr, len));
+        match ptr.checked_add(len) {
+            Some(end) => assert_eq!(range, Some(ptr..end)),
+            None => assert_eq!(range, None),
+        }
+    }
+    assert_eq!(
+        unpack_range(pack_ptr_and_len(u32::MAX, 0)),
+        Some(u32::MAX..u32::MAX)
+    );
+    assert_eq!(unpack_range(pack_ptr_and_len(u32::MAX, 1)), None);
+}
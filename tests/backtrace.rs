@@ -0,0 +1,66 @@
+//! Compares the trap diagnostics of the panicking exports of `sc_runtime_test.wasm` against
+//! the files in `tests/golden`. Run with `REPRO_BLESS=1` to rewrite them.
+
+use repro::{ExecError, Executor};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+fn render(err: &ExecError) -> String {
+    let mut rendered = String::new();
+    let kind = err.trap_kind().map(|kind| kind.to_string());
+    writeln!(rendered, "kind: {}", kind.as_deref().unwrap_or("none")).unwrap();
+    writeln!(rendered, "panic: {}", err.panic_message().unwrap_or("none")).unwrap();
+    writeln!(rendered, "backtrace:").unwrap();
+    for (idx, frame) in err.backtrace().iter().enumerate() {
+        let name = frame.name.as_deref().unwrap_or("<unknown>");
+        writeln!(rendered, "  {}: {} (func {})", idx, name, frame.func_index).unwrap();
+    }
+    rendered
+}
+
+fn check(golden: &str, method: &str, input: &[u8]) {
+    let err = Executor::from_file("sc_runtime_test.wasm")
+        .expect("the test runtime loads")
+        .call(method, input)
+        .expect_err("the call traps");
+    let actual = render(&err);
+    let path = Path::new("tests/golden").join(golden);
+    if env::var_os("REPRO_BLESS").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("{}: {}, run with REPRO_BLESS=1", path.display(), err));
+    assert_eq!(
+        expected,
+        actual,
+        "{} differs, run with REPRO_BLESS=1",
+        path.display()
+    );
+}
+
+#[test]
+fn test_panic() {
+    check("test_panic.txt", "test_panic", &[]);
+}
+
+#[test]
+fn test_conditional_panic() {
+    // `vec![1]`, SCALE encoded.
+    check(
+        "test_conditional_panic.txt",
+        "test_conditional_panic",
+        &[4, 1],
+    );
+}
+
+#[test]
+fn test_conditional_panic_undecodable_input() {
+    check(
+        "test_conditional_panic_undecodable_input.txt",
+        "test_conditional_panic",
+        &[],
+    );
+}
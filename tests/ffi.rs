@@ -0,0 +1,28 @@
+//! The C API, called the way a C caller would.
+
+use repro::ffi::{repro_call, repro_executor_free, repro_executor_new, repro_last_error};
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+#[test]
+fn null_output_fails_the_call() {
+    let wasm_path = CString::new("sc_runtime_test.wasm").unwrap();
+    let method = CString::new("test_empty_return").unwrap();
+    unsafe {
+        let executor = repro_executor_new(wasm_path.as_ptr(), repro::DEFAULT_HEAP_BASE);
+        assert!(!executor.is_null());
+        let mut output_len = 0;
+        let exit_code = repro_call(
+            executor,
+            method.as_ptr(),
+            ptr::null(),
+            0,
+            ptr::null_mut(),
+            &mut output_len,
+        );
+        assert_eq!(exit_code, 1);
+        let message = CStr::from_ptr(repro_last_error());
+        assert_eq!(message.to_str().unwrap(), "output is null");
+        repro_executor_free(executor);
+    }
+}
@@ -0,0 +1,63 @@
+//! Fuel metering of hand-written modules.
+
+use repro::{ExecError, ExecutorBuilder};
+
+/// A runtime with a start function, whose `call` export runs `body` and returns nothing.
+fn runtime(body: &str) -> Vec<u8> {
+    wat::parse_str(format!(
+        r#"(module
+            (memory (export "memory") 2)
+            (global (export "__heap_base") i32 (i32.const 1024))
+            (global $started (mut i32) (i32.const 0))
+            (func $start
+                i32.const 1
+                global.set $started)
+            (start $start)
+            (func (export "call") (param i32 i32) (result i64)
+                {}
+                i64.const 0))"#,
+        body
+    ))
+    .expect("the module assembles")
+}
+
+#[test]
+fn start_function_is_metered_from_the_limit() {
+    let output = ExecutorBuilder::new()
+        .fuel(1_000)
+        .build(&runtime("nop"))
+        .expect("the module loads")
+        .call("call", &[])
+        .expect("the start function has fuel");
+    assert!(output.is_empty());
+}
+
+#[test]
+fn every_block_is_charged() {
+    // 21 instructions, of which the run at the function entry is only the `block`.
+    let body = "(block nop) nop nop nop nop nop nop nop nop nop nop nop nop nop nop nop nop";
+    let err = ExecutorBuilder::new()
+        .fuel(10)
+        .build(&runtime(body))
+        .expect("the module loads")
+        .call("call", &[])
+        .expect_err("the instructions after the block are charged too");
+    assert!(
+        matches!(err, ExecError::OutOfFuel { limit: 10, .. }),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn fuel_beyond_i64_is_clamped() {
+    let runtime = ExecutorBuilder::new()
+        .fuel(u64::MAX)
+        .build(&runtime("nop"))
+        .expect("the module loads")
+        .instantiate()
+        .expect("the start function has fuel");
+    runtime.call("call", &[]).expect("the call has fuel");
+    let consumed = runtime.fuel_consumed().expect("the call is metered");
+    assert!(consumed < 100, "{}", consumed);
+}
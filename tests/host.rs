@@ -0,0 +1,93 @@
+//! Host functions called from hand-written modules.
+
+use repro::{ExecutorBuilder, HostCallTrace, OffchainConfig, Runtime, Storage};
+use wasmtime::Val;
+
+/// Instantiates a runtime whose `call` export returns what the import `name`, taking no
+/// params and returning a `result`, returns.
+fn forwarding_runtime(builder: ExecutorBuilder, name: &str, result: &str) -> Runtime {
+    let code = wat::parse_str(format!(
+        r#"(module
+            (import "env" "{name}" (func $host (result {result})))
+            (memory (export "memory") 2)
+            (global (export "__heap_base") i32 (i32.const 1024))
+            (func (export "call") (param i32 i32) (result {result})
+                call $host))"#,
+        name = name,
+        result = result
+    ))
+    .expect("the module assembles");
+    builder
+        .build(&code)
+        .expect("the module loads")
+        .instantiate()
+        .expect("the module instantiates")
+}
+
+#[test]
+fn host_function_without_params() {
+    let runtime = forwarding_runtime(ExecutorBuilder::new(), "ext_storage_root_version_1", "i64");
+    let output = runtime
+        .call("call", &[])
+        .expect("the call returns the root");
+    assert_eq!(output, Storage::new().root());
+}
+
+#[test]
+fn offchain_answers_from_the_config() {
+    let offchain = OffchainConfig {
+        is_validator: true,
+        timestamp: 1_588_000_000_000,
+        random_seed: [7; 32],
+        ..OffchainConfig::default()
+    };
+    let builder = ExecutorBuilder::new().offchain(offchain);
+    let forward = |name, result| {
+        let runtime = forwarding_runtime(builder.clone(), name, result);
+        let results = runtime
+            .invoke("call", &[Val::I32(0), Val::I32(0)])
+            .expect("the call returns");
+        (runtime, results[0].clone())
+    };
+
+    let (_, is_validator) = forward("ext_offchain_is_validator_version_1", "i32");
+    assert_eq!(is_validator.unwrap_i32(), 1);
+    let (_, timestamp) = forward("ext_offchain_timestamp_version_1", "i64");
+    assert_eq!(timestamp.unwrap_i64(), 1_588_000_000_000);
+    let (runtime, seed) = forward("ext_offchain_random_seed_version_1", "i32");
+    let seed = runtime
+        .read_memory(seed.unwrap_i32() as u32, 32)
+        .expect("the seed is in bounds");
+    assert_eq!(seed, [7; 32]);
+}
+
+#[test]
+fn failed_host_call_is_traced() {
+    // Prints 16 bytes from 0xffff0000, past the end of the memory.
+    let code = wat::parse_str(
+        r#"(module
+            (import "env" "ext_misc_print_utf8_version_1" (func $print (param i64)))
+            (memory (export "memory") 2)
+            (global (export "__heap_base") i32 (i32.const 1024))
+            (func (export "call") (param i32 i32) (result i64)
+                (call $print (i64.const 0x10ffff0000))
+                i64.const 0))"#,
+    )
+    .expect("the module assembles");
+    let trace = HostCallTrace::new();
+    ExecutorBuilder::new()
+        .trace_host_calls(trace.clone())
+        .build(&code)
+        .expect("the module loads")
+        .call("call", &[])
+        .expect_err("the print is out of bounds");
+    let records = trace.records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].function, "ext_misc_print_utf8_version_1");
+    assert!(records[0].results.is_empty());
+    let error = records[0]
+        .error
+        .as_deref()
+        .expect("the record holds the error");
+    assert!(error.contains("out of bounds"), "{}", error);
+}